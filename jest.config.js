@@ -21,6 +21,27 @@ module.exports = {
     '^@env/(.*)$': '<rootDir>/src/env/$1',
     '^@orchestrator/(.*)$': '<rootDir>/src/orchestrator/$1',
     '^@agents/(.*)$': '<rootDir>/src/agents/$1',
-    '^@onboard/(.*)$': '<rootDir>/src/onboard/$1'
+    '^@onboard/(.*)$': '<rootDir>/src/onboard/$1',
+    '^@blobgrid/(.*)$': '<rootDir>/src/blobgrid/$1',
+    '^@edge/(.*)$': '<rootDir>/src/edge/$1',
+    '^@irx/(.*)$': '<rootDir>/src/irx/$1',
+    '^@grid/(.*)$': '<rootDir>/src/grid/$1',
+    '^@playground/(.*)$': '<rootDir>/src/playground/$1',
+    '^@capsules/(.*)$': '<rootDir>/src/capsules/$1',
+    '^@genesis/(.*)$': '<rootDir>/src/genesis/$1',
+    '^@mesh/(.*)$': '<rootDir>/src/mesh/$1',
+    '^@ops/(.*)$': '<rootDir>/src/ops/$1',
+    '^@vvm/(.*)$': '<rootDir>/src/vvm/$1',
+    '^@apix/(.*)$': '<rootDir>/src/apix/$1',
+    '^@infinity/(.*)$': '<rootDir>/src/infinity/$1',
+    '^@federation/(.*)$': '<rootDir>/src/federation/$1',
+    '^@ai/(.*)$': '<rootDir>/src/ai/$1',
+    '^@chat/(.*)$': '<rootDir>/src/chat/$1',
+    '^@snrl/(.*)$': '<rootDir>/src/snrl/$1',
+    '^@hypermesh/(.*)$': '<rootDir>/src/hypermesh/$1',
+    '^@trust/(.*)$': '<rootDir>/src/trust/$1',
+    '^@ingestion/(.*)$': '<rootDir>/src/ingestion/$1',
+    '^@hybrid/(.*)$': '<rootDir>/src/hybrid/$1',
+    '^@streams/(.*)$': '<rootDir>/src/streams/$1'
   }
 };
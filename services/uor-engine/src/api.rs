@@ -0,0 +1,605 @@
+use futures_util::{SinkExt, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+use warp::{
+    http::{HeaderValue, Response, StatusCode},
+    hyper::body::{Body, Buf, Bytes},
+    reply::{Json, WithStatus},
+    sse,
+    ws::{Message, WebSocket, Ws},
+    Reply,
+};
+
+use crate::{
+    apps::{self, AppMount, HttpRequest},
+    auth::{AuthRejection, Caller},
+    events::{Event, StreamQuery},
+    health::{self, HealthReport},
+    history::HistoryQuery,
+    invocations::{self, Invocation, InvocationQuery},
+    jobs::{Job, JobRequest},
+    kv::{self, KvUsage},
+    limits::LimitOverrides,
+    metrics,
+    schedules::{Schedule, ScheduleRequest},
+    status::SharedState,
+    vasm::{self, VasmRequest},
+    wasi::WasiConfig,
+    wasm::{InvokeError, WasmRuntime},
+};
+
+#[derive(Deserialize, Default)]
+struct InvokeRequest {
+    #[serde(default)]
+    args: Vec<Value>,
+    #[serde(default)]
+    limits: LimitOverrides,
+}
+
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    name: String,
+    /// Owner for a new module; only an admin may name a project other than its own.
+    project: Option<String>,
+}
+
+pub async fn invoke(module: String, export: String, caller: Caller, body: Bytes, runtime: Arc<WasmRuntime>) -> Result<WithStatus<Json>, warp::Rejection> {
+    if runtime.is_draining() {
+        return Ok(draining_reply());
+    }
+    let request: InvokeRequest = if body.is_empty() {
+        InvokeRequest::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(request) => request,
+            Err(err) => return Ok(error_reply(StatusCode::BAD_REQUEST, InvokeError::new("bad_request", err.to_string()))),
+        }
+    };
+    let loaded = runtime.registry.lock().ok().and_then(|mut registry| {
+        let visible = registry.get(&module).is_some_and(|info| caller.can_use(info.project.as_deref()));
+        visible.then(|| registry.checkout(&module)).flatten()
+    });
+    let loaded = match loaded {
+        Some(loaded) => loaded,
+        None => return Ok(error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_module", format!("module `{}` is not loaded", module)))),
+    };
+    let limits = runtime.limits().with_overrides(&request.limits);
+    let invocation = Invocation {
+        id: runtime.invocation_id(),
+        loaded,
+        export,
+        args: request.args,
+        limits,
+        caller: caller.label(),
+        project: caller.project().map(str::to_string),
+        cancel: None,
+        http: None,
+        grid: None,
+    };
+    let outcome = invocations::run(runtime, invocation).await;
+    Ok(match outcome {
+        Ok(result) => warp::reply::with_status(warp::reply::json(&result), StatusCode::OK),
+        Err(err) => {
+            let status = match err.error {
+                "unknown_export" => StatusCode::NOT_FOUND,
+                "bad_arguments" => StatusCode::BAD_REQUEST,
+                _ => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            error_reply(status, err)
+        }
+    })
+}
+
+/// `POST /jobs`: queues an export call and answers `202` with the job to poll.
+pub fn submit_job(caller: Caller, body: Bytes, runtime: Arc<WasmRuntime>) -> WithStatus<Json> {
+    if runtime.is_draining() {
+        return draining_reply();
+    }
+    let request: JobRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(err) => return error_reply(StatusCode::BAD_REQUEST, InvokeError::new("bad_request", err.to_string())),
+    };
+    let module = request.module.clone();
+    let Some(new) = request.into_job(&runtime, &caller) else {
+        return error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_module", format!("module `{}` is not loaded", module)));
+    };
+    match runtime.jobs.submit(new, &runtime.config.get().jobs) {
+        Ok(job) => warp::reply::with_status(warp::reply::json(&job), StatusCode::ACCEPTED),
+        Err(message) => error_reply(StatusCode::TOO_MANY_REQUESTS, InvokeError::new("queue_full", message)),
+    }
+}
+
+/// A job of a module the caller can use; others are reported as missing.
+fn visible_job(runtime: &WasmRuntime, id: &str, caller: &Caller) -> Result<Job, WithStatus<Json>> {
+    runtime
+        .jobs
+        .get(id)
+        .filter(|job| caller.can_use(job.project.as_deref()))
+        .ok_or_else(|| error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_job", format!("job `{}` is not known", id))))
+}
+
+pub fn job(id: String, caller: Caller, runtime: Arc<WasmRuntime>) -> WithStatus<Json> {
+    match visible_job(&runtime, &id, &caller) {
+        Ok(job) => warp::reply::with_status(warp::reply::json(&job), StatusCode::OK),
+        Err(reply) => reply,
+    }
+}
+
+/// `DELETE /jobs/{id}`: a queued job is cancelled at once (`200`); a running one is stopped at its
+/// next epoch tick (`202`). Only an admin or the submitter may cancel.
+pub fn cancel_job(id: String, caller: Caller, runtime: Arc<WasmRuntime>) -> WithStatus<Json> {
+    let job = match visible_job(&runtime, &id, &caller) {
+        Ok(job) => job,
+        Err(reply) => return reply,
+    };
+    if !matches!(caller, Caller::Admin) && caller.label() != job.caller {
+        return error_reply(StatusCode::FORBIDDEN, InvokeError::new("forbidden", "only the submitter or an admin may cancel a job"));
+    }
+    match runtime.jobs.cancel(&id, runtime.config.get().jobs.retained) {
+        Some(Ok(job)) if job.cancel_requested => warp::reply::with_status(warp::reply::json(&job), StatusCode::ACCEPTED),
+        Some(Ok(job)) => warp::reply::with_status(warp::reply::json(&job), StatusCode::OK),
+        Some(Err(job)) => error_reply(StatusCode::CONFLICT, InvokeError::new("job_finished", format!("job `{}` already {}", id, job.state.as_str()))),
+        None => error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_job", format!("job `{}` is not known", id))),
+    }
+}
+
+/// Schedules for modules the caller can use.
+pub fn list_schedules(caller: Caller, runtime: Arc<WasmRuntime>) -> Json {
+    let mut schedules = runtime.schedules.list();
+    schedules.retain(|schedule| caller.can_use(schedule.project.as_deref()));
+    warp::reply::json(&schedules)
+}
+
+/// `POST /schedules`: creates a schedule, or replaces one of the same name the caller manages.
+pub fn create_schedule(caller: Caller, body: Bytes, runtime: Arc<WasmRuntime>) -> WithStatus<Json> {
+    let request: ScheduleRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(err) => return error_reply(StatusCode::BAD_REQUEST, InvokeError::new("bad_request", err.to_string())),
+    };
+    let owner = runtime.registry.lock().ok().and_then(|registry| registry.get(&request.module).map(|info| info.project.clone()));
+    let Some(owner) = owner.filter(|owner| caller.can_use(owner.as_deref())) else {
+        return error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_module", format!("module `{}` is not loaded", request.module)));
+    };
+    // A project owns the schedules it creates, shared modules included; an admin's follow the module.
+    let project = match &caller {
+        Caller::Project(project) => Some(project.clone()),
+        _ => owner,
+    };
+    let existing = runtime.schedules.get(&request.name);
+    if existing.as_ref().is_some_and(|existing| !caller.can_manage(existing.project.as_deref())) {
+        return error_reply(StatusCode::CONFLICT, InvokeError::new("name_conflict", format!("schedule name `{}` is already in use", request.name)));
+    }
+    let schedule = match Schedule::new(request, project, caller.label()) {
+        Ok(schedule) => schedule,
+        Err(message) => return error_reply(StatusCode::BAD_REQUEST, InvokeError::new("bad_request", message)),
+    };
+    match runtime.schedules.put(schedule) {
+        Ok(schedule) => {
+            println!("[uor-engine] schedule {} next runs at {}", schedule.name, schedule.next_run_at.map(|at| at.to_rfc3339()).unwrap_or_else(|| "never".to_string()));
+            let status = if existing.is_some() { StatusCode::OK } else { StatusCode::CREATED };
+            warp::reply::with_status(warp::reply::json(&schedule), status)
+        }
+        Err(message) => error_reply(StatusCode::SERVICE_UNAVAILABLE, InvokeError::new("schedules_unavailable", message)),
+    }
+}
+
+pub fn delete_schedule(name: String, caller: Caller, runtime: Arc<WasmRuntime>) -> WithStatus<Json> {
+    match runtime.schedules.get(&name) {
+        Some(schedule) if caller.can_manage(schedule.project.as_deref()) => {}
+        Some(schedule) if caller.can_use(schedule.project.as_deref()) => {
+            return error_reply(StatusCode::FORBIDDEN, InvokeError::new("forbidden", format!("schedule `{}` can only be removed by an admin", name)))
+        }
+        _ => return error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_schedule", format!("schedule `{}` does not exist", name))),
+    }
+    match runtime.schedules.remove(&name) {
+        Ok(Some(schedule)) => warp::reply::with_status(warp::reply::json(&schedule), StatusCode::OK),
+        Ok(None) => error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_schedule", format!("schedule `{}` does not exist", name))),
+        Err(message) => error_reply(StatusCode::SERVICE_UNAVAILABLE, InvokeError::new("schedules_unavailable", message)),
+    }
+}
+
+pub async fn upload_module(query: UploadQuery, caller: Caller, body: Bytes, runtime: Arc<WasmRuntime>) -> Result<WithStatus<Json>, warp::Rejection> {
+    if runtime.is_draining() {
+        return Ok(draining_reply());
+    }
+    if query.name.is_empty() || query.name.contains('/') {
+        return Ok(error_reply(StatusCode::BAD_REQUEST, InvokeError::new("bad_request", "module name must be non-empty and contain no `/`")));
+    }
+    let existing = runtime.registry.lock().ok().and_then(|registry| registry.get(&query.name).map(|info| info.project.clone()));
+    let project = match (existing, &caller) {
+        (Some(owner), _) if caller.can_manage(owner.as_deref()) => owner,
+        (Some(_), _) => return Ok(error_reply(StatusCode::CONFLICT, InvokeError::new("name_conflict", format!("module name `{}` is already in use", query.name)))),
+        (None, Caller::Admin) => query.project,
+        (None, Caller::Project(project)) if query.project.as_ref().is_none_or(|requested| requested == project) => Some(project.clone()),
+        (None, _) => return Ok(error_reply(StatusCode::FORBIDDEN, InvokeError::new("forbidden", "cannot upload modules for another project"))),
+    };
+    if let Some(info) = runtime.registry.lock().ok().and_then(|registry| registry.lookup_unchanged(&query.name, &body)) {
+        return Ok(warp::reply::with_status(warp::reply::json(&info), StatusCode::OK));
+    }
+    let worker = runtime.clone();
+    let bytes = body.clone();
+    let compiled = tokio::task::spawn_blocking(move || worker.cache.compile(&worker.engine, &bytes))
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|result| result.map_err(|err| format!("{:#}", err)));
+    let compiled = match compiled {
+        Ok(compiled) => compiled,
+        Err(message) => return Ok(error_reply(StatusCode::UNPROCESSABLE_ENTITY, InvokeError::new("compile_failed", message))),
+    };
+    let info = match runtime.registry.lock() {
+        Ok(mut registry) => registry.insert(&query.name, &body, compiled, project),
+        Err(_) => return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, InvokeError::new("internal", "module registry unavailable"))),
+    };
+    runtime.metrics.record_compile(&info);
+    runtime.events.publish(Event::ModuleLoaded {
+        name: info.name.clone(),
+        hash: info.hash.clone(),
+        source: info.source,
+    });
+    println!("[uor-engine] loaded module {} ({}) from {} in {:.1} ms", info.name, info.hash, info.source, info.load_ms);
+    Ok(warp::reply::with_status(warp::reply::json(&info), StatusCode::CREATED))
+}
+
+pub async fn list_modules(caller: Caller, runtime: Arc<WasmRuntime>) -> Result<Json, warp::Rejection> {
+    let mut modules = runtime.registry.lock().map(|registry| registry.list()).unwrap_or_default();
+    modules.retain(|info| caller.can_use(info.project.as_deref()));
+    if let Some(store) = &runtime.kv {
+        let config = runtime.config.get();
+        for info in &mut modules {
+            let namespace = kv::namespace(&info.name, info.project.as_deref(), caller.project());
+            info.kv = store.usage(&namespace).ok().map(|used_bytes| KvUsage {
+                quota_bytes: config.kv.quota(&namespace),
+                namespace,
+                used_bytes,
+            });
+        }
+    }
+    Ok(warp::reply::json(&modules))
+}
+
+/// Recent invocations made for the caller's project, newest first.
+pub fn list_invocations(query: InvocationQuery, caller: Caller, runtime: Arc<WasmRuntime>) -> Json {
+    warp::reply::json(&runtime.invocations.query(&query, |record| caller.can_see(record.project.as_deref())))
+}
+
+pub fn invocation(id: String, caller: Caller, runtime: Arc<WasmRuntime>) -> WithStatus<Json> {
+    match runtime.invocations.get(&id).filter(|record| caller.can_see(record.project.as_deref())) {
+        Some(record) => warp::reply::with_status(warp::reply::json(&record), StatusCode::OK),
+        None => error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_invocation", format!("invocation `{}` is not in the history", id))),
+    }
+}
+
+/// Log tail of a recent invocation made for the caller's project.
+pub fn invocation_logs(id: String, caller: Caller, runtime: Arc<WasmRuntime>) -> WithStatus<Json> {
+    match runtime.logs.get(&id).filter(|logs| caller.can_see(logs.tags.project.as_deref())) {
+        Some(logs) => warp::reply::with_status(warp::reply::json(&logs), StatusCode::OK),
+        None => error_reply(
+            StatusCode::NOT_FOUND,
+            InvokeError::new("unknown_invocation", format!("no logs retained for invocation `{}`", id)),
+        ),
+    }
+}
+
+/// Checks that `caller` may reconfigure or unload `name`. Modules the caller cannot see are reported as missing.
+fn check_manage(runtime: &WasmRuntime, name: &str, caller: &Caller) -> Result<(), WithStatus<Json>> {
+    let owner = runtime.registry.lock().ok().and_then(|registry| registry.get(name).map(|info| info.project.clone()));
+    match owner {
+        Some(owner) if caller.can_manage(owner.as_deref()) => Ok(()),
+        Some(owner) if caller.can_use(owner.as_deref()) => Err(error_reply(
+            StatusCode::FORBIDDEN,
+            InvokeError::new("forbidden", format!("module `{}` is shared and can only be changed by an admin", name)),
+        )),
+        _ => Err(error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_module", format!("module `{}` is not loaded", name)))),
+    }
+}
+
+pub async fn delete_module(name: String, caller: Caller, runtime: Arc<WasmRuntime>) -> Result<WithStatus<Json>, warp::Rejection> {
+    if let Err(reply) = check_manage(&runtime, &name, &caller) {
+        return Ok(reply);
+    }
+    match runtime.registry.lock().ok().and_then(|mut registry| registry.remove(&name)) {
+        Some(info) => {
+            println!("[uor-engine] unloaded module {}", info.name);
+            runtime.events.publish(Event::ModuleUnloaded { name: info.name.clone() });
+            Ok(warp::reply::with_status(warp::reply::json(&info), StatusCode::OK))
+        }
+        None => Ok(error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_module", format!("module `{}` is not loaded", name)))),
+    }
+}
+
+pub async fn configure_wasi(name: String, caller: Caller, config: WasiConfig, runtime: Arc<WasmRuntime>) -> Result<WithStatus<Json>, warp::Rejection> {
+    if let Err(message) = config.validate() {
+        return Ok(error_reply(StatusCode::BAD_REQUEST, InvokeError::new("bad_request", message)));
+    }
+    if let Err(reply) = check_manage(&runtime, &name, &caller) {
+        return Ok(reply);
+    }
+    if !config.inherit_env.is_empty() && !matches!(caller, Caller::Admin) {
+        return Ok(error_reply(StatusCode::FORBIDDEN, InvokeError::new("forbidden", "only an admin may pass host environment variables to a module")));
+    }
+    match runtime.registry.lock().ok().and_then(|mut registry| registry.set_wasi(&name, config)) {
+        Some(info) => Ok(warp::reply::with_status(warp::reply::json(&info), StatusCode::OK)),
+        None => Ok(error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_module", format!("module `{}` is not loaded", name)))),
+    }
+}
+
+/// `PUT /modules/{name}/app`: serves the module under `/apps/{name}/`.
+pub fn mount_app(name: String, caller: Caller, mount: AppMount, runtime: Arc<WasmRuntime>) -> WithStatus<Json> {
+    set_app(&name, &caller, Some(mount), &runtime)
+}
+
+/// `DELETE /modules/{name}/app`. Mounts from `apps.mounts` in the config stay in effect.
+pub fn unmount_app(name: String, caller: Caller, runtime: Arc<WasmRuntime>) -> WithStatus<Json> {
+    set_app(&name, &caller, None, &runtime)
+}
+
+fn set_app(name: &str, caller: &Caller, mount: Option<AppMount>, runtime: &WasmRuntime) -> WithStatus<Json> {
+    if let Err(reply) = check_manage(runtime, name, caller) {
+        return reply;
+    }
+    match runtime.registry.lock().ok().and_then(|mut registry| registry.set_app(name, mount)) {
+        Some(info) => warp::reply::with_status(warp::reply::json(&info), StatusCode::OK),
+        None => error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_module", format!("module `{}` is not loaded", name))),
+    }
+}
+
+/// `/apps/{name}/...`: hands the request to the module's `handle_request` export and answers
+/// with the status it returns and the headers and body it wrote.
+pub async fn serve_app<S, B>(name: String, mut request: HttpRequest, caller: Caller, body: S, runtime: Arc<WasmRuntime>) -> Result<Response<Body>, warp::Rejection>
+where
+    S: Stream<Item = Result<B, warp::Error>> + Unpin,
+    B: Buf,
+{
+    if runtime.is_draining() {
+        return Ok(draining_reply().into_response());
+    }
+    let config = runtime.config.get();
+    let loaded = runtime.registry.lock().ok().and_then(|mut registry| {
+        let info = registry.get(&name)?;
+        let mount = info.app.clone().or_else(|| config.apps.mounts.get(&name).cloned())?;
+        if !mount.public && !caller.can_use(info.project.as_deref()) {
+            return Some(Err(caller.clone()));
+        }
+        registry.checkout(&name).map(Ok)
+    });
+    let loaded = match loaded {
+        Some(Ok(loaded)) => loaded,
+        Some(Err(Caller::Anonymous)) => {
+            return Ok(error_reply(StatusCode::UNAUTHORIZED, InvokeError::new("unauthorized", "this app requires credentials")).into_response());
+        }
+        _ => return Ok(error_reply(StatusCode::NOT_FOUND, InvokeError::new("unknown_app", format!("no app is mounted at `/apps/{}`", name))).into_response()),
+    };
+    request.body = match read_capped(body, config.apps.max_request_bytes).await {
+        Ok(Some(body)) => body,
+        Ok(None) => {
+            let message = format!("request body exceeds {} bytes", config.apps.max_request_bytes);
+            return Ok(error_reply(StatusCode::PAYLOAD_TOO_LARGE, InvokeError::new("payload_too_large", message)).into_response());
+        }
+        Err(err) => return Ok(error_reply(StatusCode::BAD_REQUEST, InvokeError::new("bad_request", err.to_string())).into_response()),
+    };
+    let invocation = Invocation {
+        id: runtime.invocation_id(),
+        loaded,
+        export: apps::HANDLER_EXPORT.to_string(),
+        args: Vec::new(),
+        limits: runtime.limits(),
+        caller: caller.label(),
+        project: caller.project().map(str::to_string),
+        cancel: None,
+        http: Some(request),
+        grid: None,
+    };
+    let mut result = match invocations::run(runtime, invocation).await {
+        Ok(result) => result,
+        Err(err) => {
+            let status = if err.error == "timeout" { StatusCode::GATEWAY_TIMEOUT } else { StatusCode::BAD_GATEWAY };
+            return Ok(error_reply(status, err).into_response());
+        }
+    };
+    let invocation_id = HeaderValue::from_str(&result.invocation_id).ok();
+    let response = result.response.take().unwrap_or_default();
+    let status = match result.results.first() {
+        None => Some(StatusCode::OK),
+        Some(code) => code.as_u64().and_then(|code| u16::try_from(code).ok()).and_then(|code| StatusCode::from_u16(code).ok()),
+    };
+    let mut reply = match status {
+        _ if response.overflowed => {
+            let message = format!("response body exceeds {} bytes", config.apps.max_response_bytes);
+            error_reply(StatusCode::BAD_GATEWAY, InvokeError::new("response_too_large", message)).into_response()
+        }
+        Some(status) => {
+            let mut reply = Response::new(Body::from(response.body));
+            *reply.status_mut() = status;
+            reply.headers_mut().extend(response.headers);
+            reply
+        }
+        None => {
+            let message = format!("`{}` returned {}, not an HTTP status", apps::HANDLER_EXPORT, result.results[0]);
+            error_reply(StatusCode::BAD_GATEWAY, InvokeError::new("bad_status", message)).into_response()
+        }
+    };
+    if let Some(invocation_id) = invocation_id {
+        reply.headers_mut().insert("x-voike-invocation-id", invocation_id);
+    }
+    Ok(reply)
+}
+
+/// Collects a request body, or `None` once it passes `limit` bytes.
+async fn read_capped<S, B>(mut body: S, limit: usize) -> Result<Option<Vec<u8>>, warp::Error>
+where
+    S: Stream<Item = Result<B, warp::Error>> + Unpin,
+    B: Buf,
+{
+    let mut collected = Vec::new();
+    while let Some(chunk) = body.next().await {
+        let mut chunk = chunk?;
+        if collected.len() + chunk.remaining() > limit {
+            return Ok(None);
+        }
+        while chunk.has_remaining() {
+            let part = chunk.chunk();
+            collected.extend_from_slice(part);
+            let read = part.len();
+            chunk.advance(read);
+        }
+    }
+    Ok(Some(collected))
+}
+
+/// `POST /vasm/run`: runs a VASM program to completion and returns its registers, stack and output.
+pub async fn run_vasm(caller: Caller, body: Bytes, runtime: Arc<WasmRuntime>) -> Result<WithStatus<Json>, warp::Rejection> {
+    if runtime.is_draining() {
+        return Ok(draining_reply());
+    }
+    let request: VasmRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(err) => return Ok(error_reply(StatusCode::BAD_REQUEST, InvokeError::new("bad_request", err.to_string()))),
+    };
+    Ok(match vasm::run(runtime, caller, request).await {
+        Ok(run) => warp::reply::with_status(warp::reply::json(&run), StatusCode::OK),
+        Err(err) => error_reply(StatusCode::UNPROCESSABLE_ENTITY, InvokeError::new(err.kind, err.message)),
+    })
+}
+
+pub fn status(state: Arc<Mutex<SharedState>>) -> WithStatus<Json> {
+    match state.lock() {
+        Ok(guard) => warp::reply::with_status(warp::reply::json(&guard.status), StatusCode::OK),
+        Err(_) => error_reply(StatusCode::SERVICE_UNAVAILABLE, InvokeError::new("internal", "status state is poisoned")),
+    }
+}
+
+pub fn healthz(state: Arc<Mutex<SharedState>>, runtime: Arc<WasmRuntime>) -> WithStatus<Json> {
+    health_reply(health::liveness(&state, &runtime))
+}
+
+pub fn readyz(state: Arc<Mutex<SharedState>>, runtime: Arc<WasmRuntime>) -> WithStatus<Json> {
+    health_reply(health::readiness(&state, &runtime))
+}
+
+fn health_reply(report: HealthReport) -> WithStatus<Json> {
+    let status = if report.is_ok() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    warp::reply::with_status(warp::reply::json(&report), status)
+}
+
+pub fn config(runtime: Arc<WasmRuntime>) -> Json {
+    warp::reply::json(&runtime.config.snapshot())
+}
+
+pub async fn metrics(state: Arc<Mutex<SharedState>>, runtime: Arc<WasmRuntime>) -> Result<impl warp::Reply, warp::Rejection> {
+    let status = state.lock().map(|guard| guard.status.clone()).unwrap_or_default();
+    Ok(warp::reply::with_header(runtime.metrics.render(&status), "content-type", metrics::CONTENT_TYPE))
+}
+
+pub async fn status_history(query: HistoryQuery, state: Arc<Mutex<SharedState>>) -> Result<WithStatus<Json>, warp::Rejection> {
+    let result = match state.lock() {
+        Ok(guard) => guard.history.query(&query, chrono::Utc::now()),
+        Err(_) => Err("status history unavailable".to_string()),
+    };
+    Ok(match result {
+        Ok(response) => warp::reply::with_status(warp::reply::json(&response), StatusCode::OK),
+        Err(message) => error_reply(StatusCode::BAD_REQUEST, InvokeError::new("bad_request", message)),
+    })
+}
+
+/// `GET /status/stream`: each event as an SSE message named after the event type.
+pub fn status_stream(query: StreamQuery, runtime: Arc<WasmRuntime>) -> impl warp::Reply {
+    let stream = runtime.events.subscribe(&query).map(|event| {
+        let data = serde_json::to_value(&event).ok().and_then(|mut value| value.get_mut("data").map(Value::take));
+        let message = sse::Event::default().event(event.name());
+        Ok::<_, Infallible>(message.json_data(data.unwrap_or(Value::Null)).unwrap_or_default())
+    });
+    sse::reply(sse::keep_alive().stream(stream))
+}
+
+/// `GET /status/ws`: each event as a `{"event": ..., "data": ...}` text frame.
+pub fn status_ws(ws: Ws, query: StreamQuery, runtime: Arc<WasmRuntime>) -> impl warp::Reply {
+    ws.on_upgrade(move |socket| forward_events(socket, query, runtime))
+}
+
+async fn forward_events(socket: WebSocket, query: StreamQuery, runtime: Arc<WasmRuntime>) {
+    let (mut outgoing, mut incoming) = socket.split();
+    let mut events = Box::pin(runtime.events.subscribe(&query));
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else { break };
+                let Ok(text) = serde_json::to_string(&event) else { continue };
+                if outgoing.send(Message::text(text)).await.is_err() {
+                    break;
+                }
+            }
+            message = incoming.next() => match message {
+                Some(Ok(message)) if !message.is_close() => continue,
+                _ => break,
+            },
+        }
+    }
+    let _ = outgoing.close().await;
+}
+
+fn error_reply(status: StatusCode, err: InvokeError) -> WithStatus<Json> {
+    warp::reply::with_status(warp::reply::json(&err), status)
+}
+
+/// Turns auth rejections into the usual JSON error body; everything else keeps warp's handling.
+pub async fn recover(rejection: warp::Rejection) -> Result<WithStatus<Json>, warp::Rejection> {
+    match rejection.find::<AuthRejection>() {
+        Some(auth) => Ok(error_reply(auth.status, InvokeError::new(auth.error, auth.message))),
+        None => Err(rejection),
+    }
+}
+
+fn draining_reply() -> WithStatus<Json> {
+    error_reply(StatusCode::SERVICE_UNAVAILABLE, InvokeError::new("draining", "engine is shutting down and not accepting new work"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+
+    async fn body(reply: impl Reply) -> (StatusCode, Value) {
+        let response = reply.into_response();
+        let status = response.status();
+        let bytes = warp::hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn keeps_calls_to_shared_modules_within_the_calling_project() {
+        let runtime = WasmRuntime::for_tests(EngineConfig::default());
+        runtime.load_wat("add", r#"(module (func (export "add") (param i32 i32) (result i32) local.get 0 local.get 1 i32.add))"#, None);
+        let a = Caller::Project("a".to_string());
+        let b = Caller::Project("b".to_string());
+        let call = Invocation {
+            id: runtime.invocation_id(),
+            loaded: runtime.registry.lock().unwrap().checkout("add").unwrap(),
+            export: "add".to_string(),
+            args: vec![Value::from(2), Value::from(3)],
+            limits: runtime.limits(),
+            caller: a.label(),
+            project: a.project().map(str::to_string),
+            cancel: None,
+            http: None,
+            grid: None,
+        };
+        let id = call.id.clone();
+        assert!(invocations::run(runtime.clone(), call).await.is_ok());
+
+        let (_, listed) = body(list_invocations(InvocationQuery::default(), b.clone(), runtime.clone())).await;
+        assert_eq!(listed, serde_json::json!([]));
+        assert_eq!(body(invocation(id.clone(), b.clone(), runtime.clone())).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(body(invocation_logs(id.clone(), b, runtime.clone())).await.0, StatusCode::NOT_FOUND);
+
+        let (_, listed) = body(list_invocations(InvocationQuery::default(), a.clone(), runtime.clone())).await;
+        assert_eq!(listed.as_array().map(Vec::len), Some(1));
+        let (status, record) = body(invocation(id.clone(), a.clone(), runtime.clone())).await;
+        assert_eq!((status, &record["project"], &record["owner"]), (StatusCode::OK, &Value::from("a"), &Value::Null));
+        assert_eq!(body(invocation_logs(id.clone(), a, runtime.clone())).await.0, StatusCode::OK);
+        assert_eq!(body(invocation(id, Caller::Admin, runtime)).await.0, StatusCode::OK);
+    }
+}
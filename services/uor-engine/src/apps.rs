@@ -0,0 +1,308 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, convert::Infallible};
+use warp::{
+    http::{HeaderMap, HeaderName, HeaderValue, Method},
+    path::Tail,
+    Filter,
+};
+use wasmtime::{Caller, Linker};
+
+use crate::{
+    auth::{ADMIN_HEADER, API_KEY_HEADER},
+    limits::env_or,
+    wasm::{read_guest, write_guest, StoreState},
+};
+
+/// Export called for every request to a mounted module.
+pub const HANDLER_EXPORT: &str = "handle_request";
+
+/// Guest-visible status codes returned by the `voike.http_*` imports.
+pub const HTTP_NO_REQUEST: i32 = -1;
+pub const HTTP_TOO_LARGE: i32 = -2;
+pub const HTTP_BAD_HEADER: i32 = -3;
+
+const MAX_HEADER_NAME_BYTES: u32 = 256;
+const MAX_HEADER_VALUE_BYTES: u32 = 8 * 1024;
+/// Distinct response headers a guest may set.
+const MAX_RESPONSE_HEADERS: usize = 64;
+
+/// Request headers carrying engine credentials, which the guest never sees.
+const CREDENTIAL_HEADERS: [&str; 3] = [ADMIN_HEADER, API_KEY_HEADER, "authorization"];
+
+/// Response headers the engine manages itself.
+const RESERVED_HEADERS: [&str; 4] = ["connection", "content-length", "transfer-encoding", "upgrade"];
+
+/// `[apps]` config section: modules served under `/apps/{name}/` and their size limits.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppsConfig {
+    /// Larger request bodies are refused with `413`.
+    pub max_request_bytes: usize,
+    /// Guests that write more body than this get a `502`.
+    pub max_response_bytes: usize,
+    /// Modules mounted by name whenever they are loaded, in addition to `PUT /modules/{name}/app`.
+    pub mounts: BTreeMap<String, AppMount>,
+}
+
+impl Default for AppsConfig {
+    fn default() -> Self {
+        Self {
+            max_request_bytes: 1024 * 1024,
+            max_response_bytes: 1024 * 1024,
+            mounts: BTreeMap::new(),
+        }
+    }
+}
+
+impl AppsConfig {
+    /// Applies `UOR_APP_MAX_REQUEST_BYTES` and `UOR_APP_MAX_RESPONSE_BYTES`.
+    pub fn apply_env(&mut self) {
+        self.max_request_bytes = env_or("UOR_APP_MAX_REQUEST_BYTES", self.max_request_bytes);
+        self.max_response_bytes = env_or("UOR_APP_MAX_RESPONSE_BYTES", self.max_response_bytes);
+    }
+}
+
+/// How a module is served under `/apps/{name}/`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppMount {
+    /// Serve requests without credentials; otherwise callers need access to the module.
+    pub public: bool,
+}
+
+/// What `voike.http_request_head` hands the guest, as JSON; the body is read separately.
+#[derive(Clone, Debug, Serialize)]
+pub struct HttpRequest {
+    pub method: String,
+    /// Path below the mount, always starting with `/`.
+    pub path: String,
+    pub query: String,
+    /// Lower-case names; repeated headers are joined with `, `. Credential headers are left out.
+    pub headers: BTreeMap<String, String>,
+    #[serde(skip)]
+    pub body: Vec<u8>,
+}
+
+/// Extracts the method, path below the mount, query and headers of an `/apps/{name}/...` request.
+pub fn request_head() -> impl Filter<Extract = (HttpRequest,), Error = Infallible> + Clone {
+    let query = warp::query::raw().or(warp::any().map(String::new)).unify();
+    warp::method()
+        .and(warp::path::tail())
+        .and(query)
+        .and(warp::header::headers_cloned())
+        .map(|method: Method, tail: Tail, query: String, headers: HeaderMap| {
+            let mut joined: BTreeMap<String, String> = BTreeMap::new();
+            for (name, value) in headers.iter().filter(|(name, _)| !CREDENTIAL_HEADERS.contains(&name.as_str())) {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                joined
+                    .entry(name.as_str().to_string())
+                    .and_modify(|existing| {
+                        existing.push_str(", ");
+                        existing.push_str(&value);
+                    })
+                    .or_insert_with(|| value.into_owned());
+            }
+            HttpRequest {
+                method: method.to_string(),
+                path: format!("/{}", tail.as_str()),
+                query,
+                headers: joined,
+                body: Vec::new(),
+            }
+        })
+}
+
+/// Headers and body a guest wrote; the status is `handle_request`'s return value.
+#[derive(Debug, Default)]
+pub struct HttpResponse {
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub body: Vec<u8>,
+    /// The guest tried to write past `apps.max_response_bytes`.
+    pub overflowed: bool,
+}
+
+/// One invocation's side of an `/apps` request; empty for ordinary invocations.
+pub struct HttpExchange {
+    request: Option<HttpRequest>,
+    head: Vec<u8>,
+    response: HttpResponse,
+    max_response_bytes: usize,
+}
+
+impl HttpExchange {
+    pub fn new(request: Option<HttpRequest>, max_response_bytes: usize) -> Self {
+        let head = request.as_ref().and_then(|request| serde_json::to_vec(request).ok()).unwrap_or_default();
+        Self {
+            request,
+            head,
+            response: HttpResponse::default(),
+            max_response_bytes,
+        }
+    }
+
+    /// The response, if this invocation served a request.
+    pub fn finish(self) -> Option<HttpResponse> {
+        self.request.map(|_| self.response)
+    }
+
+    fn set_header(&mut self, name: &[u8], value: &[u8]) -> i32 {
+        let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name), HeaderValue::from_bytes(value)) else {
+            return HTTP_BAD_HEADER;
+        };
+        if RESERVED_HEADERS.contains(&name.as_str()) {
+            return HTTP_BAD_HEADER;
+        }
+        self.response.headers.retain(|(existing, _)| *existing != name);
+        if self.response.headers.len() >= MAX_RESPONSE_HEADERS {
+            return HTTP_TOO_LARGE;
+        }
+        self.response.headers.push((name, value));
+        0
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> i32 {
+        if self.response.body.len() + bytes.len() > self.max_response_bytes {
+            self.response.overflowed = true;
+            return HTTP_TOO_LARGE;
+        }
+        self.response.body.extend_from_slice(bytes);
+        0
+    }
+}
+
+/// Copies as much of `bytes` as fits into the guest's `buf` and returns the full length.
+fn copy_out(caller: &mut Caller<'_, StoreState>, bytes: Vec<u8>, buf_ptr: u32, buf_len: u32) -> wasmtime::Result<i64> {
+    write_guest(caller, buf_ptr, &bytes[..bytes.len().min(buf_len as usize)])?;
+    Ok(bytes.len() as i64)
+}
+
+/// Registers the `voike.http_*` imports a mounted module uses from `handle_request() -> i32`,
+/// whose return value is the response status. Negative results are the `HTTP_*` codes above.
+pub fn add_to_linker(linker: &mut Linker<StoreState>) -> wasmtime::Result<()> {
+    // http_request_head(buf, buf_len) -> length of the JSON head (method, path, query, headers)
+    linker.func_wrap(
+        "voike",
+        "http_request_head",
+        |mut caller: Caller<'_, StoreState>, buf_ptr: u32, buf_len: u32| -> wasmtime::Result<i64> {
+            let http = &caller.data().http;
+            if http.request.is_none() {
+                return Ok(HTTP_NO_REQUEST.into());
+            }
+            let head = http.head.clone();
+            copy_out(&mut caller, head, buf_ptr, buf_len)
+        },
+    )?;
+    // http_request_body(buf, buf_len) -> body length
+    linker.func_wrap(
+        "voike",
+        "http_request_body",
+        |mut caller: Caller<'_, StoreState>, buf_ptr: u32, buf_len: u32| -> wasmtime::Result<i64> {
+            let Some(request) = &caller.data().http.request else {
+                return Ok(HTTP_NO_REQUEST.into());
+            };
+            let body = request.body.clone();
+            copy_out(&mut caller, body, buf_ptr, buf_len)
+        },
+    )?;
+    // http_response_header(name, name_len, value, value_len) -> 0; replaces an earlier value
+    linker.func_wrap(
+        "voike",
+        "http_response_header",
+        |mut caller: Caller<'_, StoreState>, name_ptr: u32, name_len: u32, value_ptr: u32, value_len: u32| -> wasmtime::Result<i32> {
+            if caller.data().http.request.is_none() {
+                return Ok(HTTP_NO_REQUEST);
+            }
+            if name_len > MAX_HEADER_NAME_BYTES || value_len > MAX_HEADER_VALUE_BYTES {
+                return Ok(HTTP_BAD_HEADER);
+            }
+            let name = read_guest(&mut caller, name_ptr, name_len)?;
+            let value = read_guest(&mut caller, value_ptr, value_len)?;
+            Ok(caller.data_mut().http.set_header(&name, &value))
+        },
+    )?;
+    // http_response_write(data, len) -> 0; appends to the response body
+    linker.func_wrap(
+        "voike",
+        "http_response_write",
+        |mut caller: Caller<'_, StoreState>, ptr: u32, len: u32| -> wasmtime::Result<i32> {
+            let http = &caller.data().http;
+            if http.request.is_none() {
+                return Ok(HTTP_NO_REQUEST);
+            }
+            if http.response.body.len().saturating_add(len as usize) > http.max_response_bytes {
+                caller.data_mut().http.response.overflowed = true;
+                return Ok(HTTP_TOO_LARGE);
+            }
+            let bytes = read_guest(&mut caller, ptr, len)?;
+            Ok(caller.data_mut().http.write(&bytes))
+        },
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(max_response_bytes: usize) -> HttpExchange {
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            path: "/".to_string(),
+            query: String::new(),
+            headers: BTreeMap::new(),
+            body: Vec::new(),
+        };
+        HttpExchange::new(Some(request), max_response_bytes)
+    }
+
+    #[test]
+    fn caps_the_response_body() {
+        let mut http = exchange(8);
+        assert_eq!(http.write(b"hello"), 0);
+        assert_eq!(http.write(b"world"), HTTP_TOO_LARGE);
+        let response = http.finish().unwrap();
+        assert_eq!(response.body, b"hello");
+        assert!(response.overflowed);
+        assert!(HttpExchange::new(None, 8).finish().is_none());
+    }
+
+    #[test]
+    fn replaces_headers_and_refuses_reserved_ones() {
+        let mut http = exchange(8);
+        assert_eq!(http.set_header(b"Content-Type", b"text/plain"), 0);
+        assert_eq!(http.set_header(b"content-type", b"application/json"), 0);
+        assert_eq!(http.set_header(b"Content-Length", b"3"), HTTP_BAD_HEADER);
+        assert_eq!(http.set_header(b"bad header", b"x"), HTTP_BAD_HEADER);
+        let headers = http.finish().unwrap().headers;
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].1, "application/json");
+    }
+
+    #[test]
+    fn caps_the_number_of_response_headers() {
+        let mut http = exchange(8);
+        for n in 0..MAX_RESPONSE_HEADERS {
+            assert_eq!(http.set_header(format!("x-header-{}", n).as_bytes(), b"1"), 0);
+        }
+        assert_eq!(http.set_header(b"x-one-more", b"1"), HTTP_TOO_LARGE);
+        assert_eq!(http.set_header(b"x-header-0", b"2"), 0);
+        assert_eq!(http.finish().unwrap().headers.len(), MAX_RESPONSE_HEADERS);
+    }
+
+    #[tokio::test]
+    async fn keeps_credentials_out_of_the_request_head() {
+        let request = warp::test::request()
+            .method("POST")
+            .path("/apps/report/run?x=1")
+            .header(ADMIN_HEADER, "admin-secret")
+            .header(API_KEY_HEADER, "project-key")
+            .header("Authorization", "Bearer token")
+            .header("content-type", "application/json")
+            .filter(&warp::path!("apps" / String / ..).and(request_head()))
+            .await
+            .unwrap();
+        let (_, head) = request;
+        assert_eq!((head.method.as_str(), head.path.as_str(), head.query.as_str()), ("POST", "/run", "x=1"));
+        assert_eq!(head.headers, BTreeMap::from([("content-type".to_string(), "application/json".to_string())]));
+    }
+}
@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf, sync::Arc};
+use warp::{http::StatusCode, reject::Reject, Filter, Rejection};
+
+use crate::wasm::WasmRuntime;
+
+/// Header carrying a project API key, as on the control plane.
+pub const API_KEY_HEADER: &str = "x-voike-api-key";
+/// Header carrying the admin token, as on the control plane.
+pub const ADMIN_HEADER: &str = "x-voike-admin-token";
+
+pub const REDACTED: &str = "[redacted]";
+
+/// `[auth]` config section. Auth is enforced as soon as an admin token or any project key is configured.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub admin_token: Option<String>,
+    /// Project id to its accepted API keys.
+    pub projects: BTreeMap<String, Vec<String>>,
+    /// TOML file with `admin_token` and `[projects]` in the same format, merged into this section.
+    pub key_file: Option<PathBuf>,
+    /// Serve `/status`, its history and streams, and `/metrics` without credentials.
+    pub public_status: bool,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            admin_token: None,
+            projects: BTreeMap::new(),
+            key_file: None,
+            public_status: true,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyFile {
+    admin_token: Option<String>,
+    #[serde(default)]
+    projects: BTreeMap<String, Vec<String>>,
+}
+
+impl AuthConfig {
+    /// Merges `key_file` into the section; an admin token set in config takes precedence.
+    pub fn load_key_file(&mut self) -> Result<(), String> {
+        let Some(path) = &self.key_file else {
+            return Ok(());
+        };
+        let raw = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let keys: KeyFile = toml::from_str(&raw).map_err(|err| format!("{}: {}", path.display(), err))?;
+        if self.admin_token.is_none() {
+            self.admin_token = keys.admin_token;
+        }
+        for (project, project_keys) in keys.projects {
+            self.projects.entry(project).or_default().extend(project_keys);
+        }
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.admin_token.is_some() || self.projects.values().any(|keys| !keys.is_empty())
+    }
+
+    pub fn redacted(&self) -> Self {
+        let mut auth = self.clone();
+        if auth.admin_token.is_some() {
+            auth.admin_token = Some(REDACTED.to_string());
+        }
+        for keys in auth.projects.values_mut() {
+            keys.iter_mut().for_each(|key| *key = REDACTED.to_string());
+        }
+        auth
+    }
+
+    /// Resolves request credentials. Without any configured credentials every caller is an admin.
+    fn resolve(&self, api_key: Option<&str>, admin_token: Option<&str>) -> Result<Caller, AuthRejection> {
+        if !self.enabled() {
+            return Ok(Caller::Admin);
+        }
+        if let Some(token) = admin_token {
+            return match &self.admin_token {
+                Some(expected) if secure_eq(token, expected) => Ok(Caller::Admin),
+                _ => Err(AuthRejection::unauthorized("invalid admin token")),
+            };
+        }
+        if let Some(key) = api_key {
+            return self
+                .projects
+                .iter()
+                .find(|(_, keys)| keys.iter().any(|expected| secure_eq(key, expected)))
+                .map(|(project, _)| Caller::Project(project.clone()))
+                .ok_or_else(|| AuthRejection::unauthorized("invalid API key"));
+        }
+        Ok(Caller::Anonymous)
+    }
+}
+
+/// Compares secrets without short-circuiting on the first differing byte.
+fn secure_eq(given: &str, expected: &str) -> bool {
+    given.len() == expected.len() && given.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Who is making a request.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Caller {
+    Admin,
+    Project(String),
+    Anonymous,
+}
+
+impl Caller {
+    /// May list and invoke a module owned by `owner` (`None` for shared modules).
+    pub fn can_use(&self, owner: Option<&str>) -> bool {
+        match self {
+            Caller::Admin => true,
+            Caller::Project(project) => owner.is_none_or(|owner| owner == project),
+            Caller::Anonymous => false,
+        }
+    }
+
+    /// The project a project caller acts for.
+    pub fn project(&self) -> Option<&str> {
+        match self {
+            Caller::Project(project) => Some(project),
+            _ => None,
+        }
+    }
+
+    /// How the caller appears in invocation records.
+    pub fn label(&self) -> String {
+        match self {
+            Caller::Admin => "admin".to_string(),
+            Caller::Project(project) => format!("project:{}", project),
+            Caller::Anonymous => "anonymous".to_string(),
+        }
+    }
+
+    /// May read the history and logs of calls made for `project`. Calls made for no project are
+    /// admin-only, so a shared module's callers never see each other's calls.
+    pub fn can_see(&self, project: Option<&str>) -> bool {
+        match self {
+            Caller::Admin => true,
+            Caller::Project(caller) => project == Some(caller.as_str()),
+            Caller::Anonymous => false,
+        }
+    }
+
+    /// May replace, reconfigure or unload a module owned by `owner`. Shared modules are admin-only.
+    pub fn can_manage(&self, owner: Option<&str>) -> bool {
+        match self {
+            Caller::Admin => true,
+            Caller::Project(project) => owner == Some(project.as_str()),
+            Caller::Anonymous => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AuthRejection {
+    pub status: StatusCode,
+    pub error: &'static str,
+    pub message: &'static str,
+}
+
+impl AuthRejection {
+    fn unauthorized(message: &'static str) -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            error: "unauthorized",
+            message,
+        }
+    }
+
+    fn forbidden(message: &'static str) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            error: "forbidden",
+            message,
+        }
+    }
+}
+
+impl Reject for AuthRejection {}
+
+fn credentials(runtime: Arc<WasmRuntime>) -> impl Filter<Extract = (Caller,), Error = Rejection> + Clone {
+    warp::header::optional::<String>(API_KEY_HEADER)
+        .and(warp::header::optional::<String>(ADMIN_HEADER))
+        .and_then(move |api_key: Option<String>, admin_token: Option<String>| {
+            let config = runtime.config.get();
+            async move { config.auth.resolve(api_key.as_deref(), admin_token.as_deref()).map_err(warp::reject::custom) }
+        })
+}
+
+/// Requires an admin token or project API key.
+pub fn caller(runtime: Arc<WasmRuntime>) -> impl Filter<Extract = (Caller,), Error = Rejection> + Clone {
+    credentials(runtime).and_then(|caller: Caller| async move {
+        match caller {
+            Caller::Anonymous => Err(warp::reject::custom(AuthRejection::unauthorized("missing API key"))),
+            caller => Ok(caller),
+        }
+    })
+}
+
+/// Resolves credentials when sent; requests without any pass as `Caller::Anonymous`.
+pub fn optional_caller(runtime: Arc<WasmRuntime>) -> impl Filter<Extract = (Caller,), Error = Rejection> + Clone {
+    credentials(runtime)
+}
+
+/// Requires the admin token.
+pub fn admin(runtime: Arc<WasmRuntime>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    caller(runtime)
+        .and_then(|caller: Caller| async move {
+            match caller {
+                Caller::Admin => Ok(()),
+                _ => Err(warp::reject::custom(AuthRejection::forbidden("admin token required"))),
+            }
+        })
+        .untuple_one()
+}
+
+/// Passes everyone when `auth.public_status` is set, otherwise requires credentials.
+pub fn status_access(runtime: Arc<WasmRuntime>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>(API_KEY_HEADER)
+        .and(warp::header::optional::<String>(ADMIN_HEADER))
+        .and_then(move |api_key: Option<String>, admin_token: Option<String>| {
+            let config = runtime.config.get();
+            async move {
+                if config.auth.public_status {
+                    return Ok(());
+                }
+                match config.auth.resolve(api_key.as_deref(), admin_token.as_deref()) {
+                    Ok(Caller::Anonymous) => Err(warp::reject::custom(AuthRejection::unauthorized("missing API key"))),
+                    Ok(_) => Ok(()),
+                    Err(rejection) => Err(warp::reject::custom(rejection)),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AuthConfig {
+        AuthConfig {
+            admin_token: Some("admin".to_string()),
+            projects: BTreeMap::from([("acme".to_string(), vec!["acme-key".to_string()])]),
+            ..AuthConfig::default()
+        }
+    }
+
+    #[test]
+    fn resolves_credentials() {
+        let auth = config();
+        assert_eq!(auth.resolve(None, Some("admin")).unwrap(), Caller::Admin);
+        assert_eq!(auth.resolve(Some("acme-key"), None).unwrap(), Caller::Project("acme".to_string()));
+        assert_eq!(auth.resolve(None, None).unwrap(), Caller::Anonymous);
+        assert!(auth.resolve(Some("other"), None).is_err());
+        assert!(auth.resolve(None, Some("adm")).is_err());
+        assert_eq!(AuthConfig::default().resolve(None, None).unwrap(), Caller::Admin);
+    }
+
+    #[test]
+    fn scopes_modules_to_their_project() {
+        let acme = Caller::Project("acme".to_string());
+        assert!(acme.can_use(Some("acme")) && acme.can_manage(Some("acme")));
+        assert!(!acme.can_use(Some("globex")) && !acme.can_manage(Some("globex")));
+        assert!(acme.can_use(None) && !acme.can_manage(None));
+        assert!(Caller::Admin.can_manage(Some("globex")));
+    }
+}
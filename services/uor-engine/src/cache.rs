@@ -0,0 +1,258 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Instant,
+};
+use wasmtime::{Engine, Module};
+
+use crate::registry::content_hash;
+
+/// A module ready to instantiate, plus how it was produced.
+pub struct Compiled {
+    pub module: Module,
+    /// `"compiled"` for a cold compile, `"cache"` when deserialized from disk.
+    pub source: &'static str,
+    pub load_ms: f64,
+}
+
+/// Cold/warm load counters surfaced on `/status`.
+#[derive(Clone, Default, Serialize)]
+pub struct CacheStats {
+    pub enabled: bool,
+    pub hits: u64,
+    pub misses: u64,
+    pub rejected: u64,
+    pub last_compile_ms: f64,
+    pub last_deserialize_ms: f64,
+    pub avg_compile_ms: f64,
+    pub avg_deserialize_ms: f64,
+}
+
+/// Content-addressed store of `Module::serialize` artifacts.
+///
+/// Artifacts are keyed by the source hash and the engine's precompile
+/// compatibility hash (wasmtime version, target and engine config), and each
+/// one carries a SHA-256 sidecar that is checked before deserializing.
+pub struct ModuleCache {
+    dir: Option<PathBuf>,
+    compat: String,
+    stats: Mutex<CacheStats>,
+}
+
+impl ModuleCache {
+    pub fn new(engine: &Engine, dir: Option<PathBuf>) -> Self {
+        let dir = dir.filter(|dir| match fs::create_dir_all(dir) {
+            Ok(()) => true,
+            Err(err) => {
+                eprintln!("[uor-engine] module cache disabled, cannot create {}: {}", dir.display(), err);
+                false
+            }
+        });
+        let mut hasher = StableHasher::default();
+        engine.precompile_compatibility_hash().hash(&mut hasher);
+        Self {
+            stats: Mutex::new(CacheStats {
+                enabled: dir.is_some(),
+                ..CacheStats::default()
+            }),
+            dir,
+            compat: hasher.0.finalize()[..8].iter().map(|byte| format!("{:02x}", byte)).collect(),
+        }
+    }
+
+    /// Artifact directory, or `None` when the cache is disabled.
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Deserializes a cached artifact for `bytes` when a valid one exists, otherwise compiles and stores one.
+    pub fn compile(&self, engine: &Engine, bytes: &[u8]) -> Result<Compiled, wasmtime::Error> {
+        let path = self.artifact_path(bytes);
+        if let Some(path) = &path {
+            let start = Instant::now();
+            if let Some(module) = self.read_artifact(engine, path) {
+                let load_ms = start.elapsed().as_secs_f64() * 1000.0;
+                self.record(|stats| {
+                    stats.hits += 1;
+                    stats.last_deserialize_ms = load_ms;
+                    stats.avg_deserialize_ms = running_avg(stats.avg_deserialize_ms, load_ms, stats.hits);
+                });
+                return Ok(Compiled {
+                    module,
+                    source: "cache",
+                    load_ms,
+                });
+            }
+        }
+
+        let start = Instant::now();
+        let module = Module::new(engine, bytes)?;
+        let load_ms = start.elapsed().as_secs_f64() * 1000.0;
+        self.record(|stats| {
+            stats.misses += 1;
+            stats.last_compile_ms = load_ms;
+            stats.avg_compile_ms = running_avg(stats.avg_compile_ms, load_ms, stats.misses);
+        });
+        if let Some(path) = &path {
+            if let Err(err) = write_artifact(&module, path) {
+                eprintln!("[uor-engine] failed to cache {}: {:#}", path.display(), err);
+            }
+        }
+        Ok(Compiled {
+            module,
+            source: "compiled",
+            load_ms,
+        })
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats.lock().map(|stats| stats.clone()).unwrap_or_default()
+    }
+
+    fn artifact_path(&self, bytes: &[u8]) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}-{}.cwasm", content_hash(bytes), self.compat)))
+    }
+
+    fn read_artifact(&self, engine: &Engine, path: &Path) -> Option<Module> {
+        let artifact = fs::read(path).ok()?;
+        let expected = fs::read_to_string(checksum_path(path)).ok();
+        if expected.as_deref().map(str::trim) != Some(content_hash(&artifact).as_str()) {
+            self.reject(path, "checksum mismatch");
+            return None;
+        }
+        // SAFETY: the artifact was produced by `Module::serialize` on this node and its
+        // checksum matches; wasmtime additionally rejects version or config mismatches.
+        match unsafe { Module::deserialize(engine, &artifact) } {
+            Ok(module) => Some(module),
+            Err(err) => {
+                self.reject(path, &format!("{:#}", err));
+                None
+            }
+        }
+    }
+
+    fn reject(&self, path: &Path, reason: &str) {
+        eprintln!("[uor-engine] discarding cached module {}: {}", path.display(), reason);
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(checksum_path(path));
+        self.record(|stats| stats.rejected += 1);
+    }
+
+    fn record(&self, update: impl FnOnce(&mut CacheStats)) {
+        if let Ok(mut stats) = self.stats.lock() {
+            update(&mut stats);
+        }
+    }
+}
+
+/// Feeds `Hash` output into SHA-256, so artifact names do not change with the Rust release the
+/// way `DefaultHasher` output may.
+#[derive(Default)]
+struct StableHasher(Sha256);
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        let digest = self.0.clone().finalize();
+        u64::from_le_bytes(digest[..8].try_into().unwrap_or_default())
+    }
+}
+
+fn write_artifact(module: &Module, path: &Path) -> Result<(), wasmtime::Error> {
+    let artifact = module.serialize()?;
+    let tmp = path.with_extension("cwasm.tmp");
+    fs::write(&tmp, &artifact)?;
+    fs::write(checksum_path(path), content_hash(&artifact))?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn checksum_path(path: &Path) -> PathBuf {
+    path.with_extension("cwasm.sha256")
+}
+
+fn running_avg(avg: f64, sample: f64, count: u64) -> f64 {
+    avg + (sample - avg) / count as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm::build_engine;
+
+    const ADD: &[u8] = br#"(module (func (export "add") (param i32 i32) (result i32) local.get 0 local.get 1 i32.add))"#;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("uor-cache-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn keys_artifacts_by_a_stable_compatibility_hash() {
+        let engine = build_engine().unwrap();
+        let cache = ModuleCache::new(&engine, None);
+        assert_eq!(cache.compat.len(), 16);
+        assert_eq!(cache.compat, ModuleCache::new(&build_engine().unwrap(), None).compat);
+        let other = Engine::new(wasmtime::Config::new().consume_fuel(false)).unwrap();
+        assert_ne!(cache.compat, ModuleCache::new(&other, None).compat);
+    }
+
+    #[test]
+    fn loads_a_cached_artifact_on_the_second_compile() {
+        let dir = cache_dir("hit");
+        let engine = build_engine().unwrap();
+        let cache = ModuleCache::new(&engine, Some(dir.clone()));
+        assert_eq!(cache.compile(&engine, ADD).unwrap().source, "compiled");
+        assert_eq!(cache.compile(&engine, ADD).unwrap().source, "cache");
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.rejected), (1, 1, 0));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn recompiles_over_a_corrupt_artifact() {
+        let dir = cache_dir("corrupt");
+        let engine = build_engine().unwrap();
+        let cache = ModuleCache::new(&engine, Some(dir.clone()));
+        cache.compile(&engine, ADD).unwrap();
+        let path = cache.artifact_path(ADD).unwrap();
+        fs::write(&path, b"not an artifact").unwrap();
+
+        assert_eq!(cache.compile(&engine, ADD).unwrap().source, "compiled");
+        assert_eq!(cache.stats().rejected, 1);
+        // The fresh compile replaced the artifact, so the next load is a hit again.
+        assert_eq!(cache.compile(&engine, ADD).unwrap().source, "cache");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn recompiles_over_an_artifact_from_an_incompatible_engine() {
+        let dir = cache_dir("incompatible");
+        let engine = build_engine().unwrap();
+        let cache = ModuleCache::new(&engine, Some(dir.clone()));
+        let path = cache.artifact_path(ADD).unwrap();
+        let other = Engine::new(wasmtime::Config::new().consume_fuel(false)).unwrap();
+        write_artifact(&Module::new(&other, ADD).unwrap(), &path).unwrap();
+
+        let compiled = cache.compile(&engine, ADD).unwrap();
+        assert_eq!(compiled.source, "compiled");
+        assert_eq!(cache.stats().rejected, 1);
+        let mut store = wasmtime::Store::new(&engine, ());
+        store.set_fuel(1_000).unwrap();
+        store.set_epoch_deadline(1);
+        let instance = wasmtime::Instance::new(&mut store, &compiled.module, &[]).unwrap();
+        let add = instance.get_typed_func::<(i32, i32), i32>(&mut store, "add").unwrap();
+        assert_eq!(add.call(&mut store, (2, 3)).unwrap(), 5);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
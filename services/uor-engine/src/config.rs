@@ -0,0 +1,431 @@
+use chrono::{DateTime, Utc};
+use notify::{RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::{mpsc, watch},
+};
+
+use crate::{
+    apps::AppsConfig,
+    auth::AuthConfig,
+    control_plane::ControlPlaneConfig,
+    grid::GridConfig,
+    health::ResourceBudget,
+    invocations::InvocationsConfig,
+    jobs::JobsConfig,
+    kv::KvConfig,
+    limits::{env_or, ExecutionLimits},
+    logging::LogConfig,
+    mesh::NodeConfig,
+    sleep::SleepPolicy,
+    status::SamplingPolicy,
+    tls::TlsConfig,
+    vasm::VasmConfig,
+    wasm::WasmRuntime,
+};
+
+/// Read when `UOR_CONFIG` is unset; the engine runs on defaults if it does not exist.
+pub const DEFAULT_PATH: &str = "uor-engine.toml";
+
+/// Editors often write a file in several steps; changes within this window are coalesced.
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Effective engine configuration: `uor-engine.toml` with `UOR_*` environment overrides applied.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub modules: ModulesConfig,
+    pub limits: ExecutionLimits,
+    pub sampling: SamplingPolicy,
+    pub sleep: SleepPolicy,
+    pub budget: ResourceBudget,
+    pub auth: AuthConfig,
+    pub kv: KvConfig,
+    pub logging: LogConfig,
+    pub invocations: InvocationsConfig,
+    pub jobs: JobsConfig,
+    pub apps: AppsConfig,
+    pub vasm: VasmConfig,
+    pub control_plane: ControlPlaneConfig,
+    pub node: NodeConfig,
+    pub grid: GridConfig,
+    pub storage: StorageConfig,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: Vec<SocketAddr>,
+    /// How long shutdown waits for in-flight invocations before giving up on them.
+    pub drain_timeout_ms: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: vec![SocketAddr::from(([0, 0, 0, 0], 9090))],
+            drain_timeout_ms: 30_000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModulesConfig {
+    /// `.wasm`/`.wat` files loaded on boot and whenever they are added on reload.
+    pub preload: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// Precompiled module artifacts; empty disables the cache.
+    pub cache_dir: PathBuf,
+    /// Host directory WASI preopens are resolved against.
+    pub wasi_root: PathBuf,
+    /// redb file backing the guest key-value store; empty disables it.
+    pub kv_path: PathBuf,
+    /// JSON file schedules are saved to; empty keeps them in memory only.
+    pub schedules_file: PathBuf,
+    /// Where the final OpenMetrics snapshot is written on shutdown, if set.
+    pub metrics_file: Option<PathBuf>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            cache_dir: PathBuf::from(".uor-cache"),
+            wasi_root: PathBuf::from("wasi-data"),
+            kv_path: PathBuf::from("uor-kv.redb"),
+            schedules_file: PathBuf::from("uor-schedules.json"),
+            metrics_file: None,
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Parses `path` (defaults when it is missing and not `required`), then applies env overrides.
+    pub fn load(path: &Path, required: bool) -> Result<Self, String> {
+        let mut config: Self = match fs::read_to_string(path) {
+            Ok(raw) => toml::from_str(&raw).map_err(|err| format!("{}: {}", path.display(), err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => Self::default(),
+            Err(err) => return Err(format!("{}: {}", path.display(), err)),
+        };
+        config.apply_env()?;
+        config.auth.load_key_file()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<(), String> {
+        if let Ok(value) = env::var("UOR_BIND_ADDR") {
+            self.server.bind = split_list(&value)
+                .map(|addr| addr.parse().map_err(|_| format!("UOR_BIND_ADDR: invalid address `{}`", addr)))
+                .collect::<Result<_, _>>()?;
+        }
+        self.server.drain_timeout_ms = env_or("UOR_DRAIN_TIMEOUT_MS", self.server.drain_timeout_ms);
+        if let Ok(value) = env::var("UOR_WASM_MODULE") {
+            self.modules.preload = split_list(&value).map(PathBuf::from).collect();
+        }
+        if let Ok(value) = env::var("UOR_CACHE_DIR") {
+            self.storage.cache_dir = PathBuf::from(value);
+        }
+        if let Ok(value) = env::var("UOR_WASI_ROOT") {
+            self.storage.wasi_root = PathBuf::from(value);
+        }
+        if let Ok(value) = env::var("UOR_KV_PATH") {
+            self.storage.kv_path = PathBuf::from(value);
+        }
+        if let Ok(value) = env::var("UOR_SCHEDULES_FILE") {
+            self.storage.schedules_file = PathBuf::from(value);
+        }
+        if let Ok(value) = env::var("UOR_METRICS_FILE") {
+            self.storage.metrics_file = Some(PathBuf::from(value)).filter(|path| !path.as_os_str().is_empty());
+        }
+        if let Ok(value) = env::var("UOR_ADMIN_TOKEN") {
+            self.auth.admin_token = Some(value).filter(|token| !token.is_empty());
+        }
+        if let Ok(value) = env::var("UOR_KEY_FILE") {
+            self.auth.key_file = Some(PathBuf::from(value)).filter(|path| !path.as_os_str().is_empty());
+        }
+        self.tls.apply_env();
+        self.limits.apply_env();
+        self.kv.apply_env();
+        self.logging.apply_env();
+        self.invocations.apply_env();
+        self.jobs.apply_env();
+        self.apps.apply_env();
+        self.vasm.apply_env();
+        self.control_plane.apply_env();
+        self.node.apply_env();
+        self.grid.apply_env();
+        self.sampling.apply_env();
+        self.sleep.apply_env()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.server.bind.is_empty() {
+            return Err("server.bind must list at least one address".to_string());
+        }
+        self.tls.validate()?;
+        self.jobs.validate()?;
+        self.control_plane.validate()?;
+        self.node.validate()?;
+        self.grid.validate()?;
+        if self.grid.enabled() && !self.control_plane.enabled() {
+            return Err("grid.kinds requires control_plane.url".to_string());
+        }
+        self.sampling.validate()?;
+        self.sleep.validate()
+    }
+
+    /// Copy safe to return from `GET /config`.
+    pub fn redacted(&self) -> Self {
+        Self {
+            auth: self.auth.redacted(),
+            control_plane: self.control_plane.redacted(),
+            ..self.clone()
+        }
+    }
+
+    /// Settings that are only read on boot and differ in `next`.
+    fn restart_required(&self, next: &Self) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.server.bind != next.server.bind {
+            changed.push("server.bind");
+        }
+        if self.tls.enabled() != next.tls.enabled() {
+            changed.push("tls");
+        }
+        if self.storage.cache_dir != next.storage.cache_dir {
+            changed.push("storage.cache_dir");
+        }
+        if self.storage.wasi_root != next.storage.wasi_root {
+            changed.push("storage.wasi_root");
+        }
+        if self.storage.kv_path != next.storage.kv_path {
+            changed.push("storage.kv_path");
+        }
+        if self.storage.schedules_file != next.storage.schedules_file {
+            changed.push("storage.schedules_file");
+        }
+        changed
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+/// `GET /config` body.
+#[derive(Serialize)]
+pub struct ConfigSnapshot {
+    pub path: PathBuf,
+    pub file_found: bool,
+    pub loaded_at: String,
+    pub config: EngineConfig,
+}
+
+/// Holds the current config and notifies subscribers when a reload replaces it.
+pub struct ConfigStore {
+    path: PathBuf,
+    required: bool,
+    current: watch::Sender<Arc<EngineConfig>>,
+    loaded_at: Mutex<DateTime<Utc>>,
+}
+
+impl ConfigStore {
+    /// Loads the file named by `UOR_CONFIG`, or `uor-engine.toml` if present.
+    pub fn from_env() -> Result<Self, String> {
+        let (path, required) = match env::var("UOR_CONFIG") {
+            Ok(path) if !path.is_empty() => (PathBuf::from(path), true),
+            _ => (PathBuf::from(DEFAULT_PATH), false),
+        };
+        let config = EngineConfig::load(&path, required)?;
+        Ok(Self {
+            path,
+            required,
+            current: watch::channel(Arc::new(config)).0,
+            loaded_at: Mutex::new(Utc::now()),
+        })
+    }
+
+    pub fn get(&self) -> Arc<EngineConfig> {
+        self.current.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Arc<EngineConfig>> {
+        self.current.subscribe()
+    }
+
+    /// Re-reads the file; on error the current config stays in effect.
+    pub fn reload(&self) -> Result<Arc<EngineConfig>, String> {
+        let config = Arc::new(EngineConfig::load(&self.path, self.required)?);
+        self.current.send_replace(config.clone());
+        if let Ok(mut loaded_at) = self.loaded_at.lock() {
+            *loaded_at = Utc::now();
+        }
+        Ok(config)
+    }
+
+    pub fn snapshot(&self) -> ConfigSnapshot {
+        ConfigSnapshot {
+            path: self.path.clone(),
+            file_found: self.path.is_file(),
+            loaded_at: self.loaded_at.lock().map(|at| at.to_rfc3339()).unwrap_or_default(),
+            config: self.get().redacted(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl ConfigStore {
+    /// A store holding `config`, with no file behind it.
+    pub fn fixed(config: EngineConfig) -> Self {
+        Self {
+            path: PathBuf::new(),
+            required: false,
+            current: watch::channel(Arc::new(config)).0,
+            loaded_at: Mutex::new(Utc::now()),
+        }
+    }
+}
+
+/// Sends on `tx` whenever one of `paths` changes. Watches the parent directories rather than
+/// the files so editors and tools that replace a file are still seen.
+pub fn watch_files(paths: &[PathBuf], tx: mpsc::Sender<()>) -> Option<notify::RecommendedWatcher> {
+    let names: Vec<_> = paths.iter().filter_map(|path| path.file_name().map(|name| name.to_os_string())).collect();
+    let mut watcher = match notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let touched = event.is_ok_and(|event| {
+            !event.kind.is_access()
+                && event.paths.iter().any(|changed| changed.file_name().is_some_and(|name| names.iter().any(|watched| watched == name)))
+        });
+        if touched {
+            let _ = tx.try_send(());
+        }
+    }) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            eprintln!("[uor-engine] file watcher unavailable: {}", err);
+            return None;
+        }
+    };
+    let mut dirs: Vec<PathBuf> = paths
+        .iter()
+        .map(|path| match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        })
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    for dir in dirs {
+        if let Err(err) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            eprintln!("[uor-engine] not watching {} for changes: {}", dir.display(), err);
+        }
+    }
+    Some(watcher)
+}
+
+/// Reloads the config on SIGHUP or when the file changes. Most settings take effect at once;
+/// the ones that need a restart are logged. New or previously failed preloads are compiled on a
+/// blocking worker.
+pub async fn watch_for_changes(runtime: Arc<WasmRuntime>) {
+    let (tx, mut changes) = mpsc::channel::<()>(1);
+    let path = runtime.config.path.clone();
+    let _watcher = watch_files(std::slice::from_ref(&path), tx);
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            eprintln!("[uor-engine] SIGHUP handler unavailable: {}", err);
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            Some(()) = changes.recv() => {
+                tokio::time::sleep(RELOAD_DEBOUNCE).await;
+                while changes.try_recv().is_ok() {}
+            }
+            Some(()) = hangup.recv() => {}
+            else => break,
+        }
+        let previous = runtime.config.get();
+        match runtime.config.reload() {
+            Ok(config) => {
+                println!("[uor-engine] reloaded config from {}", runtime.config.path.display());
+                for setting in previous.restart_required(&config) {
+                    eprintln!("[uor-engine] config: `{}` changes take effect after a restart", setting);
+                }
+                let failed = runtime.preload_failures();
+                let pending: Vec<PathBuf> = config
+                    .modules
+                    .preload
+                    .iter()
+                    .filter(|path| !previous.modules.preload.contains(path) || failed.contains_key(*path))
+                    .cloned()
+                    .collect();
+                let worker = runtime.clone();
+                if let Err(err) = tokio::task::spawn_blocking(move || worker.preload(&pending)).await {
+                    eprintln!("[uor-engine] preloading modules failed: {}", err);
+                }
+                runtime.wake.notify_one();
+            }
+            Err(err) => eprintln!("[uor-engine] config reload failed, keeping previous config: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sections_and_keeps_defaults_for_the_rest() {
+        let config: EngineConfig = toml::from_str(
+            r#"
+            [server]
+            bind = ["127.0.0.1:9191", "[::1]:9191"]
+
+            [limits]
+            fuel = 5000
+
+            [sleep.cpu_percent]
+            warm = { enter = 10, exit = 8 }
+            active = { enter = 60, exit = 50 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.server.bind.len(), 2);
+        assert_eq!(config.limits.fuel, 5000);
+        assert_eq!(config.limits.timeout_ms, ExecutionLimits::default().timeout_ms);
+        assert_eq!(config.sleep.cpu_percent.active.enter, 60.0);
+        assert_eq!(config.storage, StorageConfig::default());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(toml::from_str::<EngineConfig>("[limits]\nfule = 1").is_err());
+    }
+
+    #[test]
+    fn redacts_secrets() {
+        let mut config = EngineConfig::default();
+        config.auth.admin_token = Some("s3cret".to_string());
+        config.auth.projects.insert("acme".to_string(), vec!["acme-key".to_string()]);
+        let body = serde_json::to_string(&config.redacted()).unwrap();
+        assert!(!body.contains("s3cret"));
+        assert!(!body.contains("acme-key"));
+    }
+}
@@ -0,0 +1,202 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{env, fmt, path::PathBuf, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    time,
+};
+use tokio_rustls::{
+    rustls::{ClientConfig, RootCertStore, ServerName},
+    TlsConnector,
+};
+use warp::{
+    http::{header, Method, Request, StatusCode, Uri},
+    hyper::{self, body::Bytes, client::conn, Body},
+};
+
+use crate::{
+    auth::{ADMIN_HEADER, REDACTED},
+    limits::env_or,
+    tls,
+};
+
+/// Error bodies are cut to this many bytes in messages.
+const MAX_ERROR_BODY: usize = 256;
+
+/// `[control_plane]` config section: the VOIKE API this node reports to. Nothing is sent while
+/// `url` is empty.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlPlaneConfig {
+    /// Base URL of the VOIKE API, e.g. `https://voike.internal:8080`.
+    pub url: String,
+    /// Sent as `x-voike-admin-token`.
+    pub admin_token: Option<String>,
+    /// PEM CA bundle used to verify an `https` URL.
+    pub ca_file: Option<PathBuf>,
+    /// Per request, connecting included.
+    pub timeout_ms: u64,
+}
+
+impl Default for ControlPlaneConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            admin_token: None,
+            ca_file: None,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+impl ControlPlaneConfig {
+    /// Applies `UOR_CONTROL_PLANE_URL`, `UOR_CONTROL_PLANE_TOKEN` and `UOR_CONTROL_PLANE_CA`.
+    pub fn apply_env(&mut self) {
+        if let Ok(url) = env::var("UOR_CONTROL_PLANE_URL") {
+            self.url = url;
+        }
+        if let Ok(token) = env::var("UOR_CONTROL_PLANE_TOKEN") {
+            self.admin_token = Some(token).filter(|token| !token.is_empty());
+        }
+        if let Ok(ca_file) = env::var("UOR_CONTROL_PLANE_CA") {
+            self.ca_file = Some(PathBuf::from(ca_file)).filter(|path| !path.as_os_str().is_empty());
+        }
+        self.timeout_ms = env_or("UOR_CONTROL_PLANE_TIMEOUT_MS", self.timeout_ms);
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.url.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        let uri: Uri = self.url.parse().map_err(|err| format!("control_plane.url: {}", err))?;
+        match uri.scheme_str() {
+            Some("http") => Ok(()),
+            Some("https") if self.ca_file.is_some() => Ok(()),
+            Some("https") => Err("control_plane.ca_file is required for an https url".to_string()),
+            _ => Err("control_plane.url must start with http:// or https://".to_string()),
+        }
+    }
+
+    pub fn redacted(&self) -> Self {
+        Self {
+            admin_token: self.admin_token.as_ref().map(|_| REDACTED.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+/// A failed control plane call.
+#[derive(Debug)]
+pub struct CallError {
+    /// Set when the control plane answered with a non-2xx status; `None` means it was not reached
+    /// or its answer could not be read.
+    pub status: Option<StatusCode>,
+    pub message: String,
+}
+
+impl CallError {
+    fn unreachable(message: impl Into<String>) -> Self {
+        Self {
+            status: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Percent-encodes `value` for use as one path segment, leaving only RFC 3986 unreserved characters as is.
+pub fn path_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Sends one request to the control plane and decodes a JSON answer; `204` and empty bodies
+/// decode as `null`. Non-2xx answers are errors carrying the status and the start of the body.
+pub async fn call<T: DeserializeOwned>(config: &ControlPlaneConfig, method: Method, path: &str, body: Option<&impl Serialize>) -> Result<T, CallError> {
+    let (status, bytes) = time::timeout(Duration::from_millis(config.timeout_ms), send(config, method.clone(), path, body))
+        .await
+        .map_err(|_| CallError::unreachable(format!("{} {} timed out after {} ms", method, path, config.timeout_ms)))?
+        .map_err(CallError::unreachable)?;
+    if !status.is_success() {
+        let text = String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_ERROR_BODY)]).into_owned();
+        return Err(CallError {
+            status: Some(status),
+            message: format!("{} {} returned {}: {}", method, path, status, text.trim()),
+        });
+    }
+    let bytes = if bytes.is_empty() { Bytes::from_static(b"null") } else { bytes };
+    serde_json::from_slice(&bytes).map_err(|err| CallError::unreachable(format!("{} {} returned an unexpected body: {}", method, path, err)))
+}
+
+async fn send(config: &ControlPlaneConfig, method: Method, path: &str, body: Option<&impl Serialize>) -> Result<(StatusCode, Bytes), String> {
+    let uri: Uri = format!("{}{}", config.url.trim_end_matches('/'), path).parse().map_err(|err| format!("{}: {}", path, err))?;
+    let host = uri.host().ok_or_else(|| format!("{} has no host", uri))?.to_string();
+    let https = uri.scheme_str() == Some("https");
+    let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri.path_and_query().map(|path| path.as_str()).unwrap_or("/"))
+        .header(header::HOST, uri.authority().map(|authority| authority.as_str()).unwrap_or(&host))
+        .header(header::USER_AGENT, concat!("uor-engine/", env!("CARGO_PKG_VERSION")));
+    if let Some(token) = &config.admin_token {
+        request = request.header(ADMIN_HEADER, token);
+    }
+    let request = match body {
+        Some(body) => {
+            let bytes = serde_json::to_vec(body).map_err(|err| err.to_string())?;
+            request.header(header::CONTENT_TYPE, "application/json").body(Body::from(bytes))
+        }
+        None => request.body(Body::empty()),
+    }
+    .map_err(|err| err.to_string())?;
+
+    let stream = TcpStream::connect((host.as_str(), port)).await.map_err(|err| format!("connect to {}:{}: {}", host, port, err))?;
+    if !https {
+        return exchange(stream, request).await;
+    }
+    let connector = TlsConnector::from(Arc::new(client_config(config)?));
+    let server_name = ServerName::try_from(host.as_str()).map_err(|err| format!("{}: {}", host, err))?;
+    let stream = connector.connect(server_name, stream).await.map_err(|err| format!("TLS handshake with {}: {}", host, err))?;
+    exchange(stream, request).await
+}
+
+/// One request over a fresh HTTP/1.1 connection; heartbeats and polls are seconds apart, so
+/// connections are not pooled.
+async fn exchange<IO>(io: IO, request: Request<Body>) -> Result<(StatusCode, Bytes), String>
+where
+    IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = conn::handshake(io).await.map_err(|err| err.to_string())?;
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    let response = sender.send_request(request).await.map_err(|err| err.to_string())?;
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.map_err(|err| err.to_string())?;
+    Ok((status, bytes))
+}
+
+/// Trusts only `ca_file`, which is re-read on every request so a rotated CA is picked up.
+fn client_config(config: &ControlPlaneConfig) -> Result<ClientConfig, String> {
+    let ca_file = config.ca_file.as_ref().ok_or("control_plane.ca_file is not set")?;
+    let mut roots = RootCertStore::empty();
+    for cert in tls::read_certs(ca_file)? {
+        roots.add(&cert).map_err(|err| format!("{}: {}", ca_file.display(), err))?;
+    }
+    Ok(ClientConfig::builder().with_safe_defaults().with_root_certificates(roots).with_no_client_auth())
+}
@@ -0,0 +1,171 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Timelike, Utc};
+use std::str::FromStr;
+
+/// How far ahead `next_after` looks before deciding an expression never fires (e.g. `0 0 30 2 *`).
+const SEARCH_YEARS: i64 = 5;
+
+/// One field as a bitmask of the values it allows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Field {
+    bits: u64,
+    /// Written as `*` or `*/n`; matters for the day-of-month/day-of-week rule.
+    wildcard: bool,
+}
+
+impl Field {
+    fn parse(spec: &str, min: u32, max: u32) -> Result<Self, String> {
+        let mut bits = 0u64;
+        for part in spec.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0).ok_or_else(|| format!("invalid step in `{}`", part))?),
+                None => (part, 1),
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((start, end)) => (value(start, min, max)?, value(end, min, max)?),
+                    // `5/15` runs from 5 to the end of the range.
+                    None if step > 1 => (value(range, min, max)?, max),
+                    None => {
+                        let value = value(range, min, max)?;
+                        (value, value)
+                    }
+                },
+            };
+            if start > end {
+                return Err(format!("range `{}` is backwards", range));
+            }
+            for v in (start..=end).step_by(step as usize) {
+                bits |= 1 << v;
+            }
+        }
+        Ok(Self {
+            bits,
+            wildcard: spec.starts_with('*'),
+        })
+    }
+
+    fn has(&self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+}
+
+fn value(raw: &str, min: u32, max: u32) -> Result<u32, String> {
+    raw.parse::<u32>()
+        .ok()
+        .filter(|value| (min..=max).contains(value))
+        .ok_or_else(|| format!("`{}` is not between {} and {}", raw, min, max))
+}
+
+/// A standard five-field cron expression (`minute hour day-of-month month day-of-week`),
+/// evaluated in UTC. Fields take `*`, values, `a-b` ranges, `/n` steps and comma lists; day of
+/// week runs 0-7 with both 0 and 7 meaning Sunday. `@hourly`, `@daily`, `@weekly`, `@monthly` and
+/// `@yearly` are accepted as shorthands.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CronExpr {
+    minute: Field,
+    hour: Field,
+    day_of_month: Field,
+    month: Field,
+    day_of_week: Field,
+}
+
+impl FromStr for CronExpr {
+    type Err = String;
+
+    fn from_str(expr: &str) -> Result<Self, String> {
+        let expr = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expr => expr,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(format!("expected 5 fields, got {}", fields.len()));
+        };
+        let mut day_of_week = Field::parse(day_of_week, 0, 7)?;
+        if day_of_week.has(7) {
+            day_of_week.bits |= 1;
+        }
+        Ok(Self {
+            minute: Field::parse(minute, 0, 59)?,
+            hour: Field::parse(hour, 0, 23)?,
+            day_of_month: Field::parse(day_of_month, 1, 31)?,
+            month: Field::parse(month, 1, 12)?,
+            day_of_week,
+        })
+    }
+}
+
+impl CronExpr {
+    /// Like cron, a date matches if either day field does when both are restricted.
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let dom = self.day_of_month.has(date.day());
+        let dow = self.day_of_week.has(date.weekday().num_days_from_sunday());
+        if self.day_of_month.wildcard || self.day_of_week.wildcard {
+            dom && dow
+        } else {
+            dom || dow
+        }
+    }
+
+    /// The first matching minute strictly after `after`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut at = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = after + Duration::days(366 * SEARCH_YEARS);
+        while at < limit {
+            let date = at.date_naive();
+            if !self.month.has(at.month()) {
+                let (year, month) = if at.month() == 12 { (at.year() + 1, 1) } else { (at.year(), at.month() + 1) };
+                at = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?.and_utc();
+            } else if !self.day_matches(date) {
+                at = date.succ_opt()?.and_hms_opt(0, 0, 0)?.and_utc();
+            } else if !self.hour.has(at.hour()) {
+                at = at.with_minute(0)? + Duration::hours(1);
+            } else if !self.minute.has(at.minute()) {
+                at += Duration::minutes(1);
+            } else {
+                return Some(at);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(raw: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(raw).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn finds_the_next_matching_minute() {
+        let every_15: CronExpr = "*/15 * * * *".parse().unwrap();
+        assert_eq!(every_15.next_after(at("2026-03-01T10:07:30Z")), Some(at("2026-03-01T10:15:00Z")));
+        assert_eq!(every_15.next_after(at("2026-03-01T10:45:00Z")), Some(at("2026-03-01T11:00:00Z")));
+
+        let weekday_mornings: CronExpr = "30 6 * * 1-5".parse().unwrap();
+        // 2026-03-07 is a Saturday.
+        assert_eq!(weekday_mornings.next_after(at("2026-03-06T07:00:00Z")), Some(at("2026-03-09T06:30:00Z")));
+
+        let new_year: CronExpr = "@yearly".parse().unwrap();
+        assert_eq!(new_year.next_after(at("2026-03-01T00:00:00Z")), Some(at("2027-01-01T00:00:00Z")));
+        let never: CronExpr = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(never.next_after(at("2026-03-01T00:00:00Z")), None);
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for bad in ["* * * *", "60 * * * *", "*/0 * * * *", "5-1 * * * *", "a * * * *"] {
+            assert!(bad.parse::<CronExpr>().is_err(), "{}", bad);
+        }
+        // Restricted day-of-month and day-of-week match either way: the 13th, or any Friday.
+        let either: CronExpr = "0 0 13 * 5".parse().unwrap();
+        assert_eq!(either.next_after(at("2026-03-01T00:00:00Z")), Some(at("2026-03-06T00:00:00Z")));
+    }
+}
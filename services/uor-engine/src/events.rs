@@ -0,0 +1,91 @@
+use futures_util::{future, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
+use tokio_stream::wrappers::BroadcastStream;
+
+use crate::status::RuntimeStatus;
+
+/// Events buffered per subscriber before a slow client starts missing them.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Pushed to `/status/stream` and `/status/ws` subscribers.
+#[derive(Clone, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum Event {
+    Status(Box<RuntimeStatus>),
+    SleepState { from: String, to: String, reason: String },
+    ModuleLoaded { name: String, hash: String, source: &'static str },
+    ModuleUnloaded { name: String },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Status(_) => "status",
+            Event::SleepState { .. } => "sleep_state",
+            Event::ModuleLoaded { .. } => "module_loaded",
+            Event::ModuleUnloaded { .. } => "module_unloaded",
+        }
+    }
+}
+
+/// Subscriber options shared by the SSE and WebSocket endpoints.
+#[derive(Deserialize, Default)]
+pub struct StreamQuery {
+    /// Minimum gap between pushed `status` samples; state-change events are never throttled.
+    pub min_interval_ms: Option<u64>,
+}
+
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+    closed: watch::Sender<bool>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            closed: watch::channel(false).0,
+        }
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: Event) {
+        // Sending only fails when nobody is subscribed.
+        let _ = self.sender.send(event);
+    }
+
+    /// Ends every subscriber stream so long-lived connections let the server shut down.
+    pub fn close(&self) {
+        self.closed.send_replace(true);
+    }
+
+    /// Subscribes to future events, dropping `status` samples that arrive within `min_interval_ms` of the last one sent.
+    pub fn subscribe(&self, query: &StreamQuery) -> impl Stream<Item = Event> + Send + 'static {
+        let min_interval = Duration::from_millis(query.min_interval_ms.unwrap_or(0));
+        let mut last_status: Option<Instant> = None;
+        let mut closed = self.closed.subscribe();
+        let stream = BroadcastStream::new(self.sender.subscribe()).filter_map(move |item| {
+            let event = match item {
+                Ok(Event::Status(status)) => {
+                    let now = Instant::now();
+                    if last_status.is_some_and(|last| now.duration_since(last) < min_interval) {
+                        None
+                    } else {
+                        last_status = Some(now);
+                        Some(Event::Status(status))
+                    }
+                }
+                Ok(event) => Some(event),
+                // Lagged subscribers skip what they missed.
+                Err(_) => None,
+            };
+            future::ready(event)
+        });
+        stream.take_until(async move {
+            let _ = closed.wait_for(|closed| *closed).await;
+        })
+    }
+}
@@ -0,0 +1,832 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    env,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::Notify,
+    time::{self, Instant},
+};
+use wasmtime::{Caller, Linker};
+
+use crate::{
+    config::EngineConfig,
+    control_plane::{self, CallError, ControlPlaneConfig},
+    invocations::{self, Invocation},
+    limits::{env_or, LimitOverrides},
+    logging::LogRecord,
+    mesh,
+    wasm::{read_guest, write_guest, InvokeError, InvokeResult, StoreState, WasmRuntime},
+};
+
+/// Grid job type whose module is picked by `params.task`.
+pub const CUSTOM_KIND: &str = "custom";
+
+/// Guest-visible status codes returned by the `voike.grid_*` imports.
+pub const GRID_NO_JOB: i32 = -1;
+pub const GRID_TOO_LARGE: i32 = -2;
+pub const GRID_BAD_RESULT: i32 = -3;
+
+const MAX_PROGRESS_MESSAGE_BYTES: u32 = 1024;
+const MAX_REF_NAME_BYTES: u32 = 256;
+const MAX_REF_BYTES: u32 = 4096;
+/// Distinct names a guest may set with `voike.grid_result_ref`.
+const MAX_RESULT_REFS: usize = 64;
+
+/// Failures another node may not hit, so the control plane is asked to retry them.
+const RETRYABLE: [&str; 6] = ["no_handler", "unknown_module", "draining", "timeout", "cancelled", "internal"];
+
+/// `[grid]` config section: the `/grid/jobs` kinds this node works on. The worker is off while
+/// `kinds` is empty.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GridConfig {
+    /// Job types to lease, e.g. `llm.infer` or `custom`.
+    pub kinds: Vec<String>,
+    /// Module that runs each kind other than `custom`.
+    pub handlers: BTreeMap<String, GridHandler>,
+    /// Module that runs each `custom` job, by its `params.task`.
+    pub tasks: BTreeMap<String, GridHandler>,
+    /// Jobs leased and running at once.
+    pub concurrency: usize,
+    /// Lease length asked for; leases are renewed every third of it while a job runs.
+    pub lease_ms: u64,
+    /// Wait before asking again after a lease request came back empty.
+    pub poll_interval_ms: u64,
+    /// Largest result a guest may set with `voike.grid_result`.
+    pub max_result_bytes: usize,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            kinds: Vec::new(),
+            handlers: BTreeMap::new(),
+            tasks: BTreeMap::new(),
+            concurrency: 2,
+            lease_ms: 30_000,
+            poll_interval_ms: 2_000,
+            max_result_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl GridConfig {
+    /// Applies `UOR_GRID_KINDS` (comma-separated), `UOR_GRID_CONCURRENCY`, `UOR_GRID_LEASE_MS`
+    /// and `UOR_GRID_POLL_INTERVAL_MS`.
+    pub fn apply_env(&mut self) {
+        if let Ok(kinds) = env::var("UOR_GRID_KINDS") {
+            self.kinds = kinds.split(',').map(str::trim).filter(|kind| !kind.is_empty()).map(str::to_string).collect();
+        }
+        self.concurrency = env_or("UOR_GRID_CONCURRENCY", self.concurrency);
+        self.lease_ms = env_or("UOR_GRID_LEASE_MS", self.lease_ms);
+        self.poll_interval_ms = env_or("UOR_GRID_POLL_INTERVAL_MS", self.poll_interval_ms);
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.kinds.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        if self.concurrency == 0 {
+            return Err("grid.concurrency must be at least 1".to_string());
+        }
+        if self.lease_ms < 3_000 {
+            return Err("grid.lease_ms must be at least 3000".to_string());
+        }
+        if self.poll_interval_ms < 100 {
+            return Err("grid.poll_interval_ms must be at least 100".to_string());
+        }
+        for kind in &self.kinds {
+            if kind == CUSTOM_KIND && self.tasks.is_empty() {
+                return Err("grid.kinds includes `custom` but grid.tasks is empty".to_string());
+            }
+            if kind != CUSTOM_KIND && !self.handlers.contains_key(kind) {
+                return Err(format!("grid.kinds includes `{}` but grid.handlers has no module for it", kind));
+            }
+        }
+        let handlers = self.handlers.iter().map(|(kind, handler)| ("handlers", kind, handler));
+        let tasks = self.tasks.iter().map(|(task, handler)| ("tasks", task, handler));
+        match handlers.chain(tasks).find(|(_, _, handler)| handler.module.is_empty()) {
+            Some((section, name, _)) => Err(format!("grid.{}.{}.module must not be empty", section, name)),
+            None => Ok(()),
+        }
+    }
+
+    /// The handler for a leased job, or why this node cannot run it.
+    fn handler(&self, job: &GridJob) -> Result<&GridHandler, String> {
+        if job.kind != CUSTOM_KIND {
+            return self.handlers.get(&job.kind).ok_or_else(|| format!("no module handles `{}` jobs", job.kind));
+        }
+        let task = job.params.get("task").and_then(Value::as_str).ok_or("custom job has no `params.task`")?;
+        self.tasks.get(task).ok_or_else(|| format!("no module handles custom task `{}`", task))
+    }
+
+    /// Kinds and custom tasks whose modules are loaded, so the node never leases work it cannot start.
+    fn leasable(&self, loaded: &[String]) -> (Vec<String>, Vec<String>) {
+        let is_loaded = |handler: &GridHandler| loaded.contains(&handler.module);
+        let tasks: Vec<String> = self.tasks.iter().filter(|(_, handler)| is_loaded(handler)).map(|(task, _)| task.clone()).collect();
+        let kinds = self
+            .kinds
+            .iter()
+            .filter(|kind| match kind.as_str() {
+                CUSTOM_KIND => !tasks.is_empty(),
+                kind => self.handlers.get(kind).is_some_and(is_loaded),
+            })
+            .cloned()
+            .collect();
+        (kinds, tasks)
+    }
+}
+
+/// Module export that runs one kind of grid job.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GridHandler {
+    pub module: String,
+    /// Called with no arguments; a non-zero return value fails the job.
+    pub export: String,
+    /// Replaces the configured `[limits]` for these jobs.
+    pub limits: LimitOverrides,
+}
+
+impl Default for GridHandler {
+    fn default() -> Self {
+        Self {
+            module: String::new(),
+            export: "run".to_string(),
+            limits: LimitOverrides::default(),
+        }
+    }
+}
+
+/// A leased job, as the control plane describes it and `voike.grid_job` hands it to the guest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GridJob {
+    pub job_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub params: Value,
+    #[serde(default)]
+    pub input_refs: Value,
+    /// 1 on the first run, counting runs on other nodes.
+    #[serde(default = "first_attempt")]
+    pub attempt: u32,
+    /// Lease granted, when the control plane shortened the one asked for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_ms: Option<u64>,
+}
+
+fn first_attempt() -> u32 {
+    1
+}
+
+/// `POST /grid/jobs/lease` body.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LeaseRequest {
+    node_id: String,
+    kinds: Vec<String>,
+    tasks: Vec<String>,
+    max: usize,
+    lease_ms: u64,
+}
+
+#[derive(Deserialize)]
+struct LeaseResponse {
+    #[serde(default)]
+    jobs: Vec<GridJob>,
+}
+
+/// Latest `voike.grid_progress` call, sent with the next lease renewal.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Progress {
+    #[serde(rename = "progress", skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// `POST /grid/jobs/{id}/heartbeat` body; renews the lease.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Renewal {
+    node_id: String,
+    lease_ms: u64,
+    #[serde(flatten)]
+    progress: Progress,
+}
+
+/// `POST /grid/jobs/{id}/complete` and `/fail` body.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    node_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    invocation_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    result_refs: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    /// Whether the control plane should hand the job to another attempt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry: Option<bool>,
+    logs: Vec<LogRecord>,
+}
+
+/// The job an invocation runs, for the `voike.grid_*` imports.
+pub struct GridTask {
+    pub job: GridJob,
+    pub progress: Arc<Mutex<Progress>>,
+}
+
+/// What a guest set while running a grid job.
+#[derive(Debug, Default)]
+pub struct GridOutput {
+    pub result: Option<Value>,
+    pub refs: BTreeMap<String, String>,
+}
+
+/// One invocation's side of a grid job; empty for ordinary invocations.
+pub struct GridExchange {
+    task: Option<GridTask>,
+    head: Vec<u8>,
+    output: GridOutput,
+    max_result_bytes: usize,
+}
+
+impl GridExchange {
+    pub fn new(task: Option<GridTask>, max_result_bytes: usize) -> Self {
+        let head = task.as_ref().and_then(|task| serde_json::to_vec(&task.job).ok()).unwrap_or_default();
+        Self {
+            task,
+            head,
+            output: GridOutput::default(),
+            max_result_bytes,
+        }
+    }
+
+    /// The output, if this invocation ran a grid job.
+    pub fn finish(self) -> Option<GridOutput> {
+        self.task.map(|_| self.output)
+    }
+
+    fn set_progress(&mut self, percent: f64, message: Option<String>) {
+        let Some(task) = &self.task else { return };
+        if let Ok(mut progress) = task.progress.lock() {
+            progress.percent = Some(if percent.is_nan() { 0.0 } else { percent.clamp(0.0, 100.0) });
+            progress.message = message;
+        }
+    }
+
+    fn set_result(&mut self, bytes: &[u8]) -> i32 {
+        if bytes.len() > self.max_result_bytes {
+            return GRID_TOO_LARGE;
+        }
+        match serde_json::from_slice(bytes) {
+            Ok(result) => {
+                self.output.result = Some(result);
+                0
+            }
+            Err(_) => GRID_BAD_RESULT,
+        }
+    }
+
+    fn set_ref(&mut self, name: String, reference: String) -> i32 {
+        let refs = &mut self.output.refs;
+        if refs.len() >= MAX_RESULT_REFS && !refs.contains_key(&name) {
+            return GRID_TOO_LARGE;
+        }
+        refs.insert(name, reference);
+        0
+    }
+}
+
+/// Registers the `voike.grid_*` imports a grid handler uses to read its job and report back.
+/// Negative results are the `GRID_*` codes above.
+pub fn add_to_linker(linker: &mut Linker<StoreState>) -> wasmtime::Result<()> {
+    // grid_job(buf, buf_len) -> length of the JSON job (jobId, type, params, inputRefs, attempt)
+    linker.func_wrap(
+        "voike",
+        "grid_job",
+        |mut caller: Caller<'_, StoreState>, buf_ptr: u32, buf_len: u32| -> wasmtime::Result<i64> {
+            let grid = &caller.data().grid;
+            if grid.task.is_none() {
+                return Ok(GRID_NO_JOB.into());
+            }
+            let head = grid.head.clone();
+            write_guest(&mut caller, buf_ptr, &head[..head.len().min(buf_len as usize)])?;
+            Ok(head.len() as i64)
+        },
+    )?;
+    // grid_progress(percent, msg, msg_len) -> 0; `msg_len = 0` for no message
+    linker.func_wrap(
+        "voike",
+        "grid_progress",
+        |mut caller: Caller<'_, StoreState>, percent: f64, msg_ptr: u32, msg_len: u32| -> wasmtime::Result<i32> {
+            if caller.data().grid.task.is_none() {
+                return Ok(GRID_NO_JOB);
+            }
+            if msg_len > MAX_PROGRESS_MESSAGE_BYTES {
+                return Ok(GRID_TOO_LARGE);
+            }
+            let message = read_guest(&mut caller, msg_ptr, msg_len)?;
+            let message = Some(String::from_utf8_lossy(&message).into_owned()).filter(|message| !message.is_empty());
+            caller.data_mut().grid.set_progress(percent, message);
+            Ok(0)
+        },
+    )?;
+    // grid_result(json, len) -> 0; replaces an earlier result
+    linker.func_wrap(
+        "voike",
+        "grid_result",
+        |mut caller: Caller<'_, StoreState>, ptr: u32, len: u32| -> wasmtime::Result<i32> {
+            let grid = &caller.data().grid;
+            if grid.task.is_none() {
+                return Ok(GRID_NO_JOB);
+            }
+            if len as usize > grid.max_result_bytes {
+                return Ok(GRID_TOO_LARGE);
+            }
+            let bytes = read_guest(&mut caller, ptr, len)?;
+            Ok(caller.data_mut().grid.set_result(&bytes))
+        },
+    )?;
+    // grid_result_ref(name, name_len, ref, ref_len) -> 0; names an output stored elsewhere, e.g. a blob,
+    // replacing an earlier ref of that name
+    linker.func_wrap(
+        "voike",
+        "grid_result_ref",
+        |mut caller: Caller<'_, StoreState>, name_ptr: u32, name_len: u32, ref_ptr: u32, ref_len: u32| -> wasmtime::Result<i32> {
+            if caller.data().grid.task.is_none() {
+                return Ok(GRID_NO_JOB);
+            }
+            if name_len == 0 || name_len > MAX_REF_NAME_BYTES || ref_len > MAX_REF_BYTES {
+                return Ok(GRID_TOO_LARGE);
+            }
+            let name = String::from_utf8_lossy(&read_guest(&mut caller, name_ptr, name_len)?).into_owned();
+            let reference = String::from_utf8_lossy(&read_guest(&mut caller, ref_ptr, ref_len)?).into_owned();
+            Ok(caller.data_mut().grid.set_ref(name, reference))
+        },
+    )?;
+    Ok(())
+}
+
+/// Jobs leased and not yet reported.
+#[derive(Default)]
+struct Worker {
+    running: AtomicUsize,
+    finished: Notify,
+}
+
+/// Holds one of the worker's `grid.concurrency` slots.
+struct Slot(Arc<Worker>);
+
+impl Slot {
+    fn take(worker: &Arc<Worker>) -> Self {
+        worker.running.fetch_add(1, Ordering::SeqCst);
+        Self(worker.clone())
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.running.fetch_sub(1, Ordering::SeqCst);
+        self.0.finished.notify_one();
+    }
+}
+
+/// Where a leased job is reported, fixed when it is leased so a reload cannot redirect it.
+#[derive(Clone)]
+struct Lease {
+    control_plane: ControlPlaneConfig,
+    node_id: String,
+    job_id: String,
+    length: Duration,
+}
+
+impl Lease {
+    async fn post(&self, action: &str, body: &impl Serialize) -> Result<Value, CallError> {
+        let path = format!("/grid/jobs/{}/{}", control_plane::path_segment(&self.job_id), action);
+        control_plane::call(&self.control_plane, warp::http::Method::POST, &path, Some(body)).await
+    }
+}
+
+/// Leases jobs of `grid.kinds` from the control plane while slots are free, runs them and reports
+/// back. It asks again as soon as a job finishes, every `grid.poll_interval_ms` while nothing is
+/// on offer, and backs off while the control plane is unreachable. Nothing new is leased once the
+/// engine is draining.
+pub async fn run(runtime: Arc<WasmRuntime>) {
+    let mut config = runtime.config.subscribe();
+    let worker = Arc::new(Worker::default());
+    let mut failures = 0u32;
+    loop {
+        if runtime.is_draining() {
+            return;
+        }
+        let current = runtime.config.get();
+        let free = current.grid.concurrency.saturating_sub(worker.running.load(Ordering::SeqCst));
+        let wait = if !current.grid.enabled() || !current.control_plane.enabled() || free == 0 {
+            None
+        } else {
+            let poll = Duration::from_millis(current.grid.poll_interval_ms);
+            match lease(&runtime, &current, free).await {
+                Ok(jobs) => {
+                    if failures > 0 {
+                        println!("[uor-engine] leasing grid jobs from {} again", current.control_plane.url);
+                    }
+                    failures = 0;
+                    let leased = !jobs.is_empty();
+                    for job in jobs {
+                        tokio::spawn(work(runtime.clone(), Slot::take(&worker), current.clone(), job));
+                    }
+                    Some(if leased { Duration::ZERO } else { poll })
+                }
+                Err(err) => {
+                    if failures == 0 {
+                        eprintln!("[uor-engine] grid lease from {} failed, retrying with backoff: {}", current.control_plane.url, err);
+                    }
+                    let wait = poll.saturating_mul(1 << failures.min(16)).min(Duration::from_millis(current.node.max_backoff_ms));
+                    failures = failures.saturating_add(1);
+                    Some(wait)
+                }
+            }
+        };
+        tokio::select! {
+            _ = time::sleep(wait.unwrap_or_default()), if wait.is_some() => {}
+            _ = worker.finished.notified() => {}
+            changed = config.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn lease(runtime: &WasmRuntime, config: &EngineConfig, free: usize) -> Result<Vec<GridJob>, CallError> {
+    let loaded = runtime.registry.lock().map(|registry| registry.names()).unwrap_or_default();
+    let (kinds, tasks) = config.grid.leasable(&loaded);
+    if kinds.is_empty() {
+        return Ok(Vec::new());
+    }
+    let request = LeaseRequest {
+        node_id: mesh::node_id(config),
+        kinds,
+        tasks,
+        max: free,
+        lease_ms: config.grid.lease_ms,
+    };
+    let response: Option<LeaseResponse> = control_plane::call(&config.control_plane, warp::http::Method::POST, "/grid/jobs/lease", Some(&request)).await?;
+    Ok(response.map(|response| response.jobs).unwrap_or_default())
+}
+
+/// Runs one leased job, renewing its lease until the guest finishes. A lease the control plane
+/// refuses to renew, or that runs out while it is unreachable, cancels the guest: the job has been
+/// or will be handed to another node, so nothing is reported.
+async fn work(runtime: Arc<WasmRuntime>, _slot: Slot, config: Arc<EngineConfig>, job: GridJob) {
+    let lease = Lease {
+        control_plane: config.control_plane.clone(),
+        node_id: mesh::node_id(&config),
+        job_id: job.job_id.clone(),
+        length: Duration::from_millis(job.lease_ms.unwrap_or(config.grid.lease_ms)),
+    };
+    let mut expires = Instant::now() + lease.length;
+    let handler = match config.grid.handler(&job) {
+        Ok(handler) => handler.clone(),
+        Err(message) => return fail(&lease, expires, None, InvokeError::new("no_handler", message)).await,
+    };
+    if runtime.is_draining() {
+        return fail(&lease, expires, None, InvokeError::new("draining", "node is shutting down")).await;
+    }
+    let Some(loaded) = runtime.registry.lock().ok().and_then(|mut registry| registry.checkout(&handler.module)) else {
+        let message = format!("module `{}` is not loaded on this node", handler.module);
+        return fail(&lease, expires, None, InvokeError::new("unknown_module", message)).await;
+    };
+
+    let invocation_id = runtime.invocation_id();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let progress = Arc::new(Mutex::new(Progress::default()));
+    let invocation = Invocation {
+        id: invocation_id.clone(),
+        loaded,
+        export: handler.export,
+        args: Vec::new(),
+        limits: runtime.limits().with_overrides(&handler.limits),
+        caller: format!("grid:{}", job.job_id),
+        project: job.project_id.clone(),
+        cancel: Some(cancel.clone()),
+        http: None,
+        grid: Some(GridTask { job, progress: progress.clone() }),
+    };
+    let guest = invocations::run(runtime.clone(), invocation);
+    tokio::pin!(guest);
+    let mut renew_at = Some(Instant::now() + lease.length / 3);
+    let mut lost = None;
+    let outcome = loop {
+        tokio::select! {
+            outcome = &mut guest => break outcome,
+            _ = time::sleep_until(renew_at.unwrap_or_else(Instant::now)), if renew_at.is_some() => {
+                let renewal = Renewal {
+                    node_id: lease.node_id.clone(),
+                    lease_ms: lease.length.as_millis() as u64,
+                    progress: progress.lock().map(|progress| progress.clone()).unwrap_or_default(),
+                };
+                let now = Instant::now();
+                match lease.post("heartbeat", &renewal).await {
+                    Ok(_) => expires = now + lease.length,
+                    Err(err) if err.status.is_some_and(|status| status.is_client_error()) => lost = Some(err.message),
+                    Err(err) if Instant::now() >= expires => lost = Some(format!("lease expired: {}", err)),
+                    Err(_) => {}
+                }
+                if lost.is_some() {
+                    cancel.store(true, Ordering::SeqCst);
+                    renew_at = None;
+                } else {
+                    renew_at = Some(Instant::now() + lease.length / 3);
+                }
+            }
+        }
+    };
+    if let Some(reason) = lost {
+        eprintln!("[uor-engine] abandoned grid job {} (invocation {}): {}", lease.job_id, invocation_id, reason);
+        return;
+    }
+    match outcome {
+        Ok(result) => complete(&lease, expires, &config, result).await,
+        Err(err) => fail(&lease, expires, Some(invocation_id), err).await,
+    }
+}
+
+async fn complete(lease: &Lease, expires: Instant, config: &EngineConfig, mut result: InvokeResult) {
+    let status = result.results.first().and_then(Value::as_i64).filter(|status| *status != 0);
+    if let Some(status) = status.or(result.exit_code.filter(|code| *code != 0).map(i64::from)) {
+        let err = InvokeError::new("guest_failed", format!("`{}` returned {}", result.export, status));
+        let report = failure(lease, Some(result.invocation_id.clone()), err, result.logs);
+        return send(lease, expires, "fail", &report).await;
+    }
+    let output = result.grid.take().unwrap_or_default();
+    let mut result_refs = output.refs;
+    let invocation = match mesh::addresses(config).get("http") {
+        Some(base) => format!("{}/invocations/{}", base.trim_end_matches('/'), result.invocation_id),
+        None => result.invocation_id.clone(),
+    };
+    result_refs.insert("invocation".to_string(), invocation);
+    let report = Report {
+        node_id: lease.node_id.clone(),
+        invocation_id: Some(result.invocation_id.clone()),
+        result: Some(output.result.unwrap_or_else(|| serde_json::json!({ "results": result.results }))),
+        result_refs,
+        error: None,
+        message: None,
+        retry: None,
+        logs: result.logs,
+    };
+    send(lease, expires, "complete", &report).await;
+}
+
+async fn fail(lease: &Lease, expires: Instant, invocation_id: Option<String>, mut err: InvokeError) {
+    let logs = err.invocation.take().map(|failed| failed.logs).unwrap_or_default();
+    let report = failure(lease, invocation_id, err, logs);
+    send(lease, expires, "fail", &report).await;
+}
+
+fn failure(lease: &Lease, invocation_id: Option<String>, err: InvokeError, logs: Vec<LogRecord>) -> Report {
+    Report {
+        node_id: lease.node_id.clone(),
+        invocation_id,
+        result: None,
+        result_refs: BTreeMap::new(),
+        retry: Some(RETRYABLE.contains(&err.error)),
+        error: Some(err.error),
+        message: Some(err.message),
+        logs,
+    }
+}
+
+/// Delivers a report, retrying until the lease expires; after that the control plane retries the
+/// job on its own.
+async fn send(lease: &Lease, expires: Instant, action: &str, report: &Report) {
+    let mut wait = Duration::from_secs(1);
+    loop {
+        let err = match lease.post(action, report).await {
+            Ok(_) => {
+                println!("[uor-engine] grid job {} {}", lease.job_id, if action == "complete" { "completed" } else { "failed" });
+                return;
+            }
+            Err(err) => err,
+        };
+        let remaining = expires.saturating_duration_since(Instant::now());
+        if err.status.is_some_and(|status| status.is_client_error()) || remaining.is_zero() {
+            eprintln!("[uor-engine] could not report grid job {}: {}", lease.job_id, err);
+            return;
+        }
+        time::sleep(wait.min(remaining)).await;
+        wait = wait.saturating_mul(2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{limits::ExecutionLimits, mesh::NodeConfig};
+    use std::net::SocketAddr;
+    use warp::{
+        http::{Method, StatusCode},
+        hyper::body::Bytes,
+        Filter,
+    };
+
+    const ECHO: &str = r#"(module
+        (import "voike" "grid_result" (func $result (param i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 0) "{\"ok\":true}")
+        (func (export "run") (result i32) (drop (call $result (i32.const 0) (i32.const 11))) i32.const 0))"#;
+
+    const SPIN: &str = r#"(module (func (export "run") (result i32) (loop $again br $again) i32.const 0))"#;
+
+    /// Control plane that hands out `jobs` once, answers heartbeats with `heartbeat` and records
+    /// every request.
+    fn control_plane(jobs: Vec<Value>, heartbeat: StatusCode) -> (SocketAddr, Arc<Mutex<Vec<Value>>>) {
+        let seen: Arc<Mutex<Vec<Value>>> = Arc::default();
+        let record = seen.clone();
+        let jobs = Arc::new(Mutex::new(jobs));
+        let mock = warp::method()
+            .and(warp::path::full())
+            .and(warp::header::optional::<String>("x-voike-admin-token"))
+            .and(warp::body::bytes())
+            .map(move |method: Method, path: warp::path::FullPath, token: Option<String>, body: Bytes| {
+                let body = serde_json::from_slice::<Value>(&body).unwrap_or(Value::Null);
+                let request = serde_json::json!({ "method": method.as_str(), "path": path.as_str(), "token": token, "body": body });
+                record.lock().unwrap().push(request);
+                let (reply, status) = match path.as_str() {
+                    "/grid/jobs/lease" => (serde_json::json!({ "jobs": std::mem::take(&mut *jobs.lock().unwrap()) }), StatusCode::OK),
+                    path if path.ends_with("/heartbeat") => (serde_json::json!({ "error": "Lease lost" }), heartbeat),
+                    _ => (serde_json::json!({ "ok": true }), StatusCode::OK),
+                };
+                warp::reply::with_status(warp::reply::json(&reply), status)
+            });
+        let (addr, server) = warp::serve(mock).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (addr, seen)
+    }
+
+    fn worker_config(addr: SocketAddr) -> EngineConfig {
+        EngineConfig {
+            limits: ExecutionLimits {
+                fuel: u64::MAX,
+                ..ExecutionLimits::default()
+            },
+            control_plane: ControlPlaneConfig {
+                url: format!("http://{}", addr),
+                admin_token: Some("secret".to_string()),
+                ..ControlPlaneConfig::default()
+            },
+            node: NodeConfig {
+                id: "edge-1".to_string(),
+                ..NodeConfig::default()
+            },
+            grid: GridConfig {
+                kinds: vec![CUSTOM_KIND.to_string()],
+                tasks: BTreeMap::from([("echo".to_string(), handler("echo")), ("spin".to_string(), handler("spin"))]),
+                poll_interval_ms: 100,
+                ..GridConfig::default()
+            },
+            ..EngineConfig::default()
+        }
+    }
+
+    fn handler(module: &str) -> GridHandler {
+        GridHandler {
+            module: module.to_string(),
+            ..GridHandler::default()
+        }
+    }
+
+    fn job(kind: &str, params: Value) -> GridJob {
+        serde_json::from_value(serde_json::json!({ "jobId": "j1", "type": kind, "params": params })).unwrap()
+    }
+
+    #[test]
+    fn maps_kinds_and_custom_tasks_to_loaded_modules() {
+        let grid = GridConfig {
+            kinds: vec!["llm.infer".to_string(), "custom".to_string()],
+            handlers: BTreeMap::from([("llm.infer".to_string(), handler("llm"))]),
+            tasks: BTreeMap::from([("fib".to_string(), handler("fib")), ("sort".to_string(), handler("sort"))]),
+            ..GridConfig::default()
+        };
+        grid.validate().unwrap();
+        assert_eq!(grid.handler(&job("llm.infer", Value::Null)).unwrap().module, "llm");
+        assert_eq!(grid.handler(&job("custom", serde_json::json!({ "task": "fib", "n": 10 }))).unwrap().module, "fib");
+        assert!(grid.handler(&job("custom", serde_json::json!({ "task": "other" }))).is_err());
+        assert!(grid.handler(&job("media.transcode", Value::Null)).is_err());
+        assert_eq!(job("custom", Value::Null).attempt, 1);
+
+        let loaded = |names: &[&str]| grid.leasable(&names.iter().map(|name| name.to_string()).collect::<Vec<_>>());
+        assert_eq!(loaded(&["fib"]), (vec!["custom".to_string()], vec!["fib".to_string()]));
+        assert_eq!(loaded(&["llm"]), (vec!["llm.infer".to_string()], Vec::new()));
+
+        let unhandled = GridConfig {
+            kinds: vec!["media.transcode".to_string()],
+            ..grid
+        };
+        assert!(unhandled.validate().is_err());
+    }
+
+    #[test]
+    fn keeps_progress_and_validates_results() {
+        let task = GridTask {
+            job: job("custom", serde_json::json!({ "task": "fib" })),
+            progress: Arc::default(),
+        };
+        let progress = task.progress.clone();
+        let mut grid = GridExchange::new(Some(task), 16);
+        grid.set_progress(150.0, Some("almost".to_string()));
+        assert_eq!(progress.lock().unwrap().percent, Some(100.0));
+        assert_eq!(grid.set_result(b"not json"), GRID_BAD_RESULT);
+        assert_eq!(grid.set_result(b"{\"fib\":\"55\",\"padding\":true}"), GRID_TOO_LARGE);
+        assert_eq!(grid.set_result(b"{\"fib\":\"55\"}"), 0);
+        for n in 0..MAX_RESULT_REFS {
+            assert_eq!(grid.set_ref(format!("part-{}", n), "blob://x".to_string()), 0);
+        }
+        assert_eq!(grid.set_ref("one-more".to_string(), "blob://y".to_string()), GRID_TOO_LARGE);
+        assert_eq!(grid.set_ref("part-0".to_string(), "blob://z".to_string()), 0);
+        let output = grid.finish().unwrap();
+        assert_eq!(output.result, Some(serde_json::json!({ "fib": "55" })));
+        assert_eq!((output.refs.len(), output.refs["part-0"].as_str()), (MAX_RESULT_REFS, "blob://z"));
+        assert!(GridExchange::new(None, 16).finish().is_none());
+    }
+
+    #[tokio::test]
+    async fn leases_runs_and_completes_a_job() {
+        let leased = serde_json::json!({ "jobId": "job 1", "projectId": "acme", "type": "custom", "params": { "task": "echo" }, "leaseMs": 60_000 });
+        let (addr, seen) = control_plane(vec![leased], StatusCode::OK);
+        let runtime = WasmRuntime::for_tests(worker_config(addr));
+        runtime.load_wat("echo", ECHO, None);
+
+        let worker = tokio::spawn(run(runtime.clone()));
+        let reported = |seen: &[Value]| seen.iter().any(|request| request["path"].as_str().is_some_and(|path| path.ends_with("/complete")));
+        for _ in 0..100 {
+            if reported(&seen.lock().unwrap()) {
+                break;
+            }
+            time::sleep(Duration::from_millis(20)).await;
+        }
+        worker.abort();
+        let _ = worker.await;
+
+        let seen = seen.lock().unwrap();
+        assert!(seen.iter().all(|request| request["method"] == "POST" && request["token"] == "secret"));
+        let lease = &seen[0];
+        assert_eq!(lease["path"], "/grid/jobs/lease");
+        assert_eq!(
+            lease["body"],
+            serde_json::json!({ "nodeId": "edge-1", "kinds": ["custom"], "tasks": ["echo"], "max": 2, "leaseMs": 30_000 })
+        );
+        let complete = seen.iter().find(|request| request["path"] == "/grid/jobs/job%201/complete").expect("job reported");
+        assert_eq!((&complete["body"]["nodeId"], &complete["body"]["result"]), (&Value::from("edge-1"), &serde_json::json!({ "ok": true })));
+        let invocation = complete["body"]["invocationId"].as_str().unwrap();
+        let reference = complete["body"]["resultRefs"]["invocation"].as_str().unwrap();
+        assert!(reference.ends_with(&format!("/invocations/{}", invocation)));
+        assert!(runtime.logs.get(invocation).is_some());
+        assert!(!seen.iter().any(|request| request["path"].as_str().is_some_and(|path| path.ends_with("/fail"))));
+    }
+
+    #[tokio::test]
+    async fn abandons_a_job_whose_lease_is_lost() {
+        let (addr, seen) = control_plane(Vec::new(), StatusCode::CONFLICT);
+        let config = worker_config(addr);
+        let runtime = WasmRuntime::for_tests(config.clone());
+        runtime.load_wat("spin", SPIN, None);
+        let leased = job(CUSTOM_KIND, serde_json::json!({ "task": "spin" }));
+        let leased = GridJob { lease_ms: Some(300), ..leased };
+
+        let slots = Arc::new(Worker::default());
+        let started = Instant::now();
+        time::timeout(Duration::from_secs(4), work(runtime.clone(), Slot::take(&slots), Arc::new(config), leased)).await.expect("guest cancelled");
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(slots.running.load(Ordering::SeqCst), 0);
+
+        let seen = seen.lock().unwrap();
+        let paths: Vec<&str> = seen.iter().filter_map(|request| request["path"].as_str()).collect();
+        assert_eq!(paths, ["/grid/jobs/j1/heartbeat"]);
+        assert_eq!(seen[0]["body"], serde_json::json!({ "nodeId": "edge-1", "leaseMs": 300 }));
+    }
+}
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::{status::SharedState, wasm::WasmRuntime};
+
+/// Shortest gap between samples that still counts as a stalled sampler.
+const MIN_STALL_MS: u64 = 5_000;
+
+/// Resource ceilings past which `/readyz` reports the node as not ready.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceBudget {
+    /// Engine process RSS; unlimited when unset.
+    pub max_rss_mb: Option<f32>,
+    pub max_memory_percent: f32,
+    pub max_queued_invocations: u32,
+}
+
+impl Default for ResourceBudget {
+    fn default() -> Self {
+        Self {
+            max_rss_mb: None,
+            max_memory_percent: 95.0,
+            max_queued_invocations: 64,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Check {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Check {
+    fn pass() -> Self {
+        Self { ok: true, reason: None }
+    }
+
+    fn fail(reason: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            reason: Some(reason.into()),
+        }
+    }
+}
+
+/// Same top-level `status` as the control plane's `/health`, plus per-check detail.
+#[derive(Serialize)]
+pub struct HealthReport {
+    pub status: &'static str,
+    pub checks: BTreeMap<&'static str, Check>,
+    pub reasons: Vec<String>,
+    pub uptime_seconds: u64,
+    pub sleep_state: String,
+}
+
+impl HealthReport {
+    fn new(checks: BTreeMap<&'static str, Check>, uptime_seconds: u64, sleep_state: String) -> Self {
+        let reasons: Vec<String> = checks
+            .iter()
+            .filter_map(|(name, check)| check.reason.as_ref().map(|reason| format!("{}: {}", name, reason)))
+            .collect();
+        Self {
+            status: if reasons.is_empty() { "ok" } else { "error" },
+            checks,
+            reasons,
+            uptime_seconds,
+            sleep_state,
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.reasons.is_empty()
+    }
+}
+
+/// Liveness: the process answers and the sampler is still producing samples.
+pub fn liveness(state: &Arc<Mutex<SharedState>>, runtime: &WasmRuntime) -> HealthReport {
+    let mut checks = BTreeMap::new();
+    checks.insert("process", Check::pass());
+    let Ok(guard) = state.lock() else {
+        checks.insert("sampler", Check::fail("status state is poisoned"));
+        return HealthReport::new(checks, 0, String::new());
+    };
+    let stall_ms = (runtime.config.get().sampling.max_period_ms * 2).max(MIN_STALL_MS);
+    let age_ms = Instant::now().saturating_duration_since(guard.sampled_at).as_millis() as u64;
+    let sampler = if age_ms > stall_ms {
+        Check::fail(format!("no sample for {} ms (limit {} ms)", age_ms, stall_ms))
+    } else {
+        Check::pass()
+    };
+    checks.insert("sampler", sampler);
+    HealthReport::new(checks, guard.status.uptime_seconds, guard.status.sleep_state.clone())
+}
+
+/// Readiness: preloads succeeded, storage is usable, the node is not draining and is within budget.
+pub fn readiness(state: &Arc<Mutex<SharedState>>, runtime: &WasmRuntime) -> HealthReport {
+    let config = runtime.config.get();
+    let mut checks = BTreeMap::new();
+
+    let failures = runtime.preload_failures();
+    checks.insert(
+        "modules",
+        if failures.is_empty() {
+            Check::pass()
+        } else {
+            Check::fail(failures.iter().map(|(path, err)| format!("{} failed to load: {}", path.display(), err)).collect::<Vec<_>>().join("; "))
+        },
+    );
+
+    let mut storage = Vec::new();
+    let cache_dir = &config.storage.cache_dir;
+    if !cache_dir.as_os_str().is_empty() && !runtime.cache.dir().is_some_and(|dir| dir.is_dir()) {
+        storage.push(format!("module cache directory {} is unavailable", cache_dir.display()));
+    }
+    if let Some(err) = &runtime.kv_error {
+        storage.push(format!("key-value store is unavailable: {}", err));
+    }
+    if let Some(err) = &runtime.schedules.error {
+        storage.push(format!("schedules are unavailable: {}", err));
+    }
+    checks.insert("storage", if storage.is_empty() { Check::pass() } else { Check::fail(storage.join("; ")) });
+
+    checks.insert(
+        "draining",
+        if runtime.is_draining() { Check::fail("engine is shutting down") } else { Check::pass() },
+    );
+
+    let Ok(guard) = state.lock() else {
+        checks.insert("budget", Check::fail("status state is poisoned"));
+        return HealthReport::new(checks, 0, String::new());
+    };
+    let status = &guard.status;
+    let budget = &config.budget;
+    let mut over = Vec::new();
+    if let Some(max) = budget.max_rss_mb.filter(|max| status.rss_mb > *max) {
+        over.push(format!("rss_mb {:.1} > {}", status.rss_mb, max));
+    }
+    if status.memory_percent > budget.max_memory_percent {
+        over.push(format!("memory_percent {:.1} > {}", status.memory_percent, budget.max_memory_percent));
+    }
+    let queued = runtime.in_flight() as u32;
+    if queued > budget.max_queued_invocations {
+        over.push(format!("queued_invocations {} > {}", queued, budget.max_queued_invocations));
+    }
+    checks.insert("budget", if over.is_empty() { Check::pass() } else { Check::fail(over.join(", ")) });
+    HealthReport::new(checks, status.uptime_seconds, status.sleep_state.clone())
+}
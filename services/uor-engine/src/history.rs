@@ -0,0 +1,263 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::status::RuntimeStatus;
+
+/// Numeric status fields kept per sample, in `Sample::values` order.
+pub const FIELDS: [&str; 6] = ["cpu_percent", "rss_mb", "threads", "open_fds", "host_cpu_percent", "host_used_memory_mb"];
+
+/// Default number of samples retained: an hour while the sampler stays at its 500 ms minimum
+/// period, and longer once it backs off while warm or idle.
+pub const DEFAULT_CAPACITY: usize = 7_200;
+
+/// Most points a single query may return before `step` is widened.
+const MAX_POINTS: i64 = 1_000;
+
+#[derive(Clone)]
+pub struct Sample {
+    at_ms: i64,
+    values: [f32; FIELDS.len()],
+    sleep_state: String,
+    sleep_reason: String,
+}
+
+impl Sample {
+    pub fn from_status(status: &RuntimeStatus, at: DateTime<Utc>) -> Self {
+        Self {
+            at_ms: at.timestamp_millis(),
+            values: [
+                status.cpu_percent,
+                status.rss_mb,
+                status.threads as f32,
+                status.open_fds as f32,
+                status.host_cpu_percent,
+                status.host_used_memory_mb,
+            ],
+            sleep_state: status.sleep_state.clone(),
+            sleep_reason: status.sleep_reason.clone(),
+        }
+    }
+}
+
+/// Bounded ring buffer of status samples.
+pub struct StatusHistory {
+    samples: VecDeque<Sample>,
+    capacity: usize,
+}
+
+/// `GET /status/history` parameters. `since` is RFC 3339 or unix seconds;
+/// `window` and `step` are durations such as `90`, `30s`, `5m` or `1h`.
+#[derive(Deserialize, Default)]
+pub struct HistoryQuery {
+    pub since: Option<String>,
+    pub window: Option<String>,
+    pub step: Option<String>,
+}
+
+#[derive(Serialize, Default, Clone, Copy)]
+pub struct Stat {
+    pub min: f32,
+    pub avg: f32,
+    pub max: f32,
+}
+
+#[derive(Serialize)]
+pub struct Point {
+    pub t: String,
+    pub samples: usize,
+    /// Sleep state of the last sample in the step.
+    pub sleep_state: String,
+    /// Classifier reason for that sample's state.
+    pub sleep_reason: String,
+    #[serde(flatten)]
+    pub stats: BTreeMap<&'static str, Stat>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryResponse {
+    pub from: String,
+    pub to: String,
+    pub step_seconds: f64,
+    pub points: Vec<Point>,
+}
+
+impl StatusHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity.min(DEFAULT_CAPACITY)),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Changes the retention, dropping the oldest samples if it shrinks.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        while self.samples.len() > self.capacity {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn push(&mut self, sample: Sample) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Downsamples the samples in `[from, to]` into `step`-wide min/avg/max points.
+    pub fn query(&self, query: &HistoryQuery, now: DateTime<Utc>) -> Result<HistoryResponse, String> {
+        let to_ms = now.timestamp_millis();
+        let window_ms = match &query.window {
+            Some(window) => parse_duration_ms(window)?,
+            None => 300_000,
+        };
+        let from_ms = match &query.since {
+            Some(since) => parse_since_ms(since)?,
+            None => to_ms.saturating_sub(window_ms),
+        };
+        if from_ms > to_ms {
+            return Err("`since` is in the future".to_string());
+        }
+        // No sample predates the unix epoch; clamping keeps the bucket arithmetic in range.
+        let from_ms = from_ms.max(0);
+        let span_ms = to_ms.saturating_sub(from_ms).max(1);
+        let requested_step = match &query.step {
+            Some(step) => parse_duration_ms(step)?,
+            None => span_ms / 60,
+        };
+        let step_ms = requested_step.max(span_ms / MAX_POINTS).max(1);
+
+        let mut buckets: BTreeMap<i64, (usize, [Stat; FIELDS.len()], &Sample)> = BTreeMap::new();
+        for sample in self.samples.iter().filter(|s| s.at_ms >= from_ms && s.at_ms <= to_ms) {
+            let bucket = from_ms + (sample.at_ms - from_ms) / step_ms * step_ms;
+            let entry = buckets.entry(bucket).or_insert_with(|| (0, [Stat::default(); FIELDS.len()], sample));
+            for (stat, value) in entry.1.iter_mut().zip(sample.values) {
+                if entry.0 == 0 {
+                    *stat = Stat {
+                        min: value,
+                        avg: value,
+                        max: value,
+                    };
+                } else {
+                    stat.min = stat.min.min(value);
+                    stat.max = stat.max.max(value);
+                    stat.avg += (value - stat.avg) / (entry.0 + 1) as f32;
+                }
+            }
+            entry.0 += 1;
+            entry.2 = sample;
+        }
+
+        Ok(HistoryResponse {
+            from: rfc3339(from_ms),
+            to: rfc3339(to_ms),
+            step_seconds: step_ms as f64 / 1000.0,
+            points: buckets
+                .into_iter()
+                .map(|(bucket, (samples, stats, last))| Point {
+                    t: rfc3339(bucket),
+                    samples,
+                    sleep_state: last.sleep_state.clone(),
+                    sleep_reason: last.sleep_reason.clone(),
+                    stats: FIELDS.iter().copied().zip(stats).collect(),
+                })
+                .collect(),
+        })
+    }
+}
+
+fn rfc3339(ms: i64) -> String {
+    Utc.timestamp_millis_opt(ms).single().unwrap_or_default().to_rfc3339()
+}
+
+fn parse_since_ms(value: &str) -> Result<i64, String> {
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Ok((seconds * 1000.0) as i64);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|at| at.timestamp_millis())
+        .map_err(|_| format!("invalid `since` value `{}`", value))
+}
+
+fn parse_duration_ms(value: &str) -> Result<i64, String> {
+    let (number, unit) = match value.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(idx) => value.split_at(idx),
+        None => (value, "s"),
+    };
+    let scale = match unit {
+        "ms" => 1.0,
+        "s" => 1_000.0,
+        "m" => 60_000.0,
+        "h" => 3_600_000.0,
+        _ => return Err(format!("invalid duration `{}`", value)),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(n) if n > 0.0 => Ok((n * scale) as i64),
+        _ => Err(format!("invalid duration `{}`", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_at(ms: i64, cpu: f32) -> Sample {
+        let status = RuntimeStatus {
+            cpu_percent: cpu,
+            sleep_state: "idle".to_string(),
+            ..RuntimeStatus::default()
+        };
+        Sample::from_status(&status, Utc.timestamp_millis_opt(ms).unwrap())
+    }
+
+    #[test]
+    fn downsamples_into_min_avg_max_steps() {
+        let mut history = StatusHistory::new(16);
+        for (idx, cpu) in [1.0, 3.0, 10.0, 20.0].into_iter().enumerate() {
+            history.push(sample_at(idx as i64 * 500, cpu));
+        }
+        let query = HistoryQuery {
+            since: Some("0".to_string()),
+            step: Some("1s".to_string()),
+            window: None,
+        };
+        let response = history.query(&query, Utc.timestamp_millis_opt(2_000).unwrap()).unwrap();
+        assert_eq!(response.points.len(), 2);
+        let first = response.points[0].stats["cpu_percent"];
+        assert_eq!((first.min, first.avg, first.max), (1.0, 2.0, 3.0));
+        let second = response.points[1].stats["cpu_percent"];
+        assert_eq!((second.min, second.avg, second.max), (10.0, 15.0, 20.0));
+    }
+
+    #[test]
+    fn clamps_extreme_ranges() {
+        let mut history = StatusHistory::new(4);
+        history.push(sample_at(1_000, 5.0));
+        let now = Utc.timestamp_millis_opt(2_000).unwrap();
+        for (since, window) in [(Some("-1e30"), None), (None, Some("9999999999999h"))] {
+            let query = HistoryQuery {
+                since: since.map(str::to_string),
+                window: window.map(str::to_string),
+                step: None,
+            };
+            let response = history.query(&query, now).unwrap();
+            assert_eq!(response.points.iter().map(|point| point.samples).sum::<usize>(), 1);
+        }
+    }
+
+    #[test]
+    fn drops_oldest_sample_when_full() {
+        let mut history = StatusHistory::new(2);
+        for ms in [0, 500, 1_000] {
+            history.push(sample_at(ms, ms as f32));
+        }
+        let query = HistoryQuery {
+            since: Some("0".to_string()),
+            ..HistoryQuery::default()
+        };
+        let response = history.query(&query, Utc.timestamp_millis_opt(1_000).unwrap()).unwrap();
+        let total: usize = response.points.iter().map(|point| point.samples).sum();
+        assert_eq!(total, 2);
+    }
+}
@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::VecDeque,
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::Instant,
+};
+
+use crate::{
+    apps::HttpRequest,
+    grid::GridTask,
+    limits::{env_or, ExecutionLimits},
+    wasm::{self, InvokeError, InvokeResult, LoadedModule, WasmRuntime},
+};
+
+/// Records returned by `GET /invocations` when no `limit` is given.
+const DEFAULT_QUERY_LIMIT: usize = 50;
+
+/// `[invocations]` config section.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InvocationsConfig {
+    /// Finished invocations kept for `GET /invocations`.
+    pub history_size: usize,
+}
+
+impl Default for InvocationsConfig {
+    fn default() -> Self {
+        Self { history_size: 1000 }
+    }
+}
+
+impl InvocationsConfig {
+    /// Applies `UOR_INVOCATION_HISTORY`.
+    pub fn apply_env(&mut self) {
+        self.history_size = env_or("UOR_INVOCATION_HISTORY", self.history_size);
+    }
+}
+
+/// What ran, for whom, and how it ended.
+#[derive(Clone, Serialize)]
+pub struct InvocationRecord {
+    pub id: String,
+    pub module: String,
+    pub export: String,
+    /// Project the call was made for, or the module's owner when the caller had none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// Project owning the module; `None` for shared modules.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// `admin` or `project:<id>` for API calls, `job:<id>` for queued jobs.
+    pub caller: String,
+    pub started_at: String,
+    pub ended_at: String,
+    pub wall_ms: f64,
+    pub fuel_consumed: u64,
+    pub peak_memory_bytes: usize,
+    /// `ok` or `error`.
+    pub status: &'static str,
+    /// `ok`, or the error kind such as `trap`, `timeout` or `fuel_exhausted`.
+    pub outcome: &'static str,
+    /// Trap or error message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+/// `GET /invocations` filters.
+#[derive(Deserialize, Default)]
+pub struct InvocationQuery {
+    pub module: Option<String>,
+    /// `ok`, `error`, or a specific outcome.
+    pub status: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl InvocationQuery {
+    fn matches(&self, record: &InvocationRecord) -> bool {
+        self.module.as_ref().is_none_or(|module| *module == record.module)
+            && self.status.as_ref().is_none_or(|status| status == record.status || status == record.outcome)
+    }
+}
+
+/// The most recent finished invocations, oldest evicted first.
+#[derive(Default)]
+pub struct InvocationHistory {
+    records: Mutex<VecDeque<InvocationRecord>>,
+}
+
+impl InvocationHistory {
+    pub fn push(&self, record: InvocationRecord, capacity: usize) {
+        let Ok(mut records) = self.records.lock() else { return };
+        records.push_back(record);
+        while records.len() > capacity {
+            records.pop_front();
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<InvocationRecord> {
+        let records = self.records.lock().ok()?;
+        records.iter().rev().find(|record| record.id == id).cloned()
+    }
+
+    /// Newest first, restricted to records `visible` accepts.
+    pub fn query(&self, query: &InvocationQuery, visible: impl Fn(&InvocationRecord) -> bool) -> Vec<InvocationRecord> {
+        let Ok(records) = self.records.lock() else {
+            return Vec::new();
+        };
+        records
+            .iter()
+            .rev()
+            .filter(|record| visible(record) && query.matches(record))
+            .take(query.limit.unwrap_or(DEFAULT_QUERY_LIMIT))
+            .cloned()
+            .collect()
+    }
+}
+
+/// One call of a module export, from whichever entry point submitted it.
+pub struct Invocation {
+    pub id: String,
+    pub loaded: LoadedModule,
+    pub export: String,
+    pub args: Vec<Value>,
+    pub limits: ExecutionLimits,
+    /// Recorded as the invocation's `caller`.
+    pub caller: String,
+    /// Project the call is made for, if any; picks a shared module's key-value namespace.
+    pub project: Option<String>,
+    /// Stops the guest at the next epoch tick once set.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Request the guest reads through the `voike.http_*` imports, for `/apps` requests.
+    pub http: Option<HttpRequest>,
+    /// Job the guest reads through the `voike.grid_*` imports, for grid jobs.
+    pub grid: Option<GridTask>,
+}
+
+impl Invocation {
+    /// Project the call's history and logs belong to: the caller's, or else the module owner's.
+    pub fn recorded_project(&self) -> Option<String> {
+        self.project.clone().or_else(|| self.loaded.project.clone())
+    }
+}
+
+/// Runs an export on a blocking worker, counting it as in flight, and records the outcome in
+/// the metrics and the invocation history. Every path that invokes guest code goes through here.
+/// The guard and the recording live on the worker, so a guest whose caller went away still counts
+/// as in flight until it finishes and still lands in the metrics and history.
+pub async fn run(runtime: Arc<WasmRuntime>, invocation: Invocation) -> Result<InvokeResult, InvokeError> {
+    let (id, module, export) = (invocation.id.clone(), invocation.loaded.name.clone(), invocation.export.clone());
+    let (project, owner, caller) = (invocation.recorded_project(), invocation.loaded.project.clone(), invocation.caller.clone());
+    let started_at = chrono::Utc::now().to_rfc3339();
+    let started = Instant::now();
+    let in_flight = runtime.begin_invocation();
+    let worker = runtime.clone();
+    tokio::task::spawn_blocking(move || {
+        let _in_flight = in_flight;
+        let outcome = wasm::invoke(&worker, invocation);
+        let wall = started.elapsed();
+        worker.metrics.record_invocation(&module, wall.as_secs_f64(), outcome.as_ref().err());
+
+        let (fuel_consumed, peak_memory_bytes) = match &outcome {
+            Ok(result) => (result.fuel_consumed, result.peak_memory_bytes),
+            Err(err) => err.invocation.as_ref().map(|failed| (failed.fuel_consumed, failed.peak_memory_bytes)).unwrap_or_default(),
+        };
+        let record = InvocationRecord {
+            id,
+            module,
+            export,
+            project,
+            owner,
+            caller,
+            started_at,
+            ended_at: chrono::Utc::now().to_rfc3339(),
+            wall_ms: wall.as_secs_f64() * 1000.0,
+            fuel_consumed,
+            peak_memory_bytes,
+            status: if outcome.is_ok() { "ok" } else { "error" },
+            outcome: outcome.as_ref().err().map(|err| err.error).unwrap_or("ok"),
+            message: outcome.as_ref().err().map(|err| err.message.clone()),
+            exit_code: outcome.as_ref().ok().and_then(|result| result.exit_code),
+        };
+        worker.invocations.push(record, worker.config.get().invocations.history_size);
+        outcome
+    })
+    .await
+    .unwrap_or_else(|err| Err(InvokeError::new("internal", err.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, module: &str, outcome: &'static str) -> InvocationRecord {
+        InvocationRecord {
+            id: id.to_string(),
+            module: module.to_string(),
+            export: "run".to_string(),
+            project: None,
+            owner: None,
+            caller: "admin".to_string(),
+            started_at: String::new(),
+            ended_at: String::new(),
+            wall_ms: 0.0,
+            fuel_consumed: 0,
+            peak_memory_bytes: 0,
+            status: if outcome == "ok" { "ok" } else { "error" },
+            outcome,
+            message: None,
+            exit_code: None,
+        }
+    }
+
+    #[test]
+    fn filters_newest_first_within_capacity() {
+        let history = InvocationHistory::default();
+        history.push(record("1", "a", "ok"), 3);
+        history.push(record("2", "a", "trap"), 3);
+        history.push(record("3", "b", "timeout"), 3);
+        history.push(record("4", "a", "ok"), 3);
+        assert!(history.get("1").is_none());
+
+        let ids = |query: InvocationQuery| history.query(&query, |_| true).into_iter().map(|record| record.id).collect::<Vec<_>>();
+        assert_eq!(ids(InvocationQuery::default()), ["4", "3", "2"]);
+        let errors = InvocationQuery {
+            status: Some("error".to_string()),
+            ..InvocationQuery::default()
+        };
+        assert_eq!(ids(errors), ["3", "2"]);
+        let traps_in_a = InvocationQuery {
+            module: Some("a".to_string()),
+            status: Some("trap".to_string()),
+            limit: Some(1),
+        };
+        assert_eq!(ids(traps_in_a), ["2"]);
+    }
+
+    #[tokio::test]
+    async fn keeps_counting_and_records_after_the_caller_goes_away() {
+        let runtime = WasmRuntime::for_tests(crate::config::EngineConfig::default());
+        runtime.load_wat("spin", r#"(module (func (export "spin") (loop $again br $again)))"#, None);
+        let invocation = Invocation {
+            id: runtime.invocation_id(),
+            loaded: runtime.registry.lock().unwrap().checkout("spin").unwrap(),
+            export: "spin".to_string(),
+            args: Vec::new(),
+            limits: ExecutionLimits {
+                fuel: u64::MAX,
+                timeout_ms: 200,
+                ..ExecutionLimits::default()
+            },
+            caller: "admin".to_string(),
+            project: None,
+            cancel: None,
+            http: None,
+            grid: None,
+        };
+        let id = invocation.id.clone();
+        let request = tokio::spawn(run(runtime.clone(), invocation));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        request.abort();
+        let _ = request.await;
+        assert_eq!(runtime.in_flight(), 1);
+        assert!(runtime.invocations.get(&id).is_none());
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), runtime.wait_idle()).await.unwrap();
+        assert_eq!(runtime.invocations.get(&id).map(|record| record.outcome), Some("timeout"));
+    }
+}
@@ -0,0 +1,524 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::Notify;
+
+use crate::{
+    auth::Caller,
+    invocations::{self, Invocation},
+    limits::{env_or, ExecutionLimits, LimitOverrides},
+    wasm::{InvokeError, WasmRuntime},
+};
+
+/// Lanes in priority order: the dispatcher fills free interactive slots first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lane {
+    Interactive,
+    #[default]
+    Batch,
+    Background,
+}
+
+impl Lane {
+    pub const ALL: [Lane; 3] = [Lane::Interactive, Lane::Batch, Lane::Background];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Lane::Interactive => "interactive",
+            Lane::Batch => "batch",
+            Lane::Background => "background",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Lane {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LaneConfig {
+    /// Jobs from this lane running at once.
+    pub concurrency: usize,
+    /// Jobs waiting in this lane before `POST /jobs` is refused.
+    pub max_queued: usize,
+}
+
+/// `[jobs]` config section.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    pub interactive: LaneConfig,
+    pub batch: LaneConfig,
+    pub background: LaneConfig,
+    /// Finished jobs kept for `GET /jobs/{id}`.
+    pub retained: usize,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            interactive: LaneConfig {
+                concurrency: 4,
+                max_queued: 256,
+            },
+            batch: LaneConfig {
+                concurrency: 2,
+                max_queued: 1024,
+            },
+            background: LaneConfig {
+                concurrency: 1,
+                max_queued: 1024,
+            },
+            retained: 1000,
+        }
+    }
+}
+
+impl JobsConfig {
+    pub fn lane(&self, lane: Lane) -> &LaneConfig {
+        match lane {
+            Lane::Interactive => &self.interactive,
+            Lane::Batch => &self.batch,
+            Lane::Background => &self.background,
+        }
+    }
+
+    /// Applies `UOR_JOBS_{INTERACTIVE,BATCH,BACKGROUND}_CONCURRENCY`.
+    pub fn apply_env(&mut self) {
+        for (key, lane) in [("INTERACTIVE", &mut self.interactive), ("BATCH", &mut self.batch), ("BACKGROUND", &mut self.background)] {
+            lane.concurrency = env_or(&format!("UOR_JOBS_{}_CONCURRENCY", key), lane.concurrency);
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for lane in Lane::ALL {
+            if self.lane(lane).concurrency == 0 {
+                return Err(format!("jobs.{}.concurrency must be at least 1", lane));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Succeeded => "succeeded",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// `POST /jobs` body, also taken by the VASM `VOIKE_RUN_JOB` syscall.
+#[derive(Deserialize)]
+pub struct JobRequest {
+    pub module: String,
+    pub export: String,
+    #[serde(default)]
+    pub args: Vec<Value>,
+    #[serde(default)]
+    pub limits: LimitOverrides,
+    #[serde(default)]
+    pub lane: Lane,
+}
+
+impl JobRequest {
+    /// The job to submit for `caller`, or `None` if it cannot use the module. A project owns the
+    /// jobs it submits, shared modules included; an admin's follow the module.
+    pub fn into_job(self, runtime: &WasmRuntime, caller: &Caller) -> Option<NewJob> {
+        let owner = runtime.registry.lock().ok().and_then(|registry| registry.get(&self.module).map(|info| info.project.clone()));
+        let owner = owner.filter(|owner| caller.can_use(owner.as_deref()))?;
+        let project = caller.project().map(str::to_string).or(owner);
+        Some(NewJob {
+            id: format!("job-{}", runtime.invocation_id()),
+            module: self.module,
+            project,
+            export: self.export,
+            args: self.args,
+            limits: runtime.limits().with_overrides(&self.limits),
+            lane: self.lane,
+            caller: caller.label(),
+        })
+    }
+}
+
+/// What a job runs, from `POST /jobs` or a schedule.
+pub struct NewJob {
+    pub id: String,
+    pub module: String,
+    pub project: Option<String>,
+    pub export: String,
+    pub args: Vec<Value>,
+    pub limits: ExecutionLimits,
+    pub lane: Lane,
+    /// Label of the submitting caller, who may also cancel the job.
+    pub caller: String,
+}
+
+/// `GET /jobs/{id}` body.
+#[derive(Clone, Debug, Serialize)]
+pub struct Job {
+    pub id: String,
+    pub module: String,
+    pub export: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    pub lane: Lane,
+    pub state: JobState,
+    pub caller: String,
+    pub submitted_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    /// Set once the job starts; its history and logs are under `/invocations/{id}`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invocation_id: Option<String>,
+    /// Set while a running job is being stopped.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cancel_requested: bool,
+    /// The `POST /invoke` success body.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// The `POST /invoke` error body.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>,
+    #[serde(skip)]
+    args: Vec<Value>,
+    #[serde(skip)]
+    limits: Option<ExecutionLimits>,
+    #[serde(skip)]
+    cancel: Arc<AtomicBool>,
+}
+
+/// A job the dispatcher has just moved to `running`.
+pub struct StartedJob {
+    pub id: String,
+    pub module: String,
+    pub project: Option<String>,
+    pub export: String,
+    pub args: Vec<Value>,
+    pub limits: ExecutionLimits,
+    pub invocation_id: String,
+    pub cancel: Arc<AtomicBool>,
+}
+
+/// One lane's load, reported in `RuntimeStatus`.
+#[derive(Clone, Serialize)]
+pub struct LaneStatus {
+    pub lane: Lane,
+    pub queued: u32,
+    pub running: u32,
+    pub concurrency: u32,
+}
+
+#[derive(Default)]
+struct QueueState {
+    jobs: HashMap<String, Job>,
+    pending: [VecDeque<String>; 3],
+    running: [usize; 3],
+    /// Finished job ids, oldest first, for eviction.
+    finished: VecDeque<String>,
+}
+
+impl QueueState {
+    fn retire(&mut self, id: &str, retained: usize) {
+        self.finished.push_back(id.to_string());
+        while self.finished.len() > retained {
+            if let Some(old) = self.finished.pop_front() {
+                self.jobs.remove(&old);
+            }
+        }
+    }
+}
+
+/// In-process job queue. Submitting or finishing a job notifies the dispatcher, which otherwise sleeps.
+#[derive(Default)]
+pub struct JobQueue {
+    state: Mutex<QueueState>,
+    wake: Notify,
+}
+
+impl JobQueue {
+    /// Queues a job, or says why its lane cannot take it.
+    pub fn submit(&self, new: NewJob, config: &JobsConfig) -> Result<Job, String> {
+        let mut state = self.state.lock().map_err(|_| "job queue unavailable".to_string())?;
+        let lane = new.lane;
+        let max_queued = config.lane(lane).max_queued;
+        if state.pending[lane.index()].len() >= max_queued {
+            return Err(format!("{} lane already has {} queued jobs", lane, max_queued));
+        }
+        let job = Job {
+            id: new.id,
+            module: new.module,
+            export: new.export,
+            project: new.project,
+            lane,
+            state: JobState::Queued,
+            caller: new.caller,
+            submitted_at: chrono::Utc::now().to_rfc3339(),
+            started_at: None,
+            finished_at: None,
+            invocation_id: None,
+            cancel_requested: false,
+            result: None,
+            error: None,
+            args: new.args,
+            limits: Some(new.limits),
+            cancel: Arc::new(AtomicBool::new(false)),
+        };
+        state.pending[lane.index()].push_back(job.id.clone());
+        state.jobs.insert(job.id.clone(), job.clone());
+        drop(state);
+        self.wake.notify_one();
+        Ok(job)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.state.lock().ok()?.jobs.get(id).cloned()
+    }
+
+    /// Cancels a queued job outright and asks a running one to stop. Finished jobs come back as `Err`.
+    pub fn cancel(&self, id: &str, retained: usize) -> Option<Result<Job, Job>> {
+        let mut state = self.state.lock().ok()?;
+        let job = state.jobs.get_mut(id)?;
+        match job.state {
+            JobState::Queued => {
+                job.state = JobState::Cancelled;
+                job.finished_at = Some(chrono::Utc::now().to_rfc3339());
+                let (lane, job) = (job.lane, job.clone());
+                state.pending[lane.index()].retain(|queued| queued != id);
+                state.retire(id, retained);
+                Some(Ok(job))
+            }
+            JobState::Running => {
+                job.cancel_requested = true;
+                job.cancel.store(true, Ordering::SeqCst);
+                Some(Ok(job.clone()))
+            }
+            _ => Some(Err(job.clone())),
+        }
+    }
+
+    /// Cancels everything still queued, returning how many jobs that was.
+    pub fn cancel_queued(&self, retained: usize) -> usize {
+        let Ok(mut state) = self.state.lock() else { return 0 };
+        let ids: Vec<String> = state.pending.iter_mut().flat_map(|pending| pending.drain(..)).collect();
+        let now = chrono::Utc::now().to_rfc3339();
+        for id in &ids {
+            if let Some(job) = state.jobs.get_mut(id) {
+                job.state = JobState::Cancelled;
+                job.finished_at = Some(now.clone());
+            }
+            state.retire(id, retained);
+        }
+        ids.len()
+    }
+
+    /// Moves queued jobs to `running` while their lanes have free slots, highest priority first.
+    /// `invocation_id` names each job's invocation.
+    fn start_ready(&self, config: &JobsConfig, mut invocation_id: impl FnMut() -> String) -> Vec<StartedJob> {
+        let Ok(mut state) = self.state.lock() else { return Vec::new() };
+        let state = &mut *state;
+        let mut started = Vec::new();
+        for lane in Lane::ALL {
+            let i = lane.index();
+            while state.running[i] < config.lane(lane).concurrency {
+                let Some(id) = state.pending[i].pop_front() else { break };
+                let Some(job) = state.jobs.get_mut(&id) else { continue };
+                let invocation = invocation_id();
+                job.state = JobState::Running;
+                job.started_at = Some(chrono::Utc::now().to_rfc3339());
+                job.invocation_id = Some(invocation.clone());
+                state.running[i] += 1;
+                started.push(StartedJob {
+                    id,
+                    module: job.module.clone(),
+                    project: job.project.clone(),
+                    export: job.export.clone(),
+                    args: std::mem::take(&mut job.args),
+                    limits: job.limits.take().unwrap_or_default(),
+                    invocation_id: invocation,
+                    cancel: job.cancel.clone(),
+                });
+            }
+        }
+        started
+    }
+
+    /// Records a running job's outcome and frees its lane slot.
+    fn finish(&self, id: &str, outcome: Result<Value, InvokeError>, retained: usize) {
+        let Ok(mut state) = self.state.lock() else { return };
+        let Some(job) = state.jobs.get_mut(id) else { return };
+        job.state = match &outcome {
+            Ok(_) => JobState::Succeeded,
+            Err(err) if err.error == "cancelled" => JobState::Cancelled,
+            Err(_) => JobState::Failed,
+        };
+        job.finished_at = Some(chrono::Utc::now().to_rfc3339());
+        match outcome {
+            Ok(result) => job.result = Some(result),
+            Err(err) => job.error = serde_json::to_value(&err).ok(),
+        }
+        let lane = job.lane.index();
+        state.running[lane] = state.running[lane].saturating_sub(1);
+        state.retire(id, retained);
+        drop(state);
+        self.wake.notify_one();
+    }
+
+    /// Queue depth and running jobs per lane.
+    pub fn lanes(&self, config: &JobsConfig) -> Vec<LaneStatus> {
+        let Ok(state) = self.state.lock() else { return Vec::new() };
+        Lane::ALL
+            .iter()
+            .map(|&lane| LaneStatus {
+                lane,
+                queued: state.pending[lane.index()].len() as u32,
+                running: state.running[lane.index()] as u32,
+                concurrency: config.lane(lane).concurrency as u32,
+            })
+            .collect()
+    }
+}
+
+/// Starts queued jobs as lane slots free up. Tickless: it only wakes when a job is submitted or
+/// finishes, or the config changes. Nothing new starts once the engine is draining.
+pub async fn dispatch(runtime: Arc<WasmRuntime>) {
+    let mut config = runtime.config.subscribe();
+    loop {
+        if !runtime.is_draining() {
+            let jobs = config.borrow_and_update().jobs.clone();
+            for job in runtime.jobs.start_ready(&jobs, || runtime.invocation_id()) {
+                tokio::spawn(run(runtime.clone(), job));
+            }
+        }
+        tokio::select! {
+            _ = runtime.jobs.wake.notified() => {}
+            changed = config.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn run(runtime: Arc<WasmRuntime>, job: StartedJob) {
+    let loaded = runtime.registry.lock().ok().and_then(|mut registry| registry.checkout(&job.module));
+    let outcome = match loaded {
+        Some(loaded) => {
+            let invocation = Invocation {
+                id: job.invocation_id,
+                loaded,
+                export: job.export,
+                args: job.args,
+                limits: job.limits,
+                caller: format!("job:{}", job.id),
+                project: job.project,
+                cancel: Some(job.cancel),
+                http: None,
+                grid: None,
+            };
+            invocations::run(runtime.clone(), invocation).await
+        }
+        None => Err(InvokeError::new("unknown_module", format!("module `{}` was unloaded before the job started", job.module))),
+    };
+    let outcome = outcome.map(|result| serde_json::to_value(&result).unwrap_or_default());
+    runtime.jobs.finish(&job.id, outcome, runtime.config.get().jobs.retained);
+    runtime.wake.notify_one();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(id: &str, lane: Lane) -> NewJob {
+        NewJob {
+            id: id.to_string(),
+            module: "m".to_string(),
+            project: None,
+            export: "run".to_string(),
+            args: Vec::new(),
+            limits: ExecutionLimits::default(),
+            lane,
+            caller: "admin".to_string(),
+        }
+    }
+
+    fn ids(started: &[StartedJob]) -> Vec<&str> {
+        started.iter().map(|job| job.id.as_str()).collect()
+    }
+
+    #[test]
+    fn fills_lanes_in_priority_order_up_to_concurrency() {
+        let queue = JobQueue::default();
+        let config = JobsConfig {
+            interactive: LaneConfig {
+                concurrency: 1,
+                max_queued: 2,
+            },
+            ..JobsConfig::default()
+        };
+        for (id, lane) in [("b1", Lane::Background), ("b2", Lane::Background), ("i1", Lane::Interactive), ("i2", Lane::Interactive)] {
+            queue.submit(job(id, lane), &config).unwrap();
+        }
+        assert!(queue.submit(job("i3", Lane::Interactive), &config).is_err());
+
+        let started = queue.start_ready(&config, || "inv".to_string());
+        assert_eq!(ids(&started), ["i1", "b1"]);
+        assert!(queue.start_ready(&config, || "inv".to_string()).is_empty());
+        queue.finish("i1", Ok(Value::Null), 10);
+        assert_eq!(queue.get("i1").unwrap().state, JobState::Succeeded);
+        assert_eq!(ids(&queue.start_ready(&config, || "inv".to_string())), ["i2"]);
+        let background = &queue.lanes(&config)[2];
+        assert_eq!((background.queued, background.running), (1, 1));
+    }
+
+    #[test]
+    fn cancels_queued_jobs_at_once_and_flags_running_ones() {
+        let queue = JobQueue::default();
+        let config = JobsConfig::default();
+        queue.submit(job("running", Lane::Background), &config).unwrap();
+        queue.submit(job("queued", Lane::Background), &config).unwrap();
+        let started = queue.start_ready(&config, || "inv".to_string());
+
+        assert_eq!(queue.cancel("queued", 10).unwrap().unwrap().state, JobState::Cancelled);
+        let running = queue.cancel("running", 10).unwrap().unwrap();
+        assert!(running.state == JobState::Running && running.cancel_requested);
+        assert!(started[0].cancel.load(Ordering::SeqCst));
+
+        queue.finish("running", Err(InvokeError::new("cancelled", "invocation cancelled")), 10);
+        assert_eq!(queue.get("running").unwrap().state, JobState::Cancelled);
+        assert!(queue.cancel("running", 10).unwrap().is_err());
+        assert!(queue.start_ready(&config, || "inv".to_string()).is_empty());
+    }
+}
@@ -0,0 +1,388 @@
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, fs, ops::Bound, path::Path, sync::Arc};
+use wasmtime::{Caller, Linker};
+
+use crate::{
+    limits::env_or,
+    wasm::{read_guest, write_guest, StoreState},
+};
+
+/// `(namespace, key)` to value.
+const ENTRIES: TableDefinition<(&str, &[u8]), &[u8]> = TableDefinition::new("entries");
+/// Namespace to the bytes of keys and values it holds, kept in step with `ENTRIES`.
+const USAGE: TableDefinition<&str, u64> = TableDefinition::new("usage");
+
+/// Guest-visible status codes returned by the `voike.kv_*` imports.
+pub const KV_NOT_FOUND: i32 = -1;
+pub const KV_QUOTA_EXCEEDED: i32 = -2;
+pub const KV_TOO_LARGE: i32 = -3;
+pub const KV_BUFFER_TOO_SMALL: i32 = -4;
+pub const KV_UNAVAILABLE: i32 = -5;
+
+/// `[kv]` config section: per-namespace quotas and entry size caps.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KvConfig {
+    /// Bytes of keys plus values a namespace may hold.
+    pub quota_bytes: u64,
+    pub max_key_bytes: u32,
+    pub max_value_bytes: u32,
+    /// Per-namespace replacements for `quota_bytes`, e.g. `"project/acme" = 104857600`.
+    pub quotas: BTreeMap<String, u64>,
+}
+
+impl Default for KvConfig {
+    fn default() -> Self {
+        Self {
+            quota_bytes: 16 * 1024 * 1024,
+            max_key_bytes: 1024,
+            max_value_bytes: 1024 * 1024,
+            quotas: BTreeMap::new(),
+        }
+    }
+}
+
+impl KvConfig {
+    /// Applies `UOR_KV_QUOTA_BYTES`.
+    pub fn apply_env(&mut self) {
+        self.quota_bytes = env_or("UOR_KV_QUOTA_BYTES", self.quota_bytes);
+    }
+
+    pub fn quota(&self, namespace: &str) -> u64 {
+        self.quotas.get(namespace).copied().unwrap_or(self.quota_bytes)
+    }
+}
+
+/// Namespace a module's entries live in: shared by every module a project owns. A shared module
+/// (no `owner`) gets one namespace per project calling it, and one for calls made for no project.
+pub fn namespace(module: &str, owner: Option<&str>, project: Option<&str>) -> String {
+    match (owner, project) {
+        (Some(owner), _) => format!("project/{}", owner),
+        (None, Some(project)) => format!("module/{}/{}", module, project),
+        (None, None) => format!("module/{}", module),
+    }
+}
+
+/// A namespace's footprint, reported with each module in `GET /modules`.
+#[derive(Clone, Serialize)]
+pub struct KvUsage {
+    pub namespace: String,
+    pub used_bytes: u64,
+    pub quota_bytes: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum KvError {
+    QuotaExceeded,
+    Storage(String),
+}
+
+impl KvError {
+    fn code(&self) -> i32 {
+        match self {
+            KvError::QuotaExceeded => KV_QUOTA_EXCEEDED,
+            KvError::Storage(_) => KV_UNAVAILABLE,
+        }
+    }
+}
+
+impl fmt::Display for KvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KvError::QuotaExceeded => f.write_str("namespace quota exceeded"),
+            KvError::Storage(message) => f.write_str(message),
+        }
+    }
+}
+
+fn storage(err: impl Into<redb::Error>) -> KvError {
+    KvError::Storage(err.into().to_string())
+}
+
+/// Bytes an entry takes in a `kv_scan` buffer: two little-endian u32 lengths plus the data.
+fn encoded_len(key: &[u8], value: &[u8]) -> usize {
+    8 + key.len() + value.len()
+}
+
+/// A key and its value, as returned by [`KvStore::scan`].
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// Embedded store on the node, persisted in a single redb file.
+pub struct KvStore {
+    db: Database,
+}
+
+impl KvStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|err| format!("{}: {}", parent.display(), err))?;
+        }
+        let db = Database::create(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let init = || -> Result<(), KvError> {
+            let txn = db.begin_write().map_err(storage)?;
+            txn.open_table(ENTRIES).map_err(storage)?;
+            txn.open_table(USAGE).map_err(storage)?;
+            txn.commit().map_err(storage)
+        };
+        init().map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(Self { db })
+    }
+
+    pub fn get(&self, namespace: &str, key: &[u8]) -> Result<Option<Vec<u8>>, KvError> {
+        let txn = self.db.begin_read().map_err(storage)?;
+        let table = txn.open_table(ENTRIES).map_err(storage)?;
+        let value = table.get((namespace, key)).map_err(storage)?;
+        Ok(value.map(|value| value.value().to_vec()))
+    }
+
+    /// Inserts or replaces `key`, refusing writes that would take the namespace past `quota` bytes.
+    pub fn put(&self, namespace: &str, key: &[u8], value: &[u8], quota: u64) -> Result<(), KvError> {
+        let txn = self.db.begin_write().map_err(storage)?;
+        {
+            let mut entries = txn.open_table(ENTRIES).map_err(storage)?;
+            let mut usage = txn.open_table(USAGE).map_err(storage)?;
+            let used = usage.get(namespace).map_err(storage)?.map(|used| used.value()).unwrap_or(0);
+            let replaced = entries.get((namespace, key)).map_err(storage)?.map(|old| (key.len() + old.value().len()) as u64).unwrap_or(0);
+            let added = (key.len() + value.len()) as u64;
+            let next = used.saturating_sub(replaced).saturating_add(added);
+            if added > replaced && next > quota {
+                return Err(KvError::QuotaExceeded);
+            }
+            entries.insert((namespace, key), value).map_err(storage)?;
+            usage.insert(namespace, next).map_err(storage)?;
+        }
+        txn.commit().map_err(storage)
+    }
+
+    /// Returns whether `key` existed.
+    pub fn delete(&self, namespace: &str, key: &[u8]) -> Result<bool, KvError> {
+        let txn = self.db.begin_write().map_err(storage)?;
+        let removed = {
+            let mut entries = txn.open_table(ENTRIES).map_err(storage)?;
+            let mut usage = txn.open_table(USAGE).map_err(storage)?;
+            let removed = entries.remove((namespace, key)).map_err(storage)?.map(|old| (key.len() + old.value().len()) as u64);
+            if let Some(freed) = removed {
+                let used = usage.get(namespace).map_err(storage)?.map(|used| used.value()).unwrap_or(0);
+                usage.insert(namespace, used.saturating_sub(freed)).map_err(storage)?;
+            }
+            removed.is_some()
+        };
+        txn.commit().map_err(storage)?;
+        Ok(removed)
+    }
+
+    /// Entries under `prefix` in key order, starting after `after`, for as long as their encoded
+    /// size fits in `budget` bytes. The flag is set when more entries remain.
+    pub fn scan(&self, namespace: &str, prefix: &[u8], after: Option<&[u8]>, budget: usize) -> Result<(Vec<Entry>, bool), KvError> {
+        let txn = self.db.begin_read().map_err(storage)?;
+        let table = txn.open_table(ENTRIES).map_err(storage)?;
+        let start = match after.filter(|after| *after >= prefix) {
+            Some(after) => Bound::Excluded((namespace, after)),
+            None => Bound::Included((namespace, prefix)),
+        };
+        let mut entries = Vec::new();
+        let mut used = 0;
+        for entry in table.range::<(&str, &[u8])>((start, Bound::Unbounded)).map_err(storage)? {
+            let (key, value) = entry.map_err(storage)?;
+            let (entry_namespace, key) = key.value();
+            if entry_namespace != namespace || !key.starts_with(prefix) {
+                break;
+            }
+            let value = value.value();
+            used += encoded_len(key, value);
+            if used > budget {
+                return Ok((entries, true));
+            }
+            entries.push((key.to_vec(), value.to_vec()));
+        }
+        Ok((entries, false))
+    }
+
+    pub fn usage(&self, namespace: &str) -> Result<u64, KvError> {
+        let txn = self.db.begin_read().map_err(storage)?;
+        let table = txn.open_table(USAGE).map_err(storage)?;
+        let used = table.get(namespace).map_err(storage)?;
+        Ok(used.map(|used| used.value()).unwrap_or(0))
+    }
+}
+
+/// What an invocation may reach in the store: its own namespace, under the config at call time.
+pub struct KvHandle {
+    pub store: Option<Arc<KvStore>>,
+    pub namespace: String,
+    pub config: KvConfig,
+}
+
+impl KvHandle {
+    fn store(&self) -> Result<&KvStore, KvError> {
+        self.store.as_deref().ok_or_else(|| KvError::Storage("key-value store is disabled".to_string()))
+    }
+
+    fn report(&self, op: &str, err: KvError) -> i32 {
+        if let KvError::Storage(message) = &err {
+            eprintln!("[uor-engine] kv_{} in {} failed: {}", op, self.namespace, message);
+        }
+        err.code()
+    }
+}
+
+/// Registers the `voike.kv_*` imports. Pointers and lengths address the guest's exported
+/// `memory`; negative results are the `KV_*` codes above, out-of-bounds ranges trap.
+pub fn add_to_linker(linker: &mut Linker<StoreState>) -> wasmtime::Result<()> {
+    // kv_get(key, key_len, buf, buf_len) -> value length, copying as much as fits into `buf`.
+    linker.func_wrap(
+        "voike",
+        "kv_get",
+        |mut caller: Caller<'_, StoreState>, key_ptr: u32, key_len: u32, buf_ptr: u32, buf_len: u32| -> wasmtime::Result<i64> {
+            if key_len > caller.data().kv.config.max_key_bytes {
+                return Ok(KV_TOO_LARGE.into());
+            }
+            let key = read_guest(&mut caller, key_ptr, key_len)?;
+            let kv = &caller.data().kv;
+            let value = match kv.store().and_then(|store| store.get(&kv.namespace, &key)) {
+                Ok(Some(value)) => value,
+                Ok(None) => return Ok(KV_NOT_FOUND.into()),
+                Err(err) => return Ok(kv.report("get", err).into()),
+            };
+            write_guest(&mut caller, buf_ptr, &value[..value.len().min(buf_len as usize)])?;
+            Ok(value.len() as i64)
+        },
+    )?;
+    // kv_put(key, key_len, value, value_len) -> 0
+    linker.func_wrap(
+        "voike",
+        "kv_put",
+        |mut caller: Caller<'_, StoreState>, key_ptr: u32, key_len: u32, value_ptr: u32, value_len: u32| -> wasmtime::Result<i32> {
+            let config = &caller.data().kv.config;
+            if key_len > config.max_key_bytes || value_len > config.max_value_bytes {
+                return Ok(KV_TOO_LARGE);
+            }
+            let key = read_guest(&mut caller, key_ptr, key_len)?;
+            let value = read_guest(&mut caller, value_ptr, value_len)?;
+            let kv = &caller.data().kv;
+            let quota = kv.config.quota(&kv.namespace);
+            Ok(match kv.store().and_then(|store| store.put(&kv.namespace, &key, &value, quota)) {
+                Ok(()) => 0,
+                Err(err) => kv.report("put", err),
+            })
+        },
+    )?;
+    // kv_delete(key, key_len) -> 1 if the key existed, 0 otherwise
+    linker.func_wrap(
+        "voike",
+        "kv_delete",
+        |mut caller: Caller<'_, StoreState>, key_ptr: u32, key_len: u32| -> wasmtime::Result<i32> {
+            if key_len > caller.data().kv.config.max_key_bytes {
+                return Ok(KV_TOO_LARGE);
+            }
+            let key = read_guest(&mut caller, key_ptr, key_len)?;
+            let kv = &caller.data().kv;
+            Ok(match kv.store().and_then(|store| store.delete(&kv.namespace, &key)) {
+                Ok(existed) => existed.into(),
+                Err(err) => kv.report("delete", err),
+            })
+        },
+    )?;
+    // kv_scan(prefix, prefix_len, after, after_len, buf, buf_len) -> bytes written, 0 when done.
+    // Each entry is written as `key_len: u32le, key, value_len: u32le, value`; pass the last key
+    // returned as `after` to continue.
+    linker.func_wrap(
+        "voike",
+        "kv_scan",
+        |mut caller: Caller<'_, StoreState>, prefix_ptr: u32, prefix_len: u32, after_ptr: u32, after_len: u32, buf_ptr: u32, buf_len: u32| -> wasmtime::Result<i64> {
+            let max_key_bytes = caller.data().kv.config.max_key_bytes;
+            if prefix_len > max_key_bytes || after_len > max_key_bytes {
+                return Ok(KV_TOO_LARGE.into());
+            }
+            let prefix = read_guest(&mut caller, prefix_ptr, prefix_len)?;
+            let after = read_guest(&mut caller, after_ptr, after_len)?;
+            let kv = &caller.data().kv;
+            let after = Some(after.as_slice()).filter(|after| !after.is_empty());
+            let (entries, more) = match kv.store().and_then(|store| store.scan(&kv.namespace, &prefix, after, buf_len as usize)) {
+                Ok(scanned) => scanned,
+                Err(err) => return Ok(kv.report("scan", err).into()),
+            };
+            if entries.is_empty() && more {
+                return Ok(KV_BUFFER_TOO_SMALL.into());
+            }
+            let mut buf = Vec::new();
+            for (key, value) in &entries {
+                buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+                buf.extend_from_slice(key);
+                buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                buf.extend_from_slice(value);
+            }
+            write_guest(&mut caller, buf_ptr, &buf)?;
+            Ok(buf.len() as i64)
+        },
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> (KvStore, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("uor-kv-test-{}-{:?}.redb", std::process::id(), std::thread::current().id()));
+        let _ = fs::remove_file(&path);
+        (KvStore::open(&path).unwrap(), path)
+    }
+
+    #[test]
+    fn keeps_namespaces_apart_and_enforces_quota() {
+        let (kv, path) = store();
+        kv.put("project/acme", b"a", b"1234", 10).unwrap();
+        assert_eq!(kv.get("project/acme", b"a").unwrap(), Some(b"1234".to_vec()));
+        assert_eq!(kv.get("module/other", b"a").unwrap(), None);
+        assert_eq!(kv.put("project/acme", b"b", b"123456", 10), Err(KvError::QuotaExceeded));
+        // Replacing with a smaller value always fits, and usage follows it.
+        kv.put("project/acme", b"a", b"1", 10).unwrap();
+        assert_eq!(kv.usage("project/acme").unwrap(), 2);
+        assert!(kv.delete("project/acme", b"a").unwrap());
+        assert!(!kv.delete("project/acme", b"a").unwrap());
+        assert_eq!(kv.usage("project/acme").unwrap(), 0);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn survives_usage_that_drifted_below_the_entries() {
+        let (kv, path) = store();
+        kv.put("module/m", b"a", b"1234", 100).unwrap();
+        let txn = kv.db.begin_write().unwrap();
+        txn.open_table(USAGE).unwrap().insert("module/m", 0).unwrap();
+        txn.commit().unwrap();
+        kv.put("module/m", b"a", b"12", 100).unwrap();
+        assert_eq!(kv.usage("module/m").unwrap(), 3);
+        kv.put("module/m", b"a", b"123456", 100).unwrap();
+        assert_eq!(kv.usage("module/m").unwrap(), 7);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn gives_each_project_its_own_namespace_in_shared_modules() {
+        assert_eq!(namespace("report", Some("acme"), Some("acme")), "project/acme");
+        assert_eq!(namespace("report", Some("acme"), None), "project/acme");
+        assert_eq!(namespace("report", None, Some("acme")), "module/report/acme");
+        assert_ne!(namespace("report", None, Some("acme")), namespace("report", None, Some("globex")));
+        assert_eq!(namespace("report", None, None), "module/report");
+    }
+
+    #[test]
+    fn scans_a_prefix_in_pages() {
+        let (kv, path) = store();
+        for key in ["user:1", "user:2", "user:3", "zebra"] {
+            kv.put("module/m", key.as_bytes(), b"v", 1024).unwrap();
+        }
+        kv.put("module/n", b"user:9", b"v", 1024).unwrap();
+        let page = encoded_len(b"user:1", b"v") * 2;
+        let (first, more) = kv.scan("module/m", b"user:", None, page).unwrap();
+        assert_eq!(first.len(), 2);
+        assert!(more);
+        let (rest, more) = kv.scan("module/m", b"user:", Some(&first[1].0), page).unwrap();
+        assert_eq!(rest, vec![(b"user:3".to_vec(), b"v".to_vec())]);
+        assert!(!more);
+        let _ = fs::remove_file(path);
+    }
+}
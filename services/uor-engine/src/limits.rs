@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+use wasmtime::{Engine, ResourceLimiter};
+
+/// Granularity of wall-clock timeouts; the engine epoch advances once per tick while guests run.
+pub const EPOCH_TICK_MS: u64 = 10;
+
+/// Per-invocation execution budget.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutionLimits {
+    pub fuel: u64,
+    pub timeout_ms: u64,
+    pub max_memory_mb: u64,
+    pub max_table_elements: u32,
+}
+
+impl Default for ExecutionLimits {
+    fn default() -> Self {
+        Self {
+            fuel: 1_000_000_000,
+            timeout_ms: 5_000,
+            max_memory_mb: 64,
+            max_table_elements: 10_000,
+        }
+    }
+}
+
+impl ExecutionLimits {
+    /// Applies `UOR_MAX_FUEL`, `UOR_TIMEOUT_MS`, `UOR_MAX_MEMORY_MB` and `UOR_MAX_TABLE_ELEMENTS`.
+    pub fn apply_env(&mut self) {
+        self.fuel = env_or("UOR_MAX_FUEL", self.fuel);
+        self.timeout_ms = env_or("UOR_TIMEOUT_MS", self.timeout_ms);
+        self.max_memory_mb = env_or("UOR_MAX_MEMORY_MB", self.max_memory_mb);
+        self.max_table_elements = env_or("UOR_MAX_TABLE_ELEMENTS", self.max_table_elements);
+    }
+
+    /// Applies per-call overrides. They can only tighten the configured limits; larger values are capped.
+    pub fn with_overrides(&self, overrides: &LimitOverrides) -> Self {
+        Self {
+            fuel: overrides.fuel.map_or(self.fuel, |fuel| fuel.min(self.fuel)),
+            timeout_ms: overrides.timeout_ms.map_or(self.timeout_ms, |timeout_ms| timeout_ms.min(self.timeout_ms)),
+            max_memory_mb: overrides.max_memory_mb.map_or(self.max_memory_mb, |max_memory_mb| max_memory_mb.min(self.max_memory_mb)),
+            max_table_elements: overrides
+                .max_table_elements
+                .map_or(self.max_table_elements, |max_table_elements| max_table_elements.min(self.max_table_elements)),
+        }
+    }
+
+    /// Number of epoch ticks before the store is interrupted.
+    pub fn epoch_deadline(&self) -> u64 {
+        self.timeout_ms.div_ceil(EPOCH_TICK_MS).max(1)
+    }
+}
+
+/// Optional per-call reductions of the configured limits.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LimitOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fuel: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_memory_mb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_table_elements: Option<u32>,
+}
+
+pub fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+/// Caps linear memory and table growth, remembering which cap was hit so the
+/// resulting trap can be reported by name.
+pub struct GuestLimiter {
+    max_memory_bytes: usize,
+    max_table_elements: u32,
+    pub tripped: Option<&'static str>,
+    /// Largest linear memory the guest was granted.
+    pub peak_memory_bytes: usize,
+}
+
+impl GuestLimiter {
+    pub fn new(limits: &ExecutionLimits) -> Self {
+        Self {
+            max_memory_bytes: (limits.max_memory_mb as usize).saturating_mul(1024 * 1024),
+            max_table_elements: limits.max_table_elements,
+            tripped: None,
+            peak_memory_bytes: 0,
+        }
+    }
+}
+
+impl ResourceLimiter for GuestLimiter {
+    fn memory_growing(&mut self, _current: usize, desired: usize, _maximum: Option<usize>) -> wasmtime::Result<bool> {
+        if desired > self.max_memory_bytes {
+            self.tripped = Some("memory_limit");
+            return Err(wasmtime::Error::msg(format!("linear memory of {} bytes exceeds the {} byte limit", desired, self.max_memory_bytes)));
+        }
+        self.peak_memory_bytes = self.peak_memory_bytes.max(desired);
+        Ok(true)
+    }
+
+    fn table_growing(&mut self, _current: u32, desired: u32, _maximum: Option<u32>) -> wasmtime::Result<bool> {
+        if desired > self.max_table_elements {
+            self.tripped = Some("table_limit");
+            return Err(wasmtime::Error::msg(format!("table of {} elements exceeds the {} element limit", desired, self.max_table_elements)));
+        }
+        Ok(true)
+    }
+}
+
+/// Advances the engine epoch every [`EPOCH_TICK_MS`] while at least one guest
+/// is running, and parks otherwise so an idle node stays tickless.
+pub struct EpochTicker {
+    running: Mutex<usize>,
+    wake: Condvar,
+}
+
+impl EpochTicker {
+    pub fn start(engine: Engine) -> Arc<Self> {
+        let ticker = Arc::new(Self {
+            running: Mutex::new(0),
+            wake: Condvar::new(),
+        });
+        let worker = ticker.clone();
+        thread::Builder::new()
+            .name("uor-epoch".to_string())
+            .spawn(move || loop {
+                {
+                    let Ok(mut running) = worker.running.lock() else { return };
+                    while *running == 0 {
+                        running = match worker.wake.wait(running) {
+                            Ok(guard) => guard,
+                            Err(_) => return,
+                        };
+                    }
+                }
+                thread::sleep(Duration::from_millis(EPOCH_TICK_MS));
+                engine.increment_epoch();
+            })
+            .expect("failed to spawn epoch ticker");
+        ticker
+    }
+
+    /// Marks a guest as running until the returned guard is dropped.
+    pub fn enter(self: &Arc<Self>) -> EpochGuard {
+        if let Ok(mut running) = self.running.lock() {
+            *running += 1;
+        }
+        self.wake.notify_one();
+        EpochGuard { ticker: self.clone() }
+    }
+}
+
+pub struct EpochGuard {
+    ticker: Arc<EpochTicker>,
+}
+
+impl Drop for EpochGuard {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.ticker.running.lock() {
+            *running = running.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_cannot_raise_the_configured_limits() {
+        let configured = ExecutionLimits::default();
+        let overrides = LimitOverrides {
+            fuel: Some(1_000),
+            timeout_ms: Some(u64::MAX),
+            max_memory_mb: Some(configured.max_memory_mb * 2),
+            max_table_elements: None,
+        };
+        let limits = configured.with_overrides(&overrides);
+        assert_eq!(limits.fuel, 1_000);
+        assert_eq!(limits.timeout_ms, configured.timeout_ms);
+        assert_eq!(limits.max_memory_mb, configured.max_memory_mb);
+        assert_eq!(limits.max_table_elements, configured.max_table_elements);
+    }
+}
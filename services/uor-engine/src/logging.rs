@@ -0,0 +1,367 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::VecDeque, fmt, str::FromStr, sync::Mutex, time::Instant};
+use wasmtime::{Caller, Linker};
+
+use crate::{
+    limits::env_or,
+    wasm::{read_guest, StoreState},
+};
+
+/// Spans open at once; every record carries the whole stack, so it stays small.
+const MAX_SPAN_DEPTH: usize = 32;
+/// Span names are cut to this many bytes.
+const MAX_SPAN_NAME_BYTES: u32 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    /// Guests pass 0 (trace) through 4 (error); anything outside is clamped.
+    fn from_guest(level: i32) -> Self {
+        match level {
+            i32::MIN..=0 => LogLevel::Trace,
+            1 => LogLevel::Debug,
+            2 => LogLevel::Info,
+            3 => LogLevel::Warn,
+            _ => LogLevel::Error,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, ()> {
+        match value.to_ascii_lowercase().as_str() {
+            "trace" => Ok(LogLevel::Trace),
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warn" => Ok(LogLevel::Warn),
+            "error" => Ok(LogLevel::Error),
+            _ => Err(()),
+        }
+    }
+}
+
+/// `[logging]` config section for guest logs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Records below this level are discarded.
+    pub level: LogLevel,
+    /// Most recent records kept per invocation.
+    pub tail_lines: usize,
+    /// Longer messages and field payloads are cut to this many bytes.
+    pub max_message_bytes: usize,
+    /// Invocations whose log tails stay available at `GET /invocations/{id}/logs`.
+    pub retained_invocations: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::Info,
+            tail_lines: 100,
+            max_message_bytes: 4096,
+            retained_invocations: 256,
+        }
+    }
+}
+
+impl LogConfig {
+    /// Applies `UOR_GUEST_LOG_LEVEL`.
+    pub fn apply_env(&mut self) {
+        self.level = env_or("UOR_GUEST_LOG_LEVEL", self.level);
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LogRecord {
+    pub at: String,
+    pub level: LogLevel,
+    pub message: String,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub fields: Value,
+    /// Open spans, outermost first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<String>,
+}
+
+/// What every record from one invocation is tagged with.
+#[derive(Clone, Serialize)]
+pub struct InvocationTags {
+    pub invocation_id: String,
+    pub module: String,
+    /// Project the call was made for; only that project may read the logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+}
+
+struct Span {
+    id: i32,
+    name: String,
+    entered: Instant,
+}
+
+/// Collects one invocation's guest logs and forwards them to the engine log.
+pub struct GuestLog {
+    tags: InvocationTags,
+    config: LogConfig,
+    tail: VecDeque<LogRecord>,
+    dropped: u64,
+    spans: Vec<Span>,
+    next_span: i32,
+}
+
+impl GuestLog {
+    pub fn new(tags: InvocationTags, config: LogConfig) -> Self {
+        Self {
+            tags,
+            config,
+            tail: VecDeque::new(),
+            dropped: 0,
+            spans: Vec::new(),
+            next_span: 1,
+        }
+    }
+
+    fn emit(&mut self, level: LogLevel, message: String, fields: Value) {
+        if level < self.config.level {
+            return;
+        }
+        let record = LogRecord {
+            at: chrono::Utc::now().to_rfc3339(),
+            level,
+            message,
+            fields,
+            spans: self.spans.iter().map(|span| span.name.clone()).collect(),
+        };
+        let tags = &self.tags;
+        let mut line = format!("[uor-engine] guest {} {} invocation={}", level, tags.module, tags.invocation_id);
+        if let Some(project) = &tags.project {
+            line.push_str(&format!(" project={}", project));
+        }
+        if !record.spans.is_empty() {
+            line.push_str(&format!(" span={}", record.spans.join("/")));
+        }
+        line.push_str(&format!(": {}", record.message));
+        if !record.fields.is_null() {
+            line.push_str(&format!(" {}", record.fields));
+        }
+        if level >= LogLevel::Warn {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+        if self.config.tail_lines == 0 {
+            self.dropped += 1;
+            return;
+        }
+        if self.tail.len() >= self.config.tail_lines {
+            self.tail.pop_front();
+            self.dropped += 1;
+        }
+        self.tail.push_back(record);
+    }
+
+    /// Opens a span, or returns -1 once `MAX_SPAN_DEPTH` spans are open.
+    fn enter(&mut self, name: String) -> i32 {
+        if self.spans.len() >= MAX_SPAN_DEPTH {
+            return -1;
+        }
+        self.emit(LogLevel::Trace, format!("enter {}", name), Value::Null);
+        let id = self.next_span;
+        self.next_span += 1;
+        self.spans.push(Span {
+            id,
+            name,
+            entered: Instant::now(),
+        });
+        id
+    }
+
+    /// Closes span `id` and any spans opened inside it that were not closed.
+    fn exit(&mut self, id: i32) -> bool {
+        let Some(depth) = self.spans.iter().position(|span| span.id == id) else {
+            return false;
+        };
+        while self.spans.len() > depth {
+            let duration_ms = self.spans.last().map(|span| span.entered.elapsed().as_secs_f64() * 1000.0).unwrap_or_default();
+            let name = self.spans.last().map(|span| span.name.clone()).unwrap_or_default();
+            self.emit(LogLevel::Debug, format!("exit {}", name), serde_json::json!({ "duration_ms": duration_ms }));
+            self.spans.pop();
+        }
+        true
+    }
+
+    pub fn finish(self) -> InvocationLogs {
+        InvocationLogs {
+            tags: self.tags,
+            logs: self.tail.into(),
+            dropped: self.dropped,
+        }
+    }
+}
+
+/// `GET /invocations/{id}/logs` body.
+#[derive(Clone, Serialize)]
+pub struct InvocationLogs {
+    #[serde(flatten)]
+    pub tags: InvocationTags,
+    pub logs: Vec<LogRecord>,
+    /// Records that fell out of the tail.
+    pub dropped: u64,
+}
+
+/// Log tails of the most recent invocations.
+#[derive(Default)]
+pub struct LogStore {
+    recent: Mutex<VecDeque<InvocationLogs>>,
+}
+
+impl LogStore {
+    pub fn push(&self, logs: InvocationLogs, retain: usize) {
+        let Ok(mut recent) = self.recent.lock() else { return };
+        recent.push_back(logs);
+        while recent.len() > retain {
+            recent.pop_front();
+        }
+    }
+
+    pub fn get(&self, invocation_id: &str) -> Option<InvocationLogs> {
+        let recent = self.recent.lock().ok()?;
+        recent.iter().rev().find(|logs| logs.tags.invocation_id == invocation_id).cloned()
+    }
+}
+
+fn read_text(caller: &mut Caller<'_, StoreState>, ptr: u32, len: u32) -> wasmtime::Result<String> {
+    let max = caller.data().log.config.max_message_bytes;
+    let bytes = read_guest(caller, ptr, len.min(max as u32))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Registers `voike.log` and the `voike.span_*` imports. Strings are UTF-8 ptr/len pairs in the
+/// guest's exported `memory`.
+pub fn add_to_linker(linker: &mut Linker<StoreState>) -> wasmtime::Result<()> {
+    // log(level, msg, msg_len, fields_json, fields_len); `fields_len = 0` for no fields.
+    linker.func_wrap(
+        "voike",
+        "log",
+        |mut caller: Caller<'_, StoreState>, level: i32, msg_ptr: u32, msg_len: u32, fields_ptr: u32, fields_len: u32| -> wasmtime::Result<()> {
+            let level = LogLevel::from_guest(level);
+            if level < caller.data().log.config.level {
+                return Ok(());
+            }
+            let message = read_text(&mut caller, msg_ptr, msg_len)?;
+            let fields = match fields_len {
+                0 => Value::Null,
+                _ => {
+                    let raw = read_text(&mut caller, fields_ptr, fields_len)?;
+                    serde_json::from_str(&raw).unwrap_or(Value::String(raw))
+                }
+            };
+            caller.data_mut().log.emit(level, message, fields);
+            Ok(())
+        },
+    )?;
+    // span_enter(name, name_len) -> span id, or -1 if too many spans are open
+    linker.func_wrap(
+        "voike",
+        "span_enter",
+        |mut caller: Caller<'_, StoreState>, name_ptr: u32, name_len: u32| -> wasmtime::Result<i32> {
+            let name = read_text(&mut caller, name_ptr, name_len.min(MAX_SPAN_NAME_BYTES))?;
+            Ok(caller.data_mut().log.enter(name))
+        },
+    )?;
+    // span_exit(span id) -> 0, or -1 if the span is not open
+    linker.func_wrap("voike", "span_exit", |mut caller: Caller<'_, StoreState>, id: i32| -> i32 {
+        if caller.data_mut().log.exit(id) {
+            0
+        } else {
+            -1
+        }
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(config: LogConfig) -> GuestLog {
+        let tags = InvocationTags {
+            invocation_id: "test-1".to_string(),
+            module: "m".to_string(),
+            project: None,
+        };
+        GuestLog::new(tags, config)
+    }
+
+    #[test]
+    fn keeps_a_bounded_tail_above_the_level() {
+        let mut log = log(LogConfig {
+            tail_lines: 2,
+            ..LogConfig::default()
+        });
+        log.emit(LogLevel::Debug, "hidden".to_string(), Value::Null);
+        for message in ["one", "two", "three"] {
+            log.emit(LogLevel::Info, message.to_string(), Value::Null);
+        }
+        let logs = log.finish();
+        assert_eq!(logs.logs.iter().map(|record| record.message.as_str()).collect::<Vec<_>>(), ["two", "three"]);
+        assert_eq!(logs.dropped, 1);
+    }
+
+    #[test]
+    fn tags_records_with_open_spans() {
+        let mut log = log(LogConfig {
+            level: LogLevel::Debug,
+            ..LogConfig::default()
+        });
+        let outer = log.enter("handle".to_string());
+        log.enter("query".to_string());
+        log.emit(LogLevel::Info, "rows".to_string(), serde_json::json!({ "n": 3 }));
+        assert!(log.exit(outer));
+        assert!(!log.exit(outer));
+        let records = log.finish().logs;
+        assert_eq!(records[0].spans, ["handle", "query"]);
+        // Closing the outer span also closes the inner one, innermost first.
+        assert_eq!(records[1].message, "exit query");
+        assert_eq!(records[2].message, "exit handle");
+    }
+
+    #[test]
+    fn caps_the_span_depth() {
+        let mut log = log(LogConfig::default());
+        let spans: Vec<i32> = (0..MAX_SPAN_DEPTH).map(|depth| log.enter(format!("span-{}", depth))).collect();
+        assert!(spans.iter().all(|id| *id > 0));
+        assert_eq!(log.enter("one too many".to_string()), -1);
+        assert_eq!(log.spans.len(), MAX_SPAN_DEPTH);
+        assert!(log.exit(spans[MAX_SPAN_DEPTH - 1]));
+        assert!(log.enter("again".to_string()) > 0);
+    }
+}
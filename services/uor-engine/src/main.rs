@@ -1,99 +1,323 @@
-use serde::Serialize;
+mod api;
+mod apps;
+mod auth;
+mod cache;
+mod config;
+mod control_plane;
+mod cron;
+mod events;
+mod grid;
+mod health;
+mod history;
+mod invocations;
+mod jobs;
+mod kv;
+mod limits;
+mod logging;
+mod mesh;
+mod metrics;
+mod process;
+mod registry;
+mod schedules;
+mod shutdown;
+mod sleep;
+mod status;
+mod tls;
+mod vasm;
+mod wasi;
+mod wasm;
+
+use cache::ModuleCache;
+use config::ConfigStore;
+use events::StreamQuery;
+use history::{HistoryQuery, StatusHistory};
+use registry::ModuleRegistry;
+use sleep::HysteresisClassifier;
+use status::{RuntimeStatus, SharedState};
+use futures_util::future;
+use tls::Certificates;
 use std::{
-    env,
-    net::SocketAddr,
     sync::{Arc, Mutex},
-    time::Instant,
+    time::{Duration, Instant},
 };
-use sysinfo::{System, SystemExt};
-use tokio::time::{interval, Duration};
+use tokio::{sync::watch, time};
 use warp::Filter;
-use wasmtime::{Engine, Module};
+use wasm::WasmRuntime;
 
-#[derive(Clone, Serialize, Default)]
-struct RuntimeStatus {
-    cpu_percent: f32,
-    rss_mb: f32,
-    uptime_seconds: u64,
-    sleep_state: String,
-    tickless: bool,
-    wasm_loaded: bool,
-    wasm_module: Option<String>,
-    timestamp: String,
-}
+/// Largest module accepted by `POST /modules`.
+const MAX_MODULE_BYTES: u64 = 32 * 1024 * 1024;
 
-#[derive(Clone)]
-struct SharedState {
-    status: RuntimeStatus,
-}
+/// How long open connections get to finish once the listeners stop accepting.
+const SERVER_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() {
-    let bind_addr: SocketAddr = env::var("UOR_BIND_ADDR")
-        .unwrap_or_else(|_| "0.0.0.0:9090".to_string())
-        .parse()
-        .expect("invalid UOR_BIND_ADDR");
+    let config = ConfigStore::from_env().unwrap_or_else(|err| panic!("invalid config: {}", err));
+    let boot = config.get();
     let start = Instant::now();
-    let wasm_module = env::var("UOR_WASM_MODULE").ok();
-    let wasm_loaded = wasm_module
-        .as_ref()
-        .map(|path| warm_wasm(path).map(|_| true).unwrap_or(false))
-        .unwrap_or(false);
+    let engine = wasm::build_engine().expect("failed to configure wasmtime engine");
+    let cache_dir = Some(boot.storage.cache_dir.clone()).filter(|dir| !dir.as_os_str().is_empty());
+    let cache = ModuleCache::new(&engine, cache_dir);
+    let runtime = Arc::new(WasmRuntime::new(engine, ModuleRegistry::default(), cache, config).expect("failed to link WASI imports"));
+    runtime.preload(&boot.modules.preload);
+    if !boot.auth.enabled() {
+        eprintln!("[uor-engine] auth disabled: no admin token or project keys configured");
+    }
 
     let state = Arc::new(Mutex::new(SharedState {
         status: RuntimeStatus {
-            wasm_loaded,
-            wasm_module: wasm_module.clone(),
             tickless: true,
             ..RuntimeStatus::default()
         },
+        history: StatusHistory::new(boot.sampling.history_samples),
+        sampled_at: start,
     }));
 
-    tokio::spawn(sample_metrics(state.clone(), start, wasm_loaded, wasm_module.clone()));
-
-    let state_filter = warp::any().map(move || state.clone());
-    let status_route = warp::path("status").and(warp::get()).and(state_filter).map(|state: Arc<Mutex<SharedState>>| {
-        let payload = state
-            .lock()
-            .map(|guard| guard.status.clone())
-            .unwrap_or_default();
-        warp::reply::json(&payload)
+    let sampler = tokio::spawn(status::sample_metrics(
+        state.clone(),
+        start,
+        runtime.clone(),
+        Box::new(HysteresisClassifier::new(boot.sleep.clone())),
+    ));
+    tokio::spawn(config::watch_for_changes(runtime.clone()));
+    tokio::spawn(jobs::dispatch(runtime.clone()));
+    tokio::spawn(schedules::run(runtime.clone()));
+    let heartbeat = tokio::spawn(mesh::run(runtime.clone(), state.clone()));
+    tokio::spawn(grid::run(runtime.clone()));
+    let certificates = boot.tls.enabled().then(|| {
+        let certificates = Arc::new(Certificates::load(&boot.tls).unwrap_or_else(|err| panic!("invalid TLS config: {}", err)));
+        tokio::spawn(tls::watch_for_changes(runtime.clone(), certificates.clone()));
+        certificates
     });
 
-    println!("[uor-engine] listening on {}", bind_addr);
-    warp::serve(status_route).run(bind_addr).await;
-}
+    let state_filter = {
+        let state = state.clone();
+        warp::any().map(move || state.clone())
+    };
+    let caller = auth::caller(runtime.clone());
+    let status_access = auth::status_access(runtime.clone());
+    let status_route = warp::path!("status")
+        .and(warp::get())
+        .and(status_access.clone())
+        .and(state_filter.clone())
+        .map(api::status);
+
+    let waker = runtime.clone();
+    let wake_sampler = warp::any().map(move || waker.wake.notify_one()).untuple_one();
+    let runtime_filter = {
+        let runtime = runtime.clone();
+        warp::any().map(move || runtime.clone())
+    };
+    let invoke_route = warp::path!("invoke" / String / String)
+        .and(warp::post())
+        .and(caller.clone())
+        .and(warp::body::bytes())
+        .and(runtime_filter.clone())
+        .and_then(api::invoke);
+    let upload_route = warp::path!("modules")
+        .and(warp::post())
+        .and(warp::query::<api::UploadQuery>())
+        .and(caller.clone())
+        .and(warp::body::content_length_limit(MAX_MODULE_BYTES))
+        .and(warp::body::bytes())
+        .and(runtime_filter.clone())
+        .and_then(api::upload_module);
+    let list_route = warp::path!("modules")
+        .and(warp::get())
+        .and(caller.clone())
+        .and(runtime_filter.clone())
+        .and_then(api::list_modules);
+    let delete_route = warp::path!("modules" / String)
+        .and(warp::delete())
+        .and(caller.clone())
+        .and(runtime_filter.clone())
+        .and_then(api::delete_module);
+    let invocations_route = warp::path!("invocations")
+        .and(warp::get())
+        .and(warp::query::<invocations::InvocationQuery>())
+        .and(caller.clone())
+        .and(runtime_filter.clone())
+        .map(api::list_invocations);
+    let invocation_route = warp::path!("invocations" / String)
+        .and(warp::get())
+        .and(caller.clone())
+        .and(runtime_filter.clone())
+        .map(api::invocation);
+    let logs_route = warp::path!("invocations" / String / "logs")
+        .and(warp::get())
+        .and(caller.clone())
+        .and(runtime_filter.clone())
+        .map(api::invocation_logs);
+    let submit_job_route = warp::path!("jobs")
+        .and(warp::post())
+        .and(caller.clone())
+        .and(warp::body::bytes())
+        .and(runtime_filter.clone())
+        .map(api::submit_job);
+    let job_route = warp::path!("jobs" / String)
+        .and(warp::get())
+        .and(caller.clone())
+        .and(runtime_filter.clone())
+        .map(api::job);
+    let cancel_job_route = warp::path!("jobs" / String)
+        .and(warp::delete())
+        .and(caller.clone())
+        .and(runtime_filter.clone())
+        .map(api::cancel_job);
+    let schedules_route = warp::path!("schedules")
+        .and(warp::get())
+        .and(caller.clone())
+        .and(runtime_filter.clone())
+        .map(api::list_schedules);
+    let create_schedule_route = warp::path!("schedules")
+        .and(warp::post())
+        .and(caller.clone())
+        .and(warp::body::bytes())
+        .and(runtime_filter.clone())
+        .map(api::create_schedule);
+    let delete_schedule_route = warp::path!("schedules" / String)
+        .and(warp::delete())
+        .and(caller.clone())
+        .and(runtime_filter.clone())
+        .map(api::delete_schedule);
+    let wasi_route = warp::path!("modules" / String / "wasi")
+        .and(warp::put())
+        .and(caller.clone())
+        .and(warp::body::json())
+        .and(runtime_filter.clone())
+        .and_then(api::configure_wasi);
+    let mount_app_route = warp::path!("modules" / String / "app")
+        .and(warp::put())
+        .and(caller.clone())
+        .and(warp::body::json())
+        .and(runtime_filter.clone())
+        .map(api::mount_app);
+    let unmount_app_route = warp::path!("modules" / String / "app")
+        .and(warp::delete())
+        .and(caller.clone())
+        .and(runtime_filter.clone())
+        .map(api::unmount_app);
+    let app_route = warp::path("apps")
+        .and(warp::path::param::<String>())
+        .and(apps::request_head())
+        .and(auth::optional_caller(runtime.clone()))
+        .and(warp::body::stream())
+        .and(runtime_filter.clone())
+        .and_then(api::serve_app);
+    let vasm_route = warp::path!("vasm" / "run")
+        .and(warp::post())
+        .and(caller)
+        .and(warp::body::bytes())
+        .and(runtime_filter.clone())
+        .and_then(api::run_vasm);
+    let history_route = warp::path!("status" / "history")
+        .and(warp::get())
+        .and(status_access.clone())
+        .and(warp::query::<HistoryQuery>())
+        .and(state_filter.clone())
+        .and_then(api::status_history);
+    let stream_route = warp::path!("status" / "stream")
+        .and(warp::get())
+        .and(status_access.clone())
+        .and(warp::query::<StreamQuery>())
+        .and(runtime_filter.clone())
+        .map(api::status_stream);
+    let ws_route = warp::path!("status" / "ws")
+        .and(status_access.clone())
+        .and(warp::ws())
+        .and(warp::query::<StreamQuery>())
+        .and(runtime_filter.clone())
+        .map(api::status_ws);
+    let metrics_route = warp::path!("metrics")
+        .and(warp::get())
+        .and(status_access)
+        .and(state_filter.clone())
+        .and(runtime_filter.clone())
+        .and_then(api::metrics);
+    let healthz_route = warp::path!("healthz")
+        .and(warp::get())
+        .and(state_filter.clone())
+        .and(runtime_filter.clone())
+        .map(api::healthz);
+    let readyz_route = warp::path!("readyz")
+        .and(warp::get())
+        .and(state_filter)
+        .and(runtime_filter.clone())
+        .map(api::readyz);
+    let config_route = warp::path!("config")
+        .and(warp::get())
+        .and(auth::admin(runtime.clone()))
+        .and(runtime_filter)
+        .map(api::config);
 
-async fn sample_metrics(state: Arc<Mutex<SharedState>>, start: Instant, wasm_loaded: bool, wasm_module: Option<String>) {
-    let mut sys = System::new_all();
-    let mut ticker = interval(Duration::from_millis(500));
-    loop {
-        ticker.tick().await;
-        sys.refresh_cpu();
-        sys.refresh_memory();
-        let cpu = sys.global_cpu_info().cpu_usage();
-        let rss_mb = sys.used_memory() as f32 / 1024.0;
-        let uptime = start.elapsed().as_secs();
-        let sleep_state = if cpu < 5.0 { "idle" } else if cpu < 40.0 { "warm" } else { "active" };
-        let timestamp = chrono::Utc::now().to_rfc3339();
-        let mut guard = match state.lock() {
-            Ok(g) => g,
-            Err(_) => continue,
+    let routes = wake_sampler.and(
+        status_route
+            .or(history_route)
+            .or(stream_route)
+            .or(ws_route)
+            .or(metrics_route)
+            .or(invoke_route)
+            .or(upload_route)
+            .or(list_route)
+            .or(delete_route)
+            .or(wasi_route)
+            .or(mount_app_route)
+            .or(unmount_app_route)
+            .or(invocations_route)
+            .or(invocation_route)
+            .or(logs_route)
+            .or(submit_job_route)
+            .or(job_route)
+            .or(cancel_job_route)
+            .or(schedules_route)
+            .or(create_schedule_route)
+            .or(delete_schedule_route)
+            .or(vasm_route)
+            .or(config_route)
+            .or(healthz_route)
+            .or(readyz_route)
+            .or(app_route)
+            .recover(api::recover),
+    );
+
+    let (stop, stopped) = watch::channel(false);
+    let mut servers = Vec::new();
+    for addr in &boot.server.bind {
+        let mut stopped = stopped.clone();
+        let shutdown = async move {
+            let _ = stopped.wait_for(|stopped| *stopped).await;
         };
-        guard.status = RuntimeStatus {
-            cpu_percent: cpu,
-            rss_mb,
-            uptime_seconds: uptime,
-            sleep_state: sleep_state.to_string(),
-            tickless: true,
-            wasm_loaded,
-            wasm_module: wasm_module.clone(),
-            timestamp,
+        let server = match &certificates {
+            Some(certificates) => {
+                let listener = tokio::net::TcpListener::bind(addr).await.unwrap_or_else(|err| panic!("failed to bind {}: {}", addr, err));
+                println!("[uor-engine] listening on {} (TLS{})", addr, if boot.tls.client_ca.is_some() { ", client certificates required" } else { "" });
+                tokio::spawn(tls::serve(listener, warp::service(routes.clone()), certificates.clone(), shutdown))
+            }
+            None => {
+                let (bound, server) = warp::serve(routes.clone()).bind_with_graceful_shutdown(*addr, shutdown);
+                println!("[uor-engine] listening on {}", bound);
+                tokio::spawn(server)
+            }
         };
+        servers.push(server);
     }
-}
 
-fn warm_wasm(path: &str) -> Result<(), wasmtime::Error> {
-    let engine = Engine::default();
-    Module::from_file(&engine, path).map(|_| ())
+    // Keep serving `/status` (now `draining`) while in-flight invocations finish, then stop listening.
+    shutdown::signal_received().await;
+    // Stop heartbeats before deregistering so a late one cannot re-register the node.
+    heartbeat.abort();
+    let _ = heartbeat.await;
+    let abandoned = shutdown::drain(&runtime).await;
+    runtime.events.close();
+    stop.send_replace(true);
+    if time::timeout(SERVER_CLOSE_TIMEOUT, future::join_all(servers)).await.is_err() {
+        eprintln!("[uor-engine] connections still open after {} s, closing them", SERVER_CLOSE_TIMEOUT.as_secs());
+    }
+    sampler.abort();
+    shutdown::flush(&runtime, &state);
+    println!("[uor-engine] shut down");
+    if abandoned > 0 {
+        std::process::exit(1);
+    }
 }
+
@@ -0,0 +1,347 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time;
+use warp::http::Method;
+
+use crate::{
+    config::EngineConfig,
+    control_plane::{self, ControlPlaneConfig},
+    health,
+    limits::env_or,
+    status::{RuntimeStatus, SharedState},
+    wasm::WasmRuntime,
+};
+
+/// Deregistering on shutdown gives up after this long rather than hold up the drain.
+const DEREGISTER_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// `[node]` config section: how this engine appears in `/mesh/nodes`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    /// Defaults to the host name.
+    pub id: String,
+    pub roles: Vec<String>,
+    pub region: Option<String>,
+    /// Where peers reach this node, by protocol. Defaults to `http` on the first bind address.
+    pub addresses: BTreeMap<String, String>,
+    pub heartbeat_interval_ms: u64,
+    /// Longest wait between attempts while the control plane is unreachable.
+    pub max_backoff_ms: u64,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            roles: vec!["wasm".to_string()],
+            region: None,
+            addresses: BTreeMap::new(),
+            heartbeat_interval_ms: 5_000,
+            max_backoff_ms: 60_000,
+        }
+    }
+}
+
+impl NodeConfig {
+    /// Applies `UOR_NODE_ID`, `UOR_NODE_ROLES` (comma-separated), `UOR_NODE_REGION`,
+    /// `UOR_NODE_HTTP_ADDRESS` and `UOR_HEARTBEAT_INTERVAL_MS`.
+    pub fn apply_env(&mut self) {
+        if let Ok(id) = env::var("UOR_NODE_ID") {
+            self.id = id;
+        }
+        if let Ok(roles) = env::var("UOR_NODE_ROLES") {
+            self.roles = roles.split(',').map(str::trim).filter(|role| !role.is_empty()).map(str::to_string).collect();
+        }
+        if let Ok(region) = env::var("UOR_NODE_REGION") {
+            self.region = Some(region).filter(|region| !region.is_empty());
+        }
+        if let Ok(address) = env::var("UOR_NODE_HTTP_ADDRESS") {
+            self.addresses.insert("http".to_string(), address);
+        }
+        self.heartbeat_interval_ms = env_or("UOR_HEARTBEAT_INTERVAL_MS", self.heartbeat_interval_ms);
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.heartbeat_interval_ms < 1_000 {
+            return Err("node.heartbeat_interval_ms must be at least 1000".to_string());
+        }
+        if self.max_backoff_ms < self.heartbeat_interval_ms {
+            return Err("node.max_backoff_ms must be at least node.heartbeat_interval_ms".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The id this node registers under.
+pub fn node_id(config: &EngineConfig) -> String {
+    if !config.node.id.is_empty() {
+        return config.node.id.clone();
+    }
+    sysinfo::System::host_name().filter(|name| !name.is_empty()).unwrap_or_else(|| "uor-engine".to_string())
+}
+
+/// Where peers reach this node, by protocol.
+pub fn addresses(config: &EngineConfig) -> BTreeMap<String, String> {
+    if !config.node.addresses.is_empty() {
+        return config.node.addresses.clone();
+    }
+    let scheme = if config.tls.enabled() { "https" } else { "http" };
+    let Some(bind) = config.server.bind.first() else { return BTreeMap::new() };
+    let host = if bind.ip().is_unspecified() { node_id(config) } else { bind.ip().to_string() };
+    BTreeMap::from([("http".to_string(), format!("{}://{}:{}", scheme, host, bind.port()))])
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnnouncedModule {
+    pub name: String,
+    pub hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+}
+
+/// `PUT /mesh/nodes/{id}` body.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Announcement {
+    pub node_id: String,
+    pub roles: Vec<String>,
+    pub addresses: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// `healthy`, or `degraded` while `/readyz` fails.
+    pub status: &'static str,
+    pub version: &'static str,
+    pub started_at: String,
+    /// The control plane can consider the node gone after missing a few of these.
+    pub heartbeat_interval_ms: u64,
+    pub modules: Vec<AnnouncedModule>,
+    pub runtime: RuntimeStatus,
+}
+
+fn announcement(runtime: &WasmRuntime, state: &Arc<Mutex<SharedState>>, started_at: &str) -> Announcement {
+    let config = runtime.config.get();
+    let modules = runtime
+        .registry
+        .lock()
+        .map(|registry| {
+            registry
+                .list()
+                .into_iter()
+                .map(|info| AnnouncedModule {
+                    name: info.name,
+                    hash: info.hash,
+                    project: info.project,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Announcement {
+        node_id: node_id(&config),
+        roles: config.node.roles.clone(),
+        addresses: addresses(&config),
+        region: config.node.region.clone(),
+        status: if health::readiness(state, runtime).is_ok() { "healthy" } else { "degraded" },
+        version: env!("CARGO_PKG_VERSION"),
+        started_at: started_at.to_string(),
+        heartbeat_interval_ms: config.node.heartbeat_interval_ms,
+        modules,
+        runtime: state.lock().map(|guard| guard.status.clone()).unwrap_or_default(),
+    }
+}
+
+fn node_path(node_id: &str) -> String {
+    format!("/mesh/nodes/{}", control_plane::path_segment(node_id))
+}
+
+/// Doubles the wait with every consecutive failure, from one interval up to `max_backoff_ms`.
+fn backoff(node: &NodeConfig, failures: u32) -> Duration {
+    let wait = node.heartbeat_interval_ms.saturating_mul(1 << failures.min(16));
+    Duration::from_millis(wait.min(node.max_backoff_ms))
+}
+
+/// Where the node is registered, so a changed URL or id can deregister the old entry.
+#[derive(Clone)]
+struct Registration {
+    control_plane: ControlPlaneConfig,
+    node_id: String,
+}
+
+impl Registration {
+    fn same_entry(&self, other: &Registration) -> bool {
+        self.control_plane.url == other.control_plane.url && self.node_id == other.node_id
+    }
+}
+
+/// Announces the node to `control_plane.url` every `node.heartbeat_interval_ms`, backing off
+/// while it is unreachable, and straight away when the config changes. With no URL configured
+/// the task only wakes on config reloads.
+pub async fn run(runtime: Arc<WasmRuntime>, state: Arc<Mutex<SharedState>>) {
+    let started_at = Utc::now().to_rfc3339();
+    let mut config = runtime.config.subscribe();
+    let mut registered: Option<Registration> = None;
+    let mut failures = 0u32;
+    loop {
+        let current = runtime.config.get();
+        let wanted = Some(Registration {
+            control_plane: current.control_plane.clone(),
+            node_id: node_id(&current),
+        })
+        .filter(|wanted| wanted.control_plane.enabled());
+        if let Some(previous) = registered.take_if(|previous| !wanted.as_ref().is_some_and(|wanted| previous.same_entry(wanted))) {
+            leave(&previous).await;
+        }
+        let wait = match &wanted {
+            None => None,
+            Some(wanted) => {
+                let body = announcement(&runtime, &state, &started_at);
+                match control_plane::call::<Value>(&wanted.control_plane, Method::PUT, &node_path(&wanted.node_id), Some(&body)).await {
+                    Ok(_) => {
+                        if failures > 0 || registered.is_none() {
+                            println!("[uor-engine] registered with {} as {}", wanted.control_plane.url, wanted.node_id);
+                        }
+                        failures = 0;
+                        registered = Some(wanted.clone());
+                        Some(Duration::from_millis(current.node.heartbeat_interval_ms))
+                    }
+                    Err(err) => {
+                        let wait = backoff(&current.node, failures);
+                        if failures == 0 {
+                            eprintln!("[uor-engine] heartbeat to {} failed, retrying with backoff: {}", wanted.control_plane.url, err);
+                        }
+                        failures = failures.saturating_add(1);
+                        Some(wait)
+                    }
+                }
+            }
+        };
+        tokio::select! {
+            _ = time::sleep(wait.unwrap_or_default()), if wait.is_some() => {}
+            changed = config.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Removes the node from the control plane; called on shutdown after the heartbeat task stops.
+pub async fn deregister(runtime: &WasmRuntime) {
+    let config = runtime.config.get();
+    if !config.control_plane.enabled() {
+        return;
+    }
+    let registration = Registration {
+        control_plane: ControlPlaneConfig {
+            timeout_ms: config.control_plane.timeout_ms.min(DEREGISTER_TIMEOUT.as_millis() as u64),
+            ..config.control_plane.clone()
+        },
+        node_id: node_id(&config),
+    };
+    leave(&registration).await;
+}
+
+async fn leave(registration: &Registration) {
+    let url = &registration.control_plane.url;
+    match control_plane::call::<Value>(&registration.control_plane, Method::DELETE, &node_path(&registration.node_id), None::<&Value>).await {
+        Ok(_) => println!("[uor-engine] deregistered {} from {}", registration.node_id, url),
+        Err(err) => eprintln!("[uor-engine] failed to deregister {} from {}: {}", registration.node_id, url, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::{hyper::body::Bytes, Filter};
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_cap() {
+        let node = NodeConfig {
+            heartbeat_interval_ms: 5_000,
+            max_backoff_ms: 30_000,
+            ..NodeConfig::default()
+        };
+        let waits: Vec<u64> = (0..5).map(|failures| backoff(&node, failures).as_millis() as u64).collect();
+        assert_eq!(waits, [5_000, 10_000, 20_000, 30_000, 30_000]);
+        assert_eq!(backoff(&node, u32::MAX), Duration::from_millis(30_000));
+    }
+
+    #[tokio::test]
+    async fn registers_and_deregisters_against_a_mock_control_plane() {
+        let seen: Arc<Mutex<Vec<Value>>> = Arc::default();
+        let record = seen.clone();
+        let mock = warp::method()
+            .and(warp::path::full())
+            .and(warp::header::optional::<String>("x-voike-admin-token"))
+            .and(warp::body::bytes())
+            .map(move |method: Method, path: warp::path::FullPath, token: Option<String>, body: Bytes| {
+                let body = serde_json::from_slice::<Value>(&body).unwrap_or(Value::Null);
+                let request = serde_json::json!({ "method": method.as_str(), "path": path.as_str(), "token": token, "body": body });
+                record.lock().unwrap().push(request);
+                warp::reply::json(&serde_json::json!({ "ok": true }))
+            });
+        let (addr, server) = warp::serve(mock).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let runtime = WasmRuntime::for_tests(EngineConfig {
+            control_plane: ControlPlaneConfig {
+                url: format!("http://{}/", addr),
+                admin_token: Some("secret".to_string()),
+                ..ControlPlaneConfig::default()
+            },
+            node: NodeConfig {
+                id: "edge 1/a".to_string(),
+                region: Some("eu-west".to_string()),
+                ..NodeConfig::default()
+            },
+            ..EngineConfig::default()
+        });
+        runtime.load_wat("report", "(module)", Some("acme"));
+        let state = Arc::new(Mutex::new(SharedState {
+            status: RuntimeStatus::default(),
+            history: crate::history::StatusHistory::new(1),
+            sampled_at: std::time::Instant::now(),
+        }));
+
+        let heartbeat = tokio::spawn(run(runtime.clone(), state));
+        for _ in 0..100 {
+            if !seen.lock().unwrap().is_empty() {
+                break;
+            }
+            time::sleep(Duration::from_millis(20)).await;
+        }
+        heartbeat.abort();
+        let _ = heartbeat.await;
+        deregister(&runtime).await;
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(
+            (&seen[0]["method"], &seen[0]["path"], &seen[0]["token"]),
+            (&Value::from("PUT"), &Value::from("/mesh/nodes/edge%201%2Fa"), &Value::from("secret"))
+        );
+        let body = &seen[0]["body"];
+        assert_eq!((&body["nodeId"], &body["region"], &body["roles"]), (&Value::from("edge 1/a"), &Value::from("eu-west"), &serde_json::json!(["wasm"])));
+        assert_eq!(body["modules"], serde_json::json!([{ "name": "report", "hash": crate::registry::content_hash(b"(module)"), "project": "acme" }]));
+        assert_eq!((&seen[1]["method"], &seen[1]["path"]), (&Value::from("DELETE"), &Value::from("/mesh/nodes/edge%201%2Fa")));
+    }
+
+    #[tokio::test]
+    async fn reports_an_unreachable_control_plane() {
+        let unreachable = ControlPlaneConfig {
+            url: "http://127.0.0.1:1".to_string(),
+            timeout_ms: 1_000,
+            ..ControlPlaneConfig::default()
+        };
+        let result = control_plane::call::<Value>(&unreachable, Method::PUT, &node_path("edge-1"), Some(&Value::Null)).await;
+        assert!(result.is_err_and(|err| err.status.is_none()));
+    }
+}
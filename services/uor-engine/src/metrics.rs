@@ -0,0 +1,162 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex};
+
+use crate::{registry::ModuleInfo, status::RuntimeStatus, wasm::InvokeError};
+
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Upper bounds (seconds) shared by the compile and invocation latency histograms.
+const BUCKETS: [f64; 11] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+#[derive(Clone, Default)]
+struct Histogram {
+    counts: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bound, count) in BUCKETS.iter().zip(self.counts.iter_mut()) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    compiles: BTreeMap<(String, &'static str), u64>,
+    compile_seconds: BTreeMap<String, Histogram>,
+    invocations: BTreeMap<(String, &'static str), u64>,
+    invocation_seconds: BTreeMap<String, Histogram>,
+    traps: BTreeMap<String, u64>,
+    limit_violations: BTreeMap<(String, &'static str), u64>,
+}
+
+/// Engine counters and histograms, labelled by module name.
+#[derive(Default)]
+pub struct Metrics {
+    counters: Mutex<Counters>,
+}
+
+impl Metrics {
+    pub fn record_compile(&self, info: &ModuleInfo) {
+        self.update(|c| {
+            *c.compiles.entry((info.name.clone(), info.source)).or_default() += 1;
+            c.compile_seconds.entry(info.name.clone()).or_default().observe(info.load_ms / 1000.0);
+        });
+    }
+
+    pub fn record_invocation(&self, module: &str, seconds: f64, error: Option<&InvokeError>) {
+        self.update(|c| {
+            let outcome = if error.is_some() { "error" } else { "ok" };
+            *c.invocations.entry((module.to_string(), outcome)).or_default() += 1;
+            c.invocation_seconds.entry(module.to_string()).or_default().observe(seconds);
+            match error.map(|err| err.error) {
+                Some("trap") => *c.traps.entry(module.to_string()).or_default() += 1,
+                Some(limit @ ("fuel_exhausted" | "timeout" | "memory_limit" | "table_limit")) => {
+                    *c.limit_violations.entry((module.to_string(), limit)).or_default() += 1
+                }
+                _ => {}
+            }
+        });
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut Counters)) {
+        if let Ok(mut counters) = self.counters.lock() {
+            apply(&mut counters);
+        }
+    }
+
+    /// Renders the status gauges and engine counters in OpenMetrics text format.
+    pub fn render(&self, status: &RuntimeStatus) -> String {
+        let mut out = String::new();
+        gauge(&mut out, "uor_cpu_percent", "Engine process CPU as a share of host capacity.", status.cpu_percent as f64);
+        gauge(&mut out, "uor_rss_mb", "Engine process resident set size.", status.rss_mb as f64);
+        gauge(&mut out, "uor_virtual_mb", "Engine process virtual size.", status.virtual_mb as f64);
+        gauge(&mut out, "uor_threads", "Engine process threads.", status.threads as f64);
+        gauge(&mut out, "uor_open_fds", "Engine process open file descriptors.", status.open_fds as f64);
+        gauge(&mut out, "uor_host_cpu_percent", "Host-wide CPU usage.", status.host_cpu_percent as f64);
+        gauge(&mut out, "uor_host_used_memory_mb", "Host-wide used memory.", status.host_used_memory_mb as f64);
+        gauge(&mut out, "uor_host_total_memory_mb", "Host-wide total memory.", status.host_total_memory_mb as f64);
+        gauge(&mut out, "uor_memory_percent", "Host memory in use as a share of total.", status.memory_percent as f64);
+        gauge(&mut out, "uor_queued_invocations", "Invocations waiting for or running on a worker.", status.queued_invocations as f64);
+        gauge(&mut out, "uor_lane_saturation_percent", "Running jobs as a share of the busiest lane's concurrency.", status.lane_saturation_percent as f64);
+        header(&mut out, "uor_jobs_queued", "gauge", "Jobs waiting for a lane slot.");
+        for lane in &status.job_lanes {
+            let _ = writeln!(out, "uor_jobs_queued{{lane=\"{}\"}} {}", lane.lane, lane.queued);
+        }
+        header(&mut out, "uor_jobs_running", "gauge", "Jobs running per lane.");
+        for lane in &status.job_lanes {
+            let _ = writeln!(out, "uor_jobs_running{{lane=\"{}\"}} {}", lane.lane, lane.running);
+        }
+        gauge(&mut out, "uor_network_kb_per_sec", "Host network traffic, received plus transmitted.", status.network_kb_per_sec as f64);
+        gauge(&mut out, "uor_uptime_seconds", "Seconds since the engine started.", status.uptime_seconds as f64);
+        gauge(&mut out, "uor_modules_loaded", "Modules in the registry.", status.wasm_modules.len() as f64);
+        header(&mut out, "uor_sleep_state", "gauge", "Current sleep state (1 for the active state).");
+        for state in ["idle", "warm", "active", "draining"] {
+            let value = if status.sleep_state == state { 1 } else { 0 };
+            let _ = writeln!(out, "uor_sleep_state{{state=\"{}\"}} {}", state, value);
+        }
+        header(&mut out, "uor_module_cache_lookups", "counter", "Module loads by precompiled cache result.");
+        let cache = &status.module_cache;
+        for (result, value) in [("hit", cache.hits), ("miss", cache.misses), ("rejected", cache.rejected)] {
+            let _ = writeln!(out, "uor_module_cache_lookups_total{{result=\"{}\"}} {}", result, value);
+        }
+
+        let Ok(c) = self.counters.lock() else {
+            out.push_str("# EOF\n");
+            return out;
+        };
+        header(&mut out, "uor_module_compiles", "counter", "Module loads by source (compiled or cache).");
+        for ((module, source), value) in &c.compiles {
+            let _ = writeln!(out, "uor_module_compiles_total{{module=\"{}\",source=\"{}\"}} {}", escape(module), source, value);
+        }
+        histogram(&mut out, "uor_module_compile_seconds", "Time to make a module ready, compiled or deserialized.", &c.compile_seconds);
+        header(&mut out, "uor_invocations", "counter", "Invocations by outcome.");
+        for ((module, outcome), value) in &c.invocations {
+            let _ = writeln!(out, "uor_invocations_total{{module=\"{}\",outcome=\"{}\"}} {}", escape(module), outcome, value);
+        }
+        histogram(&mut out, "uor_invocation_seconds", "Invocation wall-clock latency.", &c.invocation_seconds);
+        header(&mut out, "uor_traps", "counter", "Invocations that trapped.");
+        for (module, value) in &c.traps {
+            let _ = writeln!(out, "uor_traps_total{{module=\"{}\"}} {}", escape(module), value);
+        }
+        header(&mut out, "uor_limit_violations", "counter", "Invocations stopped by an execution limit.");
+        for ((module, limit), value) in &c.limit_violations {
+            let _ = writeln!(out, "uor_limit_violations_total{{module=\"{}\",limit=\"{}\"}} {}", escape(module), limit, value);
+        }
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    header(out, name, "gauge", help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, series: &BTreeMap<String, Histogram>) {
+    header(out, name, "histogram", help);
+    for (module, hist) in series {
+        let module = escape(module);
+        for (bound, count) in BUCKETS.iter().zip(hist.counts.iter()) {
+            let _ = writeln!(out, "{}_bucket{{module=\"{}\",le=\"{}\"}} {}", name, module, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{module=\"{}\",le=\"+Inf\"}} {}", name, module, hist.count);
+        let _ = writeln!(out, "{}_sum{{module=\"{}\"}} {}", name, module, hist.sum);
+        let _ = writeln!(out, "{}_count{{module=\"{}\"}} {}", name, module, hist.count);
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
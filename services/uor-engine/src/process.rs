@@ -0,0 +1,46 @@
+use std::fs;
+use sysinfo::{Pid, System};
+
+/// Resource usage of the engine process itself, as opposed to the whole host.
+#[derive(Clone, Copy, Default)]
+pub struct ProcessStats {
+    pub rss_mb: f32,
+    pub virtual_mb: f32,
+    /// Share of total host CPU capacity, so it is comparable to the host-wide figure.
+    pub cpu_percent: f32,
+    pub threads: u32,
+    pub open_fds: u32,
+}
+
+/// Samples process stats for `pid` from an already refreshed `sys`.
+pub fn sample(sys: &System, pid: Pid) -> ProcessStats {
+    let Some(process) = sys.process(pid) else {
+        return ProcessStats::default();
+    };
+    let cpus = sys.cpus().len().max(1) as f32;
+    ProcessStats {
+        rss_mb: bytes_to_mb(process.memory()),
+        virtual_mb: bytes_to_mb(process.virtual_memory()),
+        cpu_percent: process.cpu_usage() / cpus,
+        threads: thread_count().unwrap_or(0),
+        open_fds: open_fd_count().unwrap_or(0),
+    }
+}
+
+pub fn bytes_to_mb(bytes: u64) -> f32 {
+    bytes as f32 / (1024.0 * 1024.0)
+}
+
+/// Reads `Threads:` from `/proc/self/status`; `None` off Linux.
+fn thread_count() -> Option<u32> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Threads:"))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Counts entries in `/proc/self/fd`; `None` off Linux.
+fn open_fd_count() -> Option<u32> {
+    fs::read_dir("/proc/self/fd").ok().map(|entries| entries.count() as u32)
+}
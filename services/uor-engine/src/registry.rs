@@ -0,0 +1,143 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, path::Path};
+use wasmtime::{Engine, Module};
+
+use crate::{
+    apps::AppMount,
+    cache::{Compiled, ModuleCache},
+    kv::KvUsage,
+    wasi::WasiConfig,
+    wasm::LoadedModule,
+};
+
+/// Listing entry returned by `GET /modules`.
+#[derive(Clone, Serialize)]
+pub struct ModuleInfo {
+    pub name: String,
+    pub hash: String,
+    pub size_bytes: usize,
+    /// `"compiled"` or `"cache"`.
+    pub source: &'static str,
+    pub load_ms: f64,
+    pub loaded_at: String,
+    pub last_used: Option<String>,
+    /// Owning project; `None` for shared modules loaded by an operator.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    pub wasi: WasiConfig,
+    /// Set when the module is served under `/apps/{name}/` through `PUT /modules/{name}/app`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app: Option<AppMount>,
+    /// Key-value store usage; filled in for listings only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kv: Option<KvUsage>,
+}
+
+struct Entry {
+    info: ModuleInfo,
+    module: Module,
+}
+
+/// Compiled modules keyed by name, each tagged with the SHA-256 of its source bytes.
+#[derive(Default)]
+pub struct ModuleRegistry {
+    entries: HashMap<String, Entry>,
+}
+
+impl ModuleRegistry {
+    /// Returns the existing entry when `bytes` hash to the module already registered under `name`.
+    pub fn lookup_unchanged(&self, name: &str, bytes: &[u8]) -> Option<ModuleInfo> {
+        let hash = content_hash(bytes);
+        self.entries
+            .get(name)
+            .filter(|entry| entry.info.hash == hash)
+            .map(|entry| entry.info.clone())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ModuleInfo> {
+        self.entries.get(name).map(|entry| &entry.info)
+    }
+
+    pub fn insert(&mut self, name: &str, bytes: &[u8], compiled: Compiled, project: Option<String>) -> ModuleInfo {
+        let info = ModuleInfo {
+            name: name.to_string(),
+            hash: content_hash(bytes),
+            size_bytes: bytes.len(),
+            source: compiled.source,
+            load_ms: compiled.load_ms,
+            loaded_at: chrono::Utc::now().to_rfc3339(),
+            last_used: None,
+            project,
+            // A re-upload keeps the sandbox configured for the previous version.
+            wasi: self.entries.get(name).map(|entry| entry.info.wasi.clone()).unwrap_or_default(),
+            app: self.entries.get(name).and_then(|entry| entry.info.app.clone()),
+            kv: None,
+        };
+        self.entries.insert(
+            name.to_string(),
+            Entry {
+                info: info.clone(),
+                module: compiled.module,
+            },
+        );
+        info
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<ModuleInfo> {
+        self.entries.remove(name).map(|entry| entry.info)
+    }
+
+    pub fn set_wasi(&mut self, name: &str, wasi: WasiConfig) -> Option<ModuleInfo> {
+        let entry = self.entries.get_mut(name)?;
+        entry.info.wasi = wasi;
+        Some(entry.info.clone())
+    }
+
+    pub fn set_app(&mut self, name: &str, app: Option<AppMount>) -> Option<ModuleInfo> {
+        let entry = self.entries.get_mut(name)?;
+        entry.info.app = app;
+        Some(entry.info.clone())
+    }
+
+    /// Fetches a module for invocation and stamps its `last_used` time.
+    pub fn checkout(&mut self, name: &str) -> Option<LoadedModule> {
+        let entry = self.entries.get_mut(name)?;
+        entry.info.last_used = Some(chrono::Utc::now().to_rfc3339());
+        Some(LoadedModule {
+            name: entry.info.name.clone(),
+            module: entry.module.clone(),
+            project: entry.info.project.clone(),
+            wasi: entry.info.wasi.clone(),
+        })
+    }
+
+    pub fn list(&self) -> Vec<ModuleInfo> {
+        let mut infos: Vec<ModuleInfo> = self.entries.values().map(|entry| entry.info.clone()).collect();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.entries.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+pub fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Reads and compiles a module file (through the precompiled cache), returning the name it
+/// registers under, its bytes and the compiled module. Slow; call it without holding the registry.
+pub fn compile_file(engine: &Engine, cache: &ModuleCache, path: &Path) -> Result<(String, Vec<u8>, Compiled), wasmtime::Error> {
+    let bytes = std::fs::read(path)?;
+    let compiled = cache.compile(engine, &bytes)?;
+    Ok((name_from_path(path), bytes, compiled))
+}
+
+/// Default registry name for a module preloaded from disk: the file stem.
+fn name_from_path(path: &Path) -> String {
+    path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
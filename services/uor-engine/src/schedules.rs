@@ -0,0 +1,401 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::{sync::Notify, time};
+
+use crate::{
+    cron::CronExpr,
+    jobs::{Lane, NewJob},
+    limits::LimitOverrides,
+    shutdown,
+    wasm::WasmRuntime,
+};
+
+/// Shortest period an interval schedule may use.
+const MIN_INTERVAL_MS: u64 = 1_000;
+/// Longest period an interval schedule may use: a leap year.
+const MAX_INTERVAL_MS: u64 = 366 * 24 * 3_600_000;
+/// Longest delay `jitter_ms` may add to a run.
+const MAX_JITTER_MS: u64 = 3_600_000;
+
+/// `POST /schedules` body.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleRequest {
+    pub name: String,
+    pub module: String,
+    pub export: String,
+    #[serde(default)]
+    pub args: Vec<Value>,
+    #[serde(default)]
+    pub limits: LimitOverrides,
+    /// Five-field cron expression, evaluated in UTC.
+    pub cron: Option<String>,
+    pub interval_ms: Option<u64>,
+    /// Each run is delayed by up to this much, so nodes sharing a schedule do not fire together.
+    #[serde(default)]
+    pub jitter_ms: u64,
+    #[serde(default = "background")]
+    pub lane: Lane,
+}
+
+fn background() -> Lane {
+    Lane::Background
+}
+
+/// A persisted schedule, as returned by `GET /schedules`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Schedule {
+    pub name: String,
+    pub module: String,
+    pub export: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<Value>,
+    #[serde(default)]
+    pub limits: LimitOverrides,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_ms: Option<u64>,
+    #[serde(default)]
+    pub jitter_ms: u64,
+    pub lane: Lane,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// Job queued by the last run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_job: Option<String>,
+    /// Jitter included; recomputed on load, so a missed run is not replayed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_run_at: Option<DateTime<Utc>>,
+}
+
+impl Schedule {
+    pub fn new(request: ScheduleRequest, project: Option<String>, created_by: String) -> Result<Self, String> {
+        if request.name.is_empty() || request.name.contains('/') {
+            return Err("schedule name must be non-empty and contain no `/`".to_string());
+        }
+        match (&request.cron, request.interval_ms) {
+            (Some(cron), None) => {
+                cron.parse::<CronExpr>().map_err(|err| format!("cron `{}`: {}", cron, err))?;
+            }
+            (None, Some(interval_ms)) if !(MIN_INTERVAL_MS..=MAX_INTERVAL_MS).contains(&interval_ms) => {
+                return Err(format!("interval_ms must be between {} and {}", MIN_INTERVAL_MS, MAX_INTERVAL_MS));
+            }
+            (None, Some(_)) => {}
+            _ => return Err("exactly one of `cron` and `interval_ms` must be set".to_string()),
+        }
+        let max_jitter_ms = request.interval_ms.unwrap_or(MAX_JITTER_MS).min(MAX_JITTER_MS);
+        if request.jitter_ms > max_jitter_ms {
+            return Err(format!("jitter_ms must be at most {} for this schedule", max_jitter_ms));
+        }
+        Ok(Self {
+            name: request.name,
+            module: request.module,
+            export: request.export,
+            args: request.args,
+            limits: request.limits,
+            cron: request.cron,
+            interval_ms: request.interval_ms,
+            jitter_ms: request.jitter_ms,
+            lane: request.lane,
+            project,
+            created_by,
+            created_at: Utc::now(),
+            last_run_at: None,
+            last_job: None,
+            next_run_at: None,
+        })
+    }
+
+    /// Sets `next_run_at` to the first run after `now`. Interval runs sit on a grid anchored at
+    /// `created_at`, so jitter does not accumulate; one that fell behind (the engine was down when
+    /// a run was due) runs once right away rather than catching up. A time that cannot be
+    /// represented, e.g. from a hand-edited schedule file, leaves the schedule without a next run.
+    fn plan(&mut self, now: DateTime<Utc>) {
+        let due = match (&self.cron, self.interval_ms) {
+            (Some(cron), _) => cron.parse::<CronExpr>().ok().and_then(|cron| cron.next_after(now)),
+            (None, Some(interval_ms)) => i64::try_from(interval_ms).ok().filter(|interval_ms| *interval_ms > 0).and_then(|interval_ms| {
+                let periods = (now - self.created_at).num_milliseconds().div_euclid(interval_ms).checked_add(1)?;
+                let next = self.created_at.checked_add_signed(Duration::milliseconds(periods.checked_mul(interval_ms)?))?;
+                let missed = next.checked_sub_signed(Duration::milliseconds(interval_ms))?;
+                if missed > self.created_at && self.last_run_at.is_none_or(|last| last < missed) {
+                    Some(now)
+                } else {
+                    Some(next)
+                }
+            }),
+            (None, None) => None,
+        };
+        self.next_run_at = due.and_then(|due| {
+            let jitter = i64::try_from(self.jitter(due)).ok()?;
+            due.checked_add_signed(Duration::milliseconds(jitter))
+        });
+    }
+
+    /// Stable per run: derived from the schedule name and the unjittered due time.
+    fn jitter(&self, due: DateTime<Utc>) -> u64 {
+        if self.jitter_ms == 0 {
+            return 0;
+        }
+        let digest = Sha256::new().chain_update(self.name.as_bytes()).chain_update(due.timestamp_millis().to_le_bytes()).finalize();
+        let mut seed = [0u8; 8];
+        seed.copy_from_slice(&digest[..8]);
+        u64::from_le_bytes(seed) % self.jitter_ms.saturating_add(1)
+    }
+}
+
+/// Schedules by name, saved to `storage.schedules_file` on every change. Changes wake the scheduler.
+pub struct ScheduleStore {
+    path: Option<PathBuf>,
+    schedules: Mutex<BTreeMap<String, Schedule>>,
+    /// Why the file could not be read on boot; changes are refused rather than overwrite it.
+    pub error: Option<String>,
+    changed: Notify,
+}
+
+impl ScheduleStore {
+    /// Reads `path` if it exists; an empty path keeps schedules in memory only.
+    pub fn open(path: &Path) -> Self {
+        let path = Some(path.to_path_buf()).filter(|path| !path.as_os_str().is_empty());
+        let loaded = match &path {
+            Some(path) => match fs::read(path) {
+                Ok(raw) => serde_json::from_slice::<Vec<Schedule>>(&raw).map_err(|err| format!("{}: {}", path.display(), err)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+                Err(err) => Err(format!("{}: {}", path.display(), err)),
+            },
+            None => Ok(Vec::new()),
+        };
+        let (schedules, error) = match loaded {
+            Ok(schedules) => (schedules, None),
+            Err(err) => {
+                eprintln!("[uor-engine] schedules unavailable: {}", err);
+                (Vec::new(), Some(err))
+            }
+        };
+        let now = Utc::now();
+        let schedules = schedules
+            .into_iter()
+            .map(|mut schedule| {
+                schedule.plan(now);
+                (schedule.name.clone(), schedule)
+            })
+            .collect();
+        Self {
+            path,
+            schedules: Mutex::new(schedules),
+            error,
+            changed: Notify::new(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<Schedule> {
+        self.schedules.lock().map(|schedules| schedules.values().cloned().collect()).unwrap_or_default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Schedule> {
+        self.schedules.lock().ok()?.get(name).cloned()
+    }
+
+    /// Adds or replaces a schedule, returning it with its first run planned.
+    pub fn put(&self, mut schedule: Schedule) -> Result<Schedule, String> {
+        self.writable()?;
+        let mut schedules = self.schedules.lock().map_err(|_| "schedules unavailable".to_string())?;
+        schedule.plan(Utc::now());
+        let previous = schedules.insert(schedule.name.clone(), schedule.clone());
+        if let Err(err) = self.save(&schedules) {
+            match previous {
+                Some(previous) => schedules.insert(previous.name.clone(), previous),
+                None => schedules.remove(&schedule.name),
+            };
+            return Err(err);
+        }
+        self.changed.notify_one();
+        Ok(schedule)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<Option<Schedule>, String> {
+        self.writable()?;
+        let mut schedules = self.schedules.lock().map_err(|_| "schedules unavailable".to_string())?;
+        let Some(removed) = schedules.remove(name) else { return Ok(None) };
+        if let Err(err) = self.save(&schedules) {
+            schedules.insert(removed.name.clone(), removed);
+            return Err(err);
+        }
+        self.changed.notify_one();
+        Ok(Some(removed))
+    }
+
+    fn writable(&self) -> Result<(), String> {
+        match &self.error {
+            Some(err) => Err(format!("schedule file failed to load, fix it and restart: {}", err)),
+            None => Ok(()),
+        }
+    }
+
+    fn save(&self, schedules: &BTreeMap<String, Schedule>) -> Result<(), String> {
+        let Some(path) = &self.path else { return Ok(()) };
+        let list: Vec<&Schedule> = schedules.values().collect();
+        let bytes = serde_json::to_vec_pretty(&list).map_err(|err| err.to_string())?;
+        shutdown::write_atomic(path, &bytes).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Schedules due at `now`, each marked as run and planned again.
+    fn take_due(&self, now: DateTime<Utc>) -> Vec<Schedule> {
+        let Ok(mut schedules) = self.schedules.lock() else { return Vec::new() };
+        let mut due = Vec::new();
+        for schedule in schedules.values_mut() {
+            if schedule.next_run_at.is_some_and(|at| at <= now) {
+                schedule.last_run_at = Some(now);
+                schedule.plan(now);
+                due.push(schedule.clone());
+            }
+        }
+        due
+    }
+
+    /// Records the jobs the last runs queued and saves the run times.
+    fn record_runs(&self, jobs: Vec<(String, String)>) {
+        let Ok(mut schedules) = self.schedules.lock() else { return };
+        for (name, job) in jobs {
+            if let Some(schedule) = schedules.get_mut(&name) {
+                schedule.last_job = Some(job);
+            }
+        }
+        if self.error.is_none() {
+            if let Err(err) = self.save(&schedules) {
+                eprintln!("[uor-engine] failed to save schedules: {}", err);
+            }
+        }
+    }
+
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.schedules.lock().ok()?.values().filter_map(|schedule| schedule.next_run_at).min()
+    }
+}
+
+/// Queues a job for every due schedule, then sleeps until the next one is due or the schedules
+/// change. There is no tick: with no schedules the task only wakes on `POST /schedules`.
+pub async fn run(runtime: Arc<WasmRuntime>) {
+    loop {
+        if runtime.is_draining() {
+            return;
+        }
+        let due = runtime.schedules.take_due(Utc::now());
+        if !due.is_empty() {
+            let jobs = due.into_iter().filter_map(|schedule| queue(&runtime, schedule)).collect();
+            runtime.schedules.record_runs(jobs);
+        }
+        let wait = runtime.schedules.next_due().map(|at| (at - Utc::now()).to_std().unwrap_or_default());
+        tokio::select! {
+            _ = time::sleep(wait.unwrap_or_default()), if wait.is_some() => {}
+            _ = runtime.schedules.changed.notified() => {}
+        }
+    }
+}
+
+/// Submits one run to the job queue, returning the schedule and job names on success.
+fn queue(runtime: &WasmRuntime, schedule: Schedule) -> Option<(String, String)> {
+    let job = NewJob {
+        id: format!("job-{}", runtime.invocation_id()),
+        module: schedule.module,
+        project: schedule.project,
+        export: schedule.export,
+        args: schedule.args,
+        limits: runtime.limits().with_overrides(&schedule.limits),
+        lane: schedule.lane,
+        caller: format!("schedule:{}", schedule.name),
+    };
+    match runtime.jobs.submit(job, &runtime.config.get().jobs) {
+        Ok(job) => Some((schedule.name, job.id)),
+        Err(err) => {
+            eprintln!("[uor-engine] schedule {} skipped a run: {}", schedule.name, err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(cron: Option<&str>, interval_ms: Option<u64>) -> ScheduleRequest {
+        ScheduleRequest {
+            name: "compact".to_string(),
+            module: "cache-tools".to_string(),
+            export: "compact".to_string(),
+            args: Vec::new(),
+            limits: LimitOverrides::default(),
+            cron: cron.map(str::to_string),
+            interval_ms,
+            jitter_ms: 0,
+            lane: Lane::Background,
+        }
+    }
+
+    #[test]
+    fn requires_exactly_one_valid_trigger() {
+        assert!(Schedule::new(request(None, None), None, "admin".to_string()).is_err());
+        assert!(Schedule::new(request(Some("@hourly"), Some(60_000)), None, "admin".to_string()).is_err());
+        assert!(Schedule::new(request(Some("61 * * * *"), None), None, "admin".to_string()).is_err());
+        assert!(Schedule::new(request(None, Some(10)), None, "admin".to_string()).is_err());
+        assert!(Schedule::new(request(Some("*/5 * * * *"), None), None, "admin".to_string()).is_ok());
+    }
+
+    #[test]
+    fn bounds_interval_and_jitter() {
+        assert!(Schedule::new(request(None, Some(10_000_000_000_000_000)), None, "admin".to_string()).is_err());
+        let jittered = |cron: Option<&str>, interval_ms: Option<u64>, jitter_ms: u64| {
+            Schedule::new(ScheduleRequest { jitter_ms, ..request(cron, interval_ms) }, None, "admin".to_string())
+        };
+        assert!(jittered(None, Some(5_000), 5_000).is_ok());
+        assert!(jittered(None, Some(5_000), 5_001).is_err());
+        assert!(jittered(Some("@daily"), None, u64::MAX).is_err());
+
+        // Schedules read back from a file skip validation; planning them must not panic.
+        let mut loaded = Schedule::new(request(None, Some(60_000)), None, "admin".to_string()).unwrap();
+        loaded.interval_ms = Some(u64::MAX);
+        loaded.jitter_ms = u64::MAX;
+        loaded.plan(Utc::now());
+        assert!(loaded.next_run_at.is_none());
+        loaded.interval_ms = Some(i64::MAX as u64);
+        loaded.plan(Utc::now());
+        assert!(loaded.next_run_at.is_none());
+    }
+
+    #[test]
+    fn plans_runs_with_bounded_stable_jitter_and_persists() {
+        let path = std::env::temp_dir().join(format!("uor-schedules-test-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let store = ScheduleStore::open(&path);
+        let mut every_minute = Schedule::new(request(None, Some(60_000)), None, "admin".to_string()).unwrap();
+        every_minute.jitter_ms = 5_000;
+        let planned = store.put(every_minute).unwrap();
+        let next = planned.next_run_at.unwrap();
+        let base = planned.created_at + Duration::milliseconds(60_000);
+        assert!(next >= base && next <= base + Duration::milliseconds(5_000));
+
+        assert!(store.take_due(next - Duration::milliseconds(1)).is_empty());
+        let due = store.take_due(next);
+        assert_eq!(due.len(), 1);
+        let second = planned.created_at + Duration::milliseconds(120_000);
+        assert!(due[0].next_run_at.unwrap() >= second && due[0].next_run_at.unwrap() <= second + Duration::milliseconds(5_000));
+        store.record_runs(vec![("compact".to_string(), "job-1".to_string())]);
+
+        let reopened = ScheduleStore::open(&path);
+        let schedule = reopened.get("compact").unwrap();
+        assert_eq!((schedule.last_run_at, schedule.last_job.as_deref()), (Some(next), Some("job-1")));
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    time,
+};
+
+use crate::{mesh, status::SharedState, wasm::WasmRuntime};
+
+/// Resolves on the first SIGTERM or SIGINT.
+pub async fn signal_received() {
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+    let mut interrupt = signal(SignalKind::interrupt()).expect("failed to install SIGINT handler");
+    let name = tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    };
+    println!("[uor-engine] {} received, draining", name);
+}
+
+/// Marks the engine draining, cancels queued jobs, deregisters from the control plane and waits
+/// for in-flight invocations (running jobs included) up to `server.drain_timeout_ms`. Returns how
+/// many were still running at the deadline.
+pub async fn drain(runtime: &WasmRuntime) -> usize {
+    runtime.begin_drain();
+    let cancelled = runtime.jobs.cancel_queued(runtime.config.get().jobs.retained);
+    if cancelled > 0 {
+        println!("[uor-engine] cancelled {} queued job(s)", cancelled);
+    }
+    mesh::deregister(runtime).await;
+    let deadline = Duration::from_millis(runtime.config.get().server.drain_timeout_ms);
+    if time::timeout(deadline, runtime.wait_idle()).await.is_err() {
+        let abandoned = runtime.in_flight();
+        eprintln!("[uor-engine] drain deadline of {} ms passed with {} invocation(s) in flight", deadline.as_millis(), abandoned);
+        return abandoned;
+    }
+    0
+}
+
+/// Writes state that should survive the process: the final metrics snapshot, if configured.
+pub fn flush(runtime: &WasmRuntime, state: &Arc<Mutex<SharedState>>) {
+    let Some(path) = runtime.config.get().storage.metrics_file.clone() else {
+        return;
+    };
+    let status = state.lock().map(|guard| guard.status.clone()).unwrap_or_default();
+    match write_atomic(&path, runtime.metrics.render(&status).as_bytes()) {
+        Ok(()) => println!("[uor-engine] wrote metrics snapshot to {}", path.display()),
+        Err(err) => eprintln!("[uor-engine] failed to write metrics snapshot to {}: {}", path.display(), err),
+    }
+}
+
+/// Writes through a temporary file so readers never see a partial file.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)
+}
@@ -0,0 +1,316 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fmt,
+    time::{Duration, Instant},
+};
+
+/// Sleep states in increasing order of activity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SleepState {
+    Idle,
+    Warm,
+    Active,
+}
+
+impl SleepState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SleepState::Idle => "idle",
+            SleepState::Warm => "warm",
+            SleepState::Active => "active",
+        }
+    }
+}
+
+impl fmt::Display for SleepState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Signals the classifier looks at on every sample.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SleepInputs {
+    /// Host-wide CPU usage.
+    pub cpu_percent: f32,
+    /// Host memory in use, as a share of total.
+    pub memory_percent: f32,
+    /// Invocations waiting for or running on a worker, plus queued jobs.
+    pub queued_invocations: f32,
+    /// Running jobs as a share of the busiest lane's concurrency.
+    pub lane_saturation_percent: f32,
+    /// Host network traffic, received plus transmitted.
+    pub network_kb_per_sec: f32,
+}
+
+/// A state and the human-readable reason it was chosen.
+#[derive(Clone, Debug)]
+pub struct Classification {
+    pub state: SleepState,
+    pub reason: String,
+}
+
+/// Decides the sleep state from sampled inputs. Implementations keep their own
+/// state between calls, so hysteresis and dwell times live here.
+pub trait Classifier: Send {
+    fn classify(&mut self, inputs: &SleepInputs, now: Instant) -> Classification;
+
+    /// Called when the config is reloaded; the current state is kept.
+    fn set_policy(&mut self, _policy: &SleepPolicy) {}
+}
+
+/// A state is entered once an input reaches `enter` and kept until it drops below `exit`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Band {
+    pub enter: f32,
+    pub exit: f32,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Thresholds {
+    pub warm: Band,
+    pub active: Band,
+}
+
+impl Thresholds {
+    const fn new(warm: (f32, f32), active: (f32, f32)) -> Self {
+        Self {
+            warm: Band { enter: warm.0, exit: warm.1 },
+            active: Band {
+                enter: active.0,
+                exit: active.1,
+            },
+        }
+    }
+
+    /// Highest state `value` justifies given the `current` state, with the reason.
+    fn level(&self, name: &str, value: f32, current: SleepState) -> Option<(SleepState, String)> {
+        for (state, band) in [(SleepState::Active, self.active), (SleepState::Warm, self.warm)] {
+            if value >= band.enter {
+                return Some((state, format!("{} {:.1} >= {} enter {}", name, value, state, band.enter)));
+            }
+            if current >= state && value >= band.exit {
+                return Some((state, format!("{} {:.1} >= {} exit {}", name, value, state, band.exit)));
+            }
+        }
+        None
+    }
+
+    fn validate(&self, name: &str) -> Result<(), String> {
+        for (state, band) in [("warm", self.warm), ("active", self.active)] {
+            if band.exit > band.enter {
+                return Err(format!("{}.{}: exit {} is above enter {}", name, state, band.exit, band.enter));
+            }
+        }
+        if self.warm.enter > self.active.enter {
+            return Err(format!("{}: warm enter {} is above active enter {}", name, self.warm.enter, self.active.enter));
+        }
+        Ok(())
+    }
+}
+
+/// Minimum time spent in a state before stepping down from it. Stepping up is never delayed.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct DwellTimes {
+    pub warm_ms: u64,
+    pub active_ms: u64,
+}
+
+/// Thresholds and dwell times for [`HysteresisClassifier`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SleepPolicy {
+    pub cpu_percent: Thresholds,
+    pub memory_percent: Thresholds,
+    pub queued_invocations: Thresholds,
+    pub lane_saturation_percent: Thresholds,
+    pub network_kb_per_sec: Thresholds,
+    pub min_dwell: DwellTimes,
+}
+
+impl Default for SleepPolicy {
+    fn default() -> Self {
+        Self {
+            cpu_percent: Thresholds::new((5.0, 3.0), (40.0, 30.0)),
+            memory_percent: Thresholds::new((80.0, 75.0), (95.0, 90.0)),
+            queued_invocations: Thresholds::new((1.0, 1.0), (8.0, 4.0)),
+            lane_saturation_percent: Thresholds::new((1.0, 1.0), (100.0, 75.0)),
+            network_kb_per_sec: Thresholds::new((64.0, 32.0), (4096.0, 2048.0)),
+            min_dwell: DwellTimes {
+                warm_ms: 5_000,
+                active_ms: 10_000,
+            },
+        }
+    }
+}
+
+impl SleepPolicy {
+    /// Applies `UOR_SLEEP_{CPU,MEMORY,QUEUE,LANES,NETWORK}_{WARM,ACTIVE}=enter[/exit]` and
+    /// `UOR_SLEEP_DWELL_{WARM,ACTIVE}_MS` overrides.
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        for (key, thresholds) in [
+            ("CPU", &mut self.cpu_percent),
+            ("MEMORY", &mut self.memory_percent),
+            ("QUEUE", &mut self.queued_invocations),
+            ("LANES", &mut self.lane_saturation_percent),
+            ("NETWORK", &mut self.network_kb_per_sec),
+        ] {
+            for (level, band) in [("WARM", &mut thresholds.warm), ("ACTIVE", &mut thresholds.active)] {
+                let var = format!("UOR_SLEEP_{}_{}", key, level);
+                if let Ok(value) = env::var(&var) {
+                    *band = parse_band(&value).ok_or_else(|| format!("{}: expected `enter` or `enter/exit`, got `{}`", var, value))?;
+                }
+            }
+        }
+        for (var, dwell) in [
+            ("UOR_SLEEP_DWELL_WARM_MS", &mut self.min_dwell.warm_ms),
+            ("UOR_SLEEP_DWELL_ACTIVE_MS", &mut self.min_dwell.active_ms),
+        ] {
+            if let Ok(value) = env::var(var) {
+                *dwell = value.parse().map_err(|_| format!("{}: expected milliseconds, got `{}`", var, value))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.cpu_percent.validate("sleep.cpu_percent")?;
+        self.memory_percent.validate("sleep.memory_percent")?;
+        self.queued_invocations.validate("sleep.queued_invocations")?;
+        self.lane_saturation_percent.validate("sleep.lane_saturation_percent")?;
+        self.network_kb_per_sec.validate("sleep.network_kb_per_sec")
+    }
+}
+
+fn parse_band(value: &str) -> Option<Band> {
+    let (enter, exit) = value.split_once('/').unwrap_or((value, value));
+    Some(Band {
+        enter: enter.trim().parse().ok()?,
+        exit: exit.trim().parse().ok()?,
+    })
+}
+
+/// Takes the highest state any input calls for, using enter/exit bands so inputs
+/// hovering near a threshold do not flap, and holds each state for its minimum dwell.
+pub struct HysteresisClassifier {
+    policy: SleepPolicy,
+    state: SleepState,
+    since: Instant,
+}
+
+impl HysteresisClassifier {
+    pub fn new(policy: SleepPolicy) -> Self {
+        Self {
+            policy,
+            state: SleepState::Idle,
+            since: Instant::now(),
+        }
+    }
+}
+
+impl Classifier for HysteresisClassifier {
+    fn classify(&mut self, inputs: &SleepInputs, now: Instant) -> Classification {
+        let policy = &self.policy;
+        let mut target = SleepState::Idle;
+        let mut reason = "all inputs below warm thresholds".to_string();
+        for (name, value, thresholds) in [
+            ("cpu_percent", inputs.cpu_percent, &policy.cpu_percent),
+            ("memory_percent", inputs.memory_percent, &policy.memory_percent),
+            ("queued_invocations", inputs.queued_invocations, &policy.queued_invocations),
+            ("lane_saturation_percent", inputs.lane_saturation_percent, &policy.lane_saturation_percent),
+            ("network_kb_per_sec", inputs.network_kb_per_sec, &policy.network_kb_per_sec),
+        ] {
+            if let Some((level, why)) = thresholds.level(name, value, self.state) {
+                if level > target {
+                    target = level;
+                    reason = why;
+                }
+            }
+        }
+
+        if target < self.state {
+            let dwell = Duration::from_millis(match self.state {
+                SleepState::Active => policy.min_dwell.active_ms,
+                SleepState::Warm => policy.min_dwell.warm_ms,
+                SleepState::Idle => 0,
+            });
+            let held = now.saturating_duration_since(self.since);
+            if held < dwell {
+                return Classification {
+                    state: self.state,
+                    reason: format!(
+                        "holding {} for min dwell ({:.1}s of {:.1}s); {}",
+                        self.state,
+                        held.as_secs_f32(),
+                        dwell.as_secs_f32(),
+                        reason
+                    ),
+                };
+            }
+        }
+        if target != self.state {
+            self.state = target;
+            self.since = now;
+        }
+        Classification { state: target, reason }
+    }
+
+    fn set_policy(&mut self, policy: &SleepPolicy) {
+        self.policy = policy.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpu(value: f32) -> SleepInputs {
+        SleepInputs {
+            cpu_percent: value,
+            ..SleepInputs::default()
+        }
+    }
+
+    fn no_dwell() -> SleepPolicy {
+        SleepPolicy {
+            min_dwell: DwellTimes { warm_ms: 0, active_ms: 0 },
+            ..SleepPolicy::default()
+        }
+    }
+
+    #[test]
+    fn hysteresis_keeps_state_between_exit_and_enter() {
+        let mut classifier = HysteresisClassifier::new(no_dwell());
+        let now = Instant::now();
+        let states: Vec<_> = [45.0, 35.0, 41.0, 32.0, 25.0, 4.0, 2.0]
+            .into_iter()
+            .map(|value| classifier.classify(&cpu(value), now).state)
+            .collect();
+        use SleepState::*;
+        assert_eq!(states, [Active, Active, Active, Active, Warm, Warm, Idle]);
+    }
+
+    #[test]
+    fn holds_state_until_min_dwell_elapses() {
+        let mut classifier = HysteresisClassifier::new(SleepPolicy::default());
+        let start = Instant::now();
+        assert_eq!(classifier.classify(&cpu(50.0), start).state, SleepState::Active);
+        let held = classifier.classify(&cpu(0.0), start + Duration::from_secs(2));
+        assert_eq!(held.state, SleepState::Active);
+        assert!(held.reason.starts_with("holding active"), "{}", held.reason);
+        assert_eq!(classifier.classify(&cpu(0.0), start + Duration::from_secs(11)).state, SleepState::Idle);
+    }
+
+    #[test]
+    fn reports_the_input_that_drove_the_state() {
+        let mut classifier = HysteresisClassifier::new(no_dwell());
+        let inputs = SleepInputs {
+            cpu_percent: 10.0,
+            queued_invocations: 9.0,
+            ..SleepInputs::default()
+        };
+        let result = classifier.classify(&inputs, Instant::now());
+        assert_eq!(result.state, SleepState::Active);
+        assert!(result.reason.starts_with("queued_invocations 9.0 >= active enter"), "{}", result.reason);
+    }
+}
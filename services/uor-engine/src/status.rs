@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use sysinfo::{Networks, System};
+use tokio::time::{self, Duration};
+
+use crate::{
+    cache::CacheStats,
+    events::Event,
+    history::{self, Sample, StatusHistory},
+    jobs::LaneStatus,
+    limits::env_or,
+    process::{self, ProcessStats},
+    sleep::{Classifier, SleepInputs},
+    wasm::WasmRuntime,
+};
+
+#[derive(Clone, Serialize, Default)]
+pub struct RuntimeStatus {
+    /// Engine process CPU, as a share of total host capacity.
+    pub cpu_percent: f32,
+    /// Engine process resident set size.
+    pub rss_mb: f32,
+    pub virtual_mb: f32,
+    pub threads: u32,
+    pub open_fds: u32,
+    pub host_cpu_percent: f32,
+    pub host_used_memory_mb: f32,
+    pub host_total_memory_mb: f32,
+    /// Host memory in use, as a share of total.
+    pub memory_percent: f32,
+    /// Invocations waiting for or running on a worker, plus queued jobs.
+    pub queued_invocations: u32,
+    /// Jobs waiting for a lane slot.
+    pub queued_jobs: u32,
+    /// Running jobs as a share of the busiest lane's concurrency.
+    pub lane_saturation_percent: f32,
+    pub job_lanes: Vec<LaneStatus>,
+    /// When the next schedule is due; the scheduler sleeps until then.
+    pub next_schedule_at: Option<String>,
+    /// Host network traffic since the previous sample, received plus transmitted.
+    pub network_kb_per_sec: f32,
+    pub uptime_seconds: u64,
+    pub sleep_state: String,
+    /// Why the classifier chose `sleep_state`.
+    pub sleep_reason: String,
+    pub tickless: bool,
+    /// Delay the sampler will wait before its next unprompted wakeup.
+    pub sample_period_ms: u64,
+    /// Sampler wakeups over the last minute, including request-triggered ones.
+    pub wakeups_per_minute: u32,
+    pub wasm_loaded: bool,
+    pub wasm_modules: Vec<String>,
+    pub module_cache: CacheStats,
+    pub timestamp: String,
+}
+
+pub struct SharedState {
+    pub status: RuntimeStatus,
+    pub history: StatusHistory,
+    /// When the sampler last wrote `status`; the liveness probe watches it.
+    pub sampled_at: Instant,
+}
+
+/// Sampler period bounds. The sampler runs at `min_period_ms` while active,
+/// twice that while warm, and doubles its period up to `max_period_ms` while idle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SamplingPolicy {
+    pub min_period_ms: u64,
+    pub max_period_ms: u64,
+    /// Samples kept for `/status/history`.
+    pub history_samples: usize,
+}
+
+impl Default for SamplingPolicy {
+    fn default() -> Self {
+        Self {
+            min_period_ms: 500,
+            max_period_ms: 5_000,
+            history_samples: history::DEFAULT_CAPACITY,
+        }
+    }
+}
+
+impl SamplingPolicy {
+    /// Applies `UOR_SAMPLE_MIN_MS`, `UOR_SAMPLE_MAX_MS` and `UOR_HISTORY_SAMPLES`.
+    pub fn apply_env(&mut self) {
+        self.min_period_ms = env_or("UOR_SAMPLE_MIN_MS", self.min_period_ms);
+        self.max_period_ms = env_or("UOR_SAMPLE_MAX_MS", self.max_period_ms);
+        self.history_samples = env_or("UOR_HISTORY_SAMPLES", self.history_samples);
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_period_ms < 50 {
+            return Err(format!("sampling.min_period_ms must be at least 50, got {}", self.min_period_ms));
+        }
+        if self.max_period_ms < self.min_period_ms {
+            return Err(format!(
+                "sampling.max_period_ms {} is below min_period_ms {}",
+                self.max_period_ms, self.min_period_ms
+            ));
+        }
+        Ok(())
+    }
+
+    fn next_period(&self, current: Duration, sleep_state: &str) -> Duration {
+        let min = Duration::from_millis(self.min_period_ms);
+        let max = Duration::from_millis(self.max_period_ms);
+        match sleep_state {
+            "active" | "draining" => min,
+            "warm" => (min * 2).min(max),
+            _ => (current * 2).clamp(min, max),
+        }
+    }
+}
+
+/// Samples on an adaptive period instead of a fixed tick; any request or
+/// invocation notifies `runtime.wake` and triggers a sample, no sooner than
+/// `min_period_ms` after the previous one.
+pub async fn sample_metrics(state: Arc<Mutex<SharedState>>, start: Instant, runtime: Arc<WasmRuntime>, mut classifier: Box<dyn Classifier>) {
+    let mut config = runtime.config.subscribe();
+    let mut policy = config.borrow_and_update().sampling.clone();
+    let pid = sysinfo::get_current_pid().ok();
+    let mut sys = System::new();
+    let mut networks = Networks::new_with_refreshed_list();
+    let mut last_sample = Instant::now();
+    let mut period = Duration::from_millis(policy.min_period_ms);
+    let mut wakeups: VecDeque<Instant> = VecDeque::new();
+    let mut woken = false;
+    loop {
+        tokio::select! {
+            _ = time::sleep_until(time::Instant::from_std(last_sample + period)) => {}
+            _ = runtime.wake.notified() => {
+                woken = true;
+                // CPU readings taken closer together are noise; hold the sample until the minimum period has passed.
+                let min_period = Duration::from_millis(policy.min_period_ms);
+                if last_sample.elapsed() < min_period {
+                    period = period.min(min_period);
+                    continue;
+                }
+            }
+        }
+        let reconfigured = config.has_changed().unwrap_or(false);
+        if reconfigured {
+            let latest = config.borrow_and_update().clone();
+            policy = latest.sampling.clone();
+            classifier.set_policy(&latest.sleep);
+        }
+        let wake_time = Instant::now();
+        wakeups.push_back(wake_time);
+        while wakeups.front().is_some_and(|at| wake_time.duration_since(*at) > Duration::from_secs(60)) {
+            wakeups.pop_front();
+        }
+        sys.refresh_cpu();
+        sys.refresh_memory();
+        let proc_stats = match pid {
+            Some(pid) if sys.refresh_process(pid) => process::sample(&sys, pid),
+            _ => ProcessStats::default(),
+        };
+        networks.refresh();
+        let network_bytes: u64 = networks.values().map(|data| data.received() + data.transmitted()).sum();
+        let elapsed = wake_time.duration_since(last_sample).as_secs_f32().max(0.001);
+        last_sample = wake_time;
+        let cpu = sys.global_cpu_info().cpu_usage();
+        let used_memory_mb = process::bytes_to_mb(sys.used_memory());
+        let total_memory_mb = process::bytes_to_mb(sys.total_memory());
+        let job_lanes = runtime.jobs.lanes(&runtime.config.get().jobs);
+        let queued_jobs: u32 = job_lanes.iter().map(|lane| lane.queued).sum();
+        let lane_saturation_percent = job_lanes
+            .iter()
+            .map(|lane| lane.running as f32 / lane.concurrency.max(1) as f32 * 100.0)
+            .fold(0.0, f32::max);
+        let inputs = SleepInputs {
+            cpu_percent: cpu,
+            memory_percent: if total_memory_mb > 0.0 { used_memory_mb / total_memory_mb * 100.0 } else { 0.0 },
+            queued_invocations: (runtime.in_flight() + queued_jobs as usize) as f32,
+            lane_saturation_percent,
+            network_kb_per_sec: network_bytes as f32 / 1024.0 / elapsed,
+        };
+        let classification = classifier.classify(&inputs, wake_time);
+        let (sleep_state, sleep_reason) = if runtime.is_draining() {
+            ("draining", "shutting down; waiting for in-flight invocations".to_string())
+        } else {
+            (classification.state.as_str(), classification.reason)
+        };
+        let uptime = start.elapsed().as_secs();
+        let now = chrono::Utc::now();
+        period = if std::mem::take(&mut woken) {
+            Duration::from_millis(policy.min_period_ms)
+        } else {
+            policy.next_period(period, sleep_state)
+        };
+        let wasm_modules = runtime.registry.lock().map(|registry| registry.names()).unwrap_or_default();
+        let mut guard = match state.lock() {
+            Ok(g) => g,
+            Err(_) => continue,
+        };
+        if reconfigured {
+            guard.history.set_capacity(policy.history_samples);
+        }
+        let previous_state = std::mem::take(&mut guard.status.sleep_state);
+        guard.status = RuntimeStatus {
+            cpu_percent: proc_stats.cpu_percent,
+            rss_mb: proc_stats.rss_mb,
+            virtual_mb: proc_stats.virtual_mb,
+            threads: proc_stats.threads,
+            open_fds: proc_stats.open_fds,
+            host_cpu_percent: cpu,
+            host_used_memory_mb: used_memory_mb,
+            host_total_memory_mb: total_memory_mb,
+            memory_percent: inputs.memory_percent,
+            queued_invocations: inputs.queued_invocations as u32,
+            queued_jobs,
+            lane_saturation_percent,
+            job_lanes,
+            next_schedule_at: runtime.schedules.next_due().map(|at| at.to_rfc3339()),
+            network_kb_per_sec: inputs.network_kb_per_sec,
+            uptime_seconds: uptime,
+            sleep_state: sleep_state.to_string(),
+            sleep_reason,
+            tickless: true,
+            sample_period_ms: period.as_millis() as u64,
+            wakeups_per_minute: wakeups.len() as u32,
+            wasm_loaded: !wasm_modules.is_empty(),
+            wasm_modules,
+            module_cache: runtime.cache.stats(),
+            timestamp: now.to_rfc3339(),
+        };
+        guard.sampled_at = wake_time;
+        let sample = Sample::from_status(&guard.status, now);
+        guard.history.push(sample);
+        if previous_state != guard.status.sleep_state && !previous_state.is_empty() {
+            runtime.events.publish(Event::SleepState {
+                from: previous_state,
+                to: guard.status.sleep_state.clone(),
+                reason: guard.status.sleep_reason.clone(),
+            });
+        }
+        runtime.events.publish(Event::Status(Box::new(guard.status.clone())));
+    }
+}
@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::Infallible,
+    env, fs,
+    future::Future,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, watch},
+    task::JoinSet,
+    time,
+};
+use tokio_rustls::{
+    rustls::{server::AllowAnyAuthenticatedClient, Certificate, PrivateKey, RootCertStore, ServerConfig},
+    TlsAcceptor,
+};
+use warp::hyper::{server::conn::Http, service::Service, Body, Request, Response};
+
+use crate::{config, wasm::WasmRuntime};
+
+/// Clients that have not finished the handshake by then are dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Certificates are often rotated a file at a time; changes within this window are coalesced.
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// `[tls]` config section. HTTPS is served on every bind address when `cert` and `key` are set.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first.
+    pub cert: Option<PathBuf>,
+    /// PEM private key (PKCS#8, RSA or SEC1).
+    pub key: Option<PathBuf>,
+    /// PEM CA bundle; when set, clients must present a certificate signed by it (mTLS).
+    pub client_ca: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    pub fn enabled(&self) -> bool {
+        self.cert.is_some() && self.key.is_some()
+    }
+
+    pub fn apply_env(&mut self) {
+        for (name, slot) in [("UOR_TLS_CERT", &mut self.cert), ("UOR_TLS_KEY", &mut self.key), ("UOR_TLS_CLIENT_CA", &mut self.client_ca)] {
+            if let Ok(value) = env::var(name) {
+                *slot = Some(PathBuf::from(value)).filter(|path| !path.as_os_str().is_empty());
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.cert.is_some() != self.key.is_some() {
+            return Err("tls.cert and tls.key must be set together".to_string());
+        }
+        if self.client_ca.is_some() && !self.enabled() {
+            return Err("tls.client_ca requires tls.cert and tls.key".to_string());
+        }
+        Ok(())
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        [&self.cert, &self.key, &self.client_ca].into_iter().flatten().cloned().collect()
+    }
+
+    /// Reads the certificate, key and client CA into a rustls server config.
+    fn server_config(&self) -> Result<ServerConfig, String> {
+        let (Some(cert_path), Some(key_path)) = (&self.cert, &self.key) else {
+            return Err("tls.cert and tls.key are not set".to_string());
+        };
+        let certs = read_certs(cert_path)?;
+        let key = read_key(key_path)?;
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &self.client_ca {
+            Some(ca_path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in read_certs(ca_path)? {
+                    roots.add(&cert).map_err(|err| format!("{}: {}", ca_path.display(), err))?;
+                }
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder
+            .with_single_cert(certs, key)
+            .map_err(|err| format!("{}: {}", cert_path.display(), err))?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+}
+
+pub fn read_certs(path: &Path) -> Result<Vec<Certificate>, String> {
+    let file = fs::File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file)).map_err(|err| format!("{}: {}", path.display(), err))?;
+    if certs.is_empty() {
+        return Err(format!("{}: no PEM certificates found", path.display()));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_key(path: &Path) -> Result<PrivateKey, String> {
+    let file = fs::File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let items = rustls_pemfile::read_all(&mut BufReader::new(file)).map_err(|err| format!("{}: {}", path.display(), err))?;
+    items
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("{}: no PEM private key found", path.display()))
+}
+
+/// The server config handed to new connections; swapped in place when certificates are reloaded.
+pub struct Certificates {
+    current: RwLock<Arc<ServerConfig>>,
+}
+
+impl Certificates {
+    pub fn load(config: &TlsConfig) -> Result<Self, String> {
+        Ok(Self {
+            current: RwLock::new(Arc::new(config.server_config()?)),
+        })
+    }
+
+    fn current(&self) -> Arc<ServerConfig> {
+        self.current.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// Re-reads the files; on error the current certificates stay in use.
+    fn reload(&self, config: &TlsConfig) -> Result<(), String> {
+        let next = Arc::new(config.server_config()?);
+        *self.current.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = next;
+        Ok(())
+    }
+}
+
+/// Reloads certificates when the files change or the config is reloaded (including on SIGHUP).
+/// Connections already established keep the certificate they were accepted with.
+pub async fn watch_for_changes(runtime: Arc<WasmRuntime>, certificates: Arc<Certificates>) {
+    let mut config = runtime.config.subscribe();
+    loop {
+        let files = config.borrow_and_update().tls.files();
+        let (tx, mut changes) = mpsc::channel::<()>(1);
+        let _watcher = config::watch_files(&files, tx);
+        tokio::select! {
+            changed = config.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+            Some(()) = changes.recv() => {
+                time::sleep(RELOAD_DEBOUNCE).await;
+            }
+        }
+        let tls = runtime.config.get().tls.clone();
+        if !tls.enabled() {
+            continue;
+        }
+        match certificates.reload(&tls) {
+            Ok(()) => println!("[uor-engine] reloaded TLS certificate from {}", tls.cert.as_deref().unwrap_or(Path::new("")).display()),
+            Err(err) => eprintln!("[uor-engine] TLS reload failed, keeping previous certificate: {}", err),
+        }
+    }
+}
+
+/// Accepts TLS connections on `listener` until `shutdown` resolves, then lets open connections
+/// finish their current request.
+pub async fn serve<S>(listener: TcpListener, service: S, certificates: Arc<Certificates>, shutdown: impl Future<Output = ()>)
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    let (closing, _) = watch::channel(false);
+    let mut connections = JoinSet::new();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept() => {
+                let (stream, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        eprintln!("[uor-engine] accept failed: {}", err);
+                        continue;
+                    }
+                };
+                let acceptor = TlsAcceptor::from(certificates.current());
+                let service = service.clone();
+                let mut closing = closing.subscribe();
+                connections.spawn(async move {
+                    let stream = match time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => stream,
+                        Ok(Err(err)) => {
+                            eprintln!("[uor-engine] TLS handshake with {} failed: {}", peer, err);
+                            return;
+                        }
+                        Err(_) => return,
+                    };
+                    let connection = Http::new().serve_connection(stream, service).with_upgrades();
+                    tokio::pin!(connection);
+                    tokio::select! {
+                        _ = &mut connection => {}
+                        _ = async { drop(closing.wait_for(|closing| *closing).await) } => {
+                            connection.as_mut().graceful_shutdown();
+                            let _ = connection.await;
+                        }
+                    }
+                });
+            }
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+        }
+    }
+    closing.send_replace(true);
+    while connections.join_next().await.is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_cert_and_key_together() {
+        let cert_only = TlsConfig {
+            cert: Some(PathBuf::from("server.pem")),
+            ..TlsConfig::default()
+        };
+        assert!(cert_only.validate().is_err());
+        let ca_only = TlsConfig {
+            client_ca: Some(PathBuf::from("ca.pem")),
+            ..TlsConfig::default()
+        };
+        assert!(ca_only.validate().is_err());
+        assert!(TlsConfig::default().validate().is_ok() && !TlsConfig::default().enabled());
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let missing = TlsConfig {
+            cert: Some(PathBuf::from("/nonexistent/server.pem")),
+            key: Some(PathBuf::from("/nonexistent/server.key")),
+            client_ca: None,
+        };
+        let err = missing.server_config().unwrap_err();
+        assert!(err.contains("/nonexistent/server.pem"));
+    }
+}
@@ -0,0 +1,611 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{auth::Caller, jobs::JobRequest, limits::env_or, wasm::WasmRuntime};
+
+/// `r0` through `r7`.
+pub const REGISTERS: usize = 8;
+
+/// `[vasm]` config section: bounds for programs run through `POST /vasm/run`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VasmConfig {
+    /// Instructions a program may execute; requests can only lower it.
+    pub max_instructions: u64,
+    /// Values and return addresses the stack may hold.
+    pub max_stack: usize,
+}
+
+impl Default for VasmConfig {
+    fn default() -> Self {
+        Self {
+            max_instructions: 1_000_000,
+            max_stack: 1024,
+        }
+    }
+}
+
+impl VasmConfig {
+    /// Applies `UOR_VASM_MAX_INSTRUCTIONS` and `UOR_VASM_MAX_STACK`.
+    pub fn apply_env(&mut self) {
+        self.max_instructions = env_or("UOR_VASM_MAX_INSTRUCTIONS", self.max_instructions);
+        self.max_stack = env_or("UOR_VASM_MAX_STACK", self.max_stack);
+    }
+}
+
+/// Opcodes of `vasm/src/program.ts`, spelled the same in program JSON.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Opcode {
+    LoadConst,
+    Mov,
+    Push,
+    Pop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Inc,
+    Dec,
+    Cmplt,
+    Cmple,
+    Cmpeq,
+    Cmpne,
+    And,
+    Or,
+    Not,
+    Jmp,
+    Jif,
+    Call,
+    Ret,
+    Halt,
+    Print,
+    Now,
+    VoikeQuery,
+    VoikeBlob,
+    VoikeGridJob,
+    VoikeAiAsk,
+    VoikeRunJob,
+}
+
+impl Opcode {
+    /// Opcodes dispatched to syscall handlers rather than executed by the interpreter.
+    pub const SYSCALLS: [Opcode; 7] = [
+        Opcode::Print,
+        Opcode::Now,
+        Opcode::VoikeQuery,
+        Opcode::VoikeBlob,
+        Opcode::VoikeGridJob,
+        Opcode::VoikeAiAsk,
+        Opcode::VoikeRunJob,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Opcode::LoadConst => "LOAD_CONST",
+            Opcode::Mov => "MOV",
+            Opcode::Push => "PUSH",
+            Opcode::Pop => "POP",
+            Opcode::Add => "ADD",
+            Opcode::Sub => "SUB",
+            Opcode::Mul => "MUL",
+            Opcode::Div => "DIV",
+            Opcode::Mod => "MOD",
+            Opcode::Inc => "INC",
+            Opcode::Dec => "DEC",
+            Opcode::Cmplt => "CMPLT",
+            Opcode::Cmple => "CMPLE",
+            Opcode::Cmpeq => "CMPEQ",
+            Opcode::Cmpne => "CMPNE",
+            Opcode::And => "AND",
+            Opcode::Or => "OR",
+            Opcode::Not => "NOT",
+            Opcode::Jmp => "JMP",
+            Opcode::Jif => "JIF",
+            Opcode::Call => "CALL",
+            Opcode::Ret => "RET",
+            Opcode::Halt => "HALT",
+            Opcode::Print => "PRINT",
+            Opcode::Now => "NOW",
+            Opcode::VoikeQuery => "VOIKE_QUERY",
+            Opcode::VoikeBlob => "VOIKE_BLOB",
+            Opcode::VoikeGridJob => "VOIKE_GRID_JOB",
+            Opcode::VoikeAiAsk => "VOIKE_AI_ASK",
+            Opcode::VoikeRunJob => "VOIKE_RUN_JOB",
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A register name, label or constant.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Operand {
+    Number(f64),
+    Text(String),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Number(value) => write!(f, "{}", value),
+            Operand::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Instruction {
+    pub op: Opcode,
+    #[serde(default)]
+    pub args: Vec<Operand>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// Program JSON as the TypeScript VM takes it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+}
+
+/// `POST /vasm/run` body: a program, optionally with a lower instruction budget.
+#[derive(Deserialize)]
+pub struct VasmRequest {
+    #[serde(flatten)]
+    pub program: Program,
+    pub max_instructions: Option<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct VasmError {
+    /// `instruction_limit`, `stack_overflow`, `syscall_failed` or `vasm_fault`.
+    pub kind: &'static str,
+    pub message: String,
+}
+
+impl VasmError {
+    pub fn fault(message: impl Into<String>) -> Self {
+        Self {
+            kind: "vasm_fault",
+            message: message.into(),
+        }
+    }
+
+    fn at(self, pc: usize, op: Opcode) -> Self {
+        Self {
+            message: format!("instruction {} ({}): {}", pc, op, self.message),
+            ..self
+        }
+    }
+}
+
+/// Registers, stack and output of a running program, as syscall handlers see them.
+pub struct Machine {
+    registers: [Value; REGISTERS],
+    stack: Vec<Value>,
+    max_stack: usize,
+    /// Lines written by `PRINT`.
+    pub output: Vec<String>,
+    /// Notes from syscalls, such as ones with no backend on this node.
+    pub debug: Vec<String>,
+}
+
+impl Machine {
+    fn new(max_stack: usize) -> Self {
+        Self {
+            registers: std::array::from_fn(|_| Value::from(0)),
+            stack: Vec::new(),
+            max_stack,
+            output: Vec::new(),
+            debug: Vec::new(),
+        }
+    }
+
+    pub fn register(&self, operand: Option<&Operand>) -> Result<&Value, VasmError> {
+        Ok(&self.registers[register_index(operand)?])
+    }
+
+    pub fn set_register(&mut self, operand: Option<&Operand>, value: Value) -> Result<(), VasmError> {
+        self.registers[register_index(operand)?] = value;
+        Ok(())
+    }
+
+    fn number(&self, operand: Option<&Operand>) -> Result<f64, VasmError> {
+        let value = self.register(operand)?;
+        value.as_f64().ok_or_else(|| VasmError::fault(format!("{} holds {}, not a number", operand.map(Operand::to_string).unwrap_or_default(), value)))
+    }
+
+    fn push(&mut self, value: Value) -> Result<(), VasmError> {
+        if self.stack.len() >= self.max_stack {
+            return Err(VasmError {
+                kind: "stack_overflow",
+                message: format!("stack is full at {} entries", self.max_stack),
+            });
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+}
+
+fn register_index(operand: Option<&Operand>) -> Result<usize, VasmError> {
+    match operand {
+        Some(Operand::Text(name)) => parse_register(name).ok_or_else(|| VasmError::fault(format!("invalid register: {}", name))),
+        Some(other) => Err(VasmError::fault(format!("invalid register: {}", other))),
+        None => Err(VasmError::fault("missing register operand")),
+    }
+}
+
+fn parse_register(name: &str) -> Option<usize> {
+    name.strip_prefix('r')?.parse::<usize>().ok().filter(|idx| *idx < REGISTERS)
+}
+
+/// Stores integral results as JSON integers, so `2 + 3` reads back as `5` rather than `5.0`.
+pub fn number(value: f64) -> Result<Value, VasmError> {
+    if value.fract() == 0.0 && value.abs() < 9_007_199_254_740_992.0 {
+        return Ok(Value::from(value as i64));
+    }
+    serde_json::Number::from_f64(value)
+        .map(Value::Number)
+        .ok_or_else(|| VasmError::fault(format!("result {} is not a finite number", value)))
+}
+
+/// Like the TypeScript VM's `!== 0`: everything but a numeric zero is true.
+fn truthy(value: &Value) -> bool {
+    value.as_f64() != Some(0.0)
+}
+
+/// Handles one syscall opcode with the instruction's operands.
+pub type SyscallHandler = Box<dyn FnMut(&mut Machine, &[Operand]) -> Result<(), VasmError> + Send>;
+
+/// A handler for the usual `OP src, dest` shape: reads the `src` register and stores what `f`
+/// returns in `dest`. Errors fail the run as `syscall_failed`.
+pub fn value_syscall<F>(mut f: F) -> SyscallHandler
+where
+    F: FnMut(&Value) -> Result<Value, String> + Send + 'static,
+{
+    Box::new(move |machine, args| {
+        let result = f(machine.register(args.first())?).map_err(|message| VasmError { kind: "syscall_failed", message })?;
+        machine.set_register(args.get(1), result)
+    })
+}
+
+/// Stands in for a VOIKE syscall with no backend on this node: notes it and stores `0`.
+fn unavailable(op: Opcode) -> SyscallHandler {
+    Box::new(move |machine, args| {
+        machine.debug.push(format!("{}: no handler on this node", op));
+        machine.set_register(args.get(1), Value::from(0))
+    })
+}
+
+fn default_syscalls() -> HashMap<Opcode, SyscallHandler> {
+    let mut syscalls: HashMap<Opcode, SyscallHandler> = Opcode::SYSCALLS.into_iter().map(|op| (op, unavailable(op))).collect();
+    syscalls.insert(
+        Opcode::Print,
+        Box::new(|machine, args| {
+            let line = match machine.register(args.first())? {
+                Value::String(text) => text.clone(),
+                value => value.to_string(),
+            };
+            machine.output.push(line);
+            Ok(())
+        }),
+    );
+    syscalls.insert(
+        Opcode::Now,
+        Box::new(|machine, args| {
+            let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_millis() as i64).unwrap_or_default();
+            machine.set_register(args.first(), Value::from(millis))
+        }),
+    );
+    syscalls
+}
+
+/// What a finished program left behind.
+#[derive(Debug, Serialize)]
+pub struct VasmRun {
+    pub registers: BTreeMap<String, Value>,
+    pub stack: Vec<Value>,
+    pub output: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub debug: Vec<String>,
+    /// Instructions executed.
+    pub instructions: u64,
+}
+
+enum Flow {
+    Next,
+    Jump(usize),
+    Halt,
+}
+
+/// Executes a program with the semantics of `vasm/src/vm.ts`: eight registers, one stack shared
+/// by values and `CALL` return addresses, and labels resolved on load. Registers hold JSON values
+/// so syscalls can pass strings and objects around; arithmetic and comparisons need numbers.
+pub struct Interpreter<'p> {
+    program: &'p Program,
+    labels: HashMap<&'p str, usize>,
+    syscalls: HashMap<Opcode, SyscallHandler>,
+    max_instructions: u64,
+    max_stack: usize,
+}
+
+impl<'p> Interpreter<'p> {
+    pub fn new(program: &'p Program, max_instructions: u64, max_stack: usize) -> Self {
+        let labels = program
+            .instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, instruction)| instruction.label.as_deref().map(|label| (label, idx)))
+            .collect();
+        Self {
+            program,
+            labels,
+            syscalls: default_syscalls(),
+            max_instructions,
+            max_stack,
+        }
+    }
+
+    /// Replaces the handler for one of [`Opcode::SYSCALLS`].
+    pub fn with_syscall(mut self, op: Opcode, handler: SyscallHandler) -> Self {
+        self.syscalls.insert(op, handler);
+        self
+    }
+
+    /// Runs until `HALT`, a `RET` with an empty stack, or the end of the program.
+    pub fn run(&mut self) -> Result<VasmRun, VasmError> {
+        let program = self.program;
+        let mut machine = Machine::new(self.max_stack);
+        let mut pc = 0;
+        let mut executed = 0;
+        while let Some(instruction) = program.instructions.get(pc) {
+            if executed >= self.max_instructions {
+                return Err(VasmError {
+                    kind: "instruction_limit",
+                    message: format!("program did not halt within {} instructions", self.max_instructions),
+                });
+            }
+            executed += 1;
+            match self.step(&mut machine, pc, instruction).map_err(|err| err.at(pc, instruction.op))? {
+                Flow::Next => pc += 1,
+                Flow::Jump(target) => pc = target,
+                Flow::Halt => break,
+            }
+        }
+        Ok(VasmRun {
+            registers: machine.registers.iter().enumerate().map(|(idx, value)| (format!("r{}", idx), value.clone())).collect(),
+            stack: machine.stack,
+            output: machine.output,
+            debug: machine.debug,
+            instructions: executed,
+        })
+    }
+
+    fn step(&mut self, machine: &mut Machine, pc: usize, instruction: &Instruction) -> Result<Flow, VasmError> {
+        let args = instruction.args.as_slice();
+        match instruction.op {
+            Opcode::LoadConst => {
+                let value = match args.get(1) {
+                    Some(Operand::Number(value)) => number(*value)?,
+                    Some(Operand::Text(text)) => match text.trim().parse::<f64>() {
+                        Ok(value) => number(value)?,
+                        // The TypeScript VM turns these into NaN; keeping the text lets it feed syscalls.
+                        Err(_) => Value::String(text.clone()),
+                    },
+                    None => return Err(VasmError::fault("missing constant")),
+                };
+                machine.set_register(args.first(), value)?;
+            }
+            Opcode::Mov => {
+                let value = machine.register(args.get(1))?.clone();
+                machine.set_register(args.first(), value)?;
+            }
+            Opcode::Push => {
+                let value = machine.register(args.first())?.clone();
+                machine.push(value)?;
+            }
+            Opcode::Pop => {
+                let value = machine.stack.pop().ok_or_else(|| VasmError::fault("stack underflow: attempted to POP from an empty stack"))?;
+                machine.set_register(args.first(), value)?;
+            }
+            op @ (Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div | Opcode::Mod) => {
+                let (a, b) = (machine.number(args.get(1))?, machine.number(args.get(2))?);
+                let result = match op {
+                    Opcode::Add => a + b,
+                    Opcode::Sub => a - b,
+                    Opcode::Mul => a * b,
+                    Opcode::Div if b == 0.0 => return Err(VasmError::fault("division by zero")),
+                    Opcode::Div => a / b,
+                    _ if b == 0.0 => return Err(VasmError::fault("modulo by zero")),
+                    _ => a % b,
+                };
+                machine.set_register(args.first(), number(result)?)?;
+            }
+            op @ (Opcode::Inc | Opcode::Dec) => {
+                let delta = if op == Opcode::Inc { 1.0 } else { -1.0 };
+                let value = number(machine.number(args.first())? + delta)?;
+                machine.set_register(args.first(), value)?;
+            }
+            op @ (Opcode::Cmplt | Opcode::Cmple) => {
+                let (a, b) = (machine.number(args.get(1))?, machine.number(args.get(2))?);
+                let result = if op == Opcode::Cmplt { a < b } else { a <= b };
+                machine.set_register(args.first(), Value::from(result as i64))?;
+            }
+            op @ (Opcode::Cmpeq | Opcode::Cmpne) => {
+                let (a, b) = (machine.register(args.get(1))?, machine.register(args.get(2))?);
+                let equal = match (a.as_f64(), b.as_f64()) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => a == b,
+                };
+                machine.set_register(args.first(), Value::from((equal == (op == Opcode::Cmpeq)) as i64))?;
+            }
+            op @ (Opcode::And | Opcode::Or | Opcode::Not) => {
+                let a = truthy(machine.register(args.get(1))?);
+                let b = match args.get(2) {
+                    Some(_) => truthy(machine.register(args.get(2))?),
+                    None => false,
+                };
+                let result = match op {
+                    Opcode::And => a && b,
+                    Opcode::Or => a || b,
+                    _ => !a,
+                };
+                machine.set_register(args.first(), Value::from(result as i64))?;
+            }
+            Opcode::Jmp => {
+                if let Some(target) = self.target(machine, args.first())? {
+                    return Ok(Flow::Jump(target));
+                }
+            }
+            Opcode::Jif => {
+                if truthy(machine.register(args.first())?) {
+                    if let Some(target) = self.target(machine, args.get(1))? {
+                        return Ok(Flow::Jump(target));
+                    }
+                }
+            }
+            Opcode::Call => {
+                let target = self.target(machine, args.first())?.ok_or_else(|| VasmError::fault("invalid CALL target"))?;
+                machine.push(Value::from(pc + 1))?;
+                return Ok(Flow::Jump(target));
+            }
+            Opcode::Ret => {
+                let Some(address) = machine.stack.pop() else { return Ok(Flow::Halt) };
+                let address = address.as_f64().ok_or_else(|| VasmError::fault(format!("return address {} is not a number", address)))?;
+                return Ok(Flow::Jump(self.address(address)?));
+            }
+            Opcode::Halt => return Ok(Flow::Halt),
+            op => {
+                let handler = self.syscalls.get_mut(&op).ok_or_else(|| VasmError::fault(format!("no syscall handler for {}", op)))?;
+                handler(machine, args)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Resolves a jump target: an address, a register holding one, or a label. `None` when
+    /// absent, which makes `JMP` a no-op as in the TypeScript VM.
+    fn target(&self, machine: &Machine, target: Option<&Operand>) -> Result<Option<usize>, VasmError> {
+        let address = match target {
+            None => return Ok(None),
+            Some(Operand::Number(address)) => *address,
+            Some(Operand::Text(name)) if parse_register(name).is_some() => machine.number(target)?,
+            Some(Operand::Text(label)) => {
+                return self.labels.get(label.as_str()).copied().map(Some).ok_or_else(|| VasmError::fault(format!("unknown label: {}", label)));
+            }
+        };
+        self.address(address).map(Some)
+    }
+
+    /// Jumping to the end of the program is allowed and stops it.
+    fn address(&self, address: f64) -> Result<usize, VasmError> {
+        if address.fract() != 0.0 || address < 0.0 || address > self.program.instructions.len() as f64 {
+            return Err(VasmError::fault(format!("jump target {} is outside the program", address)));
+        }
+        Ok(address as usize)
+    }
+}
+
+/// Runs a `POST /vasm/run` program on a blocking worker, counted as in flight until it halts even
+/// if the request is dropped, so shutdown waits for it. `VOIKE_RUN_JOB` queues a module job as the
+/// caller; the other VOIKE syscalls have no backend on a node and store `0`.
+pub async fn run(runtime: Arc<WasmRuntime>, caller: Caller, request: VasmRequest) -> Result<VasmRun, VasmError> {
+    let config = runtime.config.get().vasm.clone();
+    let max_instructions = request.max_instructions.map_or(config.max_instructions, |requested| requested.min(config.max_instructions));
+    let in_flight = runtime.begin_invocation();
+    let worker = runtime.clone();
+    tokio::task::spawn_blocking(move || {
+        let _in_flight = in_flight;
+        let run_job = value_syscall(move |value| {
+            let request: JobRequest = match value {
+                Value::String(text) => serde_json::from_str(text),
+                value => serde_json::from_value(value.clone()),
+            }
+            .map_err(|err| format!("VOIKE_RUN_JOB expects a job request: {}", err))?;
+            let module = request.module.clone();
+            let job = request.into_job(&worker, &caller).ok_or_else(|| format!("module `{}` is not loaded", module))?;
+            let job = worker.jobs.submit(job, &worker.config.get().jobs)?;
+            Ok(Value::String(job.id))
+        });
+        Interpreter::new(&request.program, max_instructions, config.max_stack)
+            .with_syscall(Opcode::VoikeRunJob, run_job)
+            .run()
+    })
+    .await
+    .unwrap_or_else(|err| Err(VasmError::fault(err.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path};
+
+    /// Fixtures shared with `tests/vasm/conformance.test.ts`.
+    #[derive(Deserialize)]
+    struct Fixture {
+        name: String,
+        program: Program,
+        registers: BTreeMap<String, Value>,
+    }
+
+    #[test]
+    fn matches_the_typescript_vm_fixtures() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tests/vasm/fixtures");
+        let mut checked = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let fixture: Fixture = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+            let run = Interpreter::new(&fixture.program, 10_000, 64).run().unwrap_or_else(|err| panic!("{}: {:?}", fixture.name, err));
+            for (register, expected) in &fixture.registers {
+                assert_eq!(run.registers[register].as_f64(), expected.as_f64(), "{}: {}", fixture.name, register);
+            }
+            checked += 1;
+        }
+        assert!(checked >= 2);
+    }
+
+    #[test]
+    fn enforces_limits_and_dispatches_syscalls() {
+        let program: Program = serde_json::from_value(serde_json::json!({
+            "instructions": [
+                { "op": "LOAD_CONST", "args": ["r1", "select 1"] },
+                { "op": "VOIKE_QUERY", "args": ["r1", "r2"] },
+                { "op": "VOIKE_BLOB", "args": ["r1", "r3"] },
+                { "op": "PRINT", "args": ["r2"] },
+                { "label": "spin", "op": "JMP", "args": ["spin"] }
+            ]
+        }))
+        .unwrap();
+        let query = value_syscall(|query| Ok(Value::from(format!("rows for {}", query.as_str().unwrap_or_default()))));
+        let err = Interpreter::new(&program, 100, 64).with_syscall(Opcode::VoikeQuery, query).run().unwrap_err();
+        assert_eq!(err.kind, "instruction_limit");
+
+        let halting = Program {
+            instructions: program.instructions[..4].to_vec(),
+        };
+        let query = value_syscall(|query| Ok(Value::from(format!("rows for {}", query.as_str().unwrap_or_default()))));
+        let run = Interpreter::new(&halting, 100, 64).with_syscall(Opcode::VoikeQuery, query).run().unwrap();
+        assert_eq!(run.output, ["rows for select 1"]);
+        assert_eq!(run.registers["r3"], Value::from(0));
+        assert_eq!(run.debug, ["VOIKE_BLOB: no handler on this node"]);
+
+        let underflow: Program = serde_json::from_value(serde_json::json!({ "instructions": [{ "op": "POP", "args": ["r0"] }] })).unwrap();
+        assert_eq!(Interpreter::new(&underflow, 100, 64).run().unwrap_err().kind, "vasm_fault");
+    }
+}
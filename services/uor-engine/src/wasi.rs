@@ -0,0 +1,127 @@
+use cap_std::{ambient_authority, fs::Dir};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+use wasmtime_wasi::preview2::{pipe::MemoryOutputPipe, preview1::WasiPreview1Adapter, DirPerms, FilePerms, Table, WasiCtx, WasiCtxBuilder};
+
+/// Upper bound on captured stdout/stderr per stream; further guest writes fail.
+const MAX_CAPTURED_OUTPUT: usize = 64 * 1024;
+
+/// Host variables with this prefix carry engine secrets and are never passed to guests.
+const ENGINE_ENV_PREFIX: &str = "UOR_";
+
+/// Per-module WASI sandbox, set through `PUT /modules/{name}/wasi`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WasiConfig {
+    #[serde(default)]
+    pub preopens: Vec<Preopen>,
+    /// Host environment variables the guest may read. Only an admin may set these.
+    #[serde(default)]
+    pub inherit_env: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// A host directory (relative to the module's WASI root) mapped to a guest path.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Preopen {
+    pub host: String,
+    pub guest: String,
+    #[serde(default)]
+    pub writable: bool,
+}
+
+impl WasiConfig {
+    /// Rejects preopens that could escape the WASI root.
+    pub fn validate(&self) -> Result<(), String> {
+        for preopen in &self.preopens {
+            let escapes = Path::new(&preopen.host)
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+            if escapes {
+                return Err(format!("preopen `{}` must be a relative path without `..`", preopen.host));
+            }
+            if preopen.guest.is_empty() {
+                return Err(format!("preopen `{}` needs a guest path", preopen.host));
+            }
+        }
+        if let Some(name) = self.inherit_env.iter().find(|name| name.starts_with(ENGINE_ENV_PREFIX)) {
+            return Err(format!("`{}` is reserved for the engine and cannot be inherited", name));
+        }
+        Ok(())
+    }
+}
+
+/// Directory a module's preopens resolve under: `root` for shared modules, `root/projects/{project}`
+/// for a project's, so one project's sandbox never reaches another's files.
+pub fn module_root(root: &Path, project: Option<&str>) -> PathBuf {
+    match project {
+        Some(project) => root.join("projects").join(project),
+        None => root.to_path_buf(),
+    }
+}
+
+/// WASI state owned by a single invocation's store.
+pub struct WasiState {
+    pub table: Table,
+    pub ctx: WasiCtx,
+    pub adapter: WasiPreview1Adapter,
+}
+
+/// Handles to the guest's captured stdio.
+pub struct CapturedOutput {
+    stdout: MemoryOutputPipe,
+    stderr: MemoryOutputPipe,
+}
+
+impl CapturedOutput {
+    pub fn stdout(&self) -> String {
+        String::from_utf8_lossy(&self.stdout.contents()).into_owned()
+    }
+
+    pub fn stderr(&self) -> String {
+        String::from_utf8_lossy(&self.stderr.contents()).into_owned()
+    }
+}
+
+/// Builds a WASI context for `config`, with preopens resolved under `root`.
+pub fn build(config: &WasiConfig, root: &Path) -> Result<(WasiState, CapturedOutput), String> {
+    let stdout = MemoryOutputPipe::new(MAX_CAPTURED_OUTPUT);
+    let stderr = MemoryOutputPipe::new(MAX_CAPTURED_OUTPUT);
+    let mut builder = WasiCtxBuilder::new();
+    builder.stdout(stdout.clone()).stderr(stderr.clone()).args(&config.args);
+    for name in config.inherit_env.iter().filter(|name| !name.starts_with(ENGINE_ENV_PREFIX)) {
+        if let Ok(value) = std::env::var(name) {
+            builder.env(name, value);
+        }
+    }
+    for (key, value) in &config.env {
+        builder.env(key, value);
+    }
+    for preopen in &config.preopens {
+        let host = root.join(&preopen.host);
+        if preopen.writable {
+            fs::create_dir_all(&host).map_err(|err| format!("cannot create {}: {}", host.display(), err))?;
+        }
+        let dir = Dir::open_ambient_dir(&host, ambient_authority()).map_err(|err| format!("cannot open {}: {}", host.display(), err))?;
+        let (dir_perms, file_perms) = if preopen.writable {
+            (DirPerms::all(), FilePerms::all())
+        } else {
+            (DirPerms::READ, FilePerms::READ)
+        };
+        builder.preopened_dir(dir, dir_perms, file_perms, &preopen.guest);
+    }
+    Ok((
+        WasiState {
+            table: Table::new(),
+            ctx: builder.build(),
+            adapter: WasiPreview1Adapter::new(),
+        },
+        CapturedOutput { stdout, stderr },
+    ))
+}
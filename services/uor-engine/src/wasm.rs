@@ -0,0 +1,693 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+use tokio::sync::Notify;
+use wasmtime::{Caller, Config, Engine, Linker, Memory, Module, Store, Trap, UpdateDeadline, Val, ValType};
+use wasmtime_wasi::preview2::{
+    preview1::{self, WasiPreview1Adapter, WasiPreview1View},
+    I32Exit, Table, WasiCtx, WasiView,
+};
+
+use crate::{
+    apps::{self, HttpExchange, HttpResponse},
+    cache::ModuleCache,
+    config::ConfigStore,
+    events::{Event, EventBus},
+    grid::{self, GridExchange, GridOutput},
+    invocations::{Invocation, InvocationHistory},
+    jobs::JobQueue,
+    kv::{self, KvHandle, KvStore},
+    logging::{self, GuestLog, InvocationTags, LogRecord, LogStore},
+    limits::{EpochTicker, ExecutionLimits, GuestLimiter},
+    metrics::Metrics,
+    registry::{self, ModuleRegistry},
+    schedules::ScheduleStore,
+    wasi::{self, CapturedOutput, WasiConfig, WasiState},
+};
+
+/// Compiled WASM state shared by request handlers and the sampler.
+pub struct WasmRuntime {
+    pub engine: Engine,
+    pub registry: Mutex<ModuleRegistry>,
+    pub cache: ModuleCache,
+    pub config: ConfigStore,
+    pub ticker: Arc<EpochTicker>,
+    /// Host directory that WASI preopens are resolved against.
+    pub wasi_root: PathBuf,
+    /// Guest key-value store; `None` when disabled or it failed to open.
+    pub kv: Option<Arc<KvStore>>,
+    /// Why the key-value store failed to open on boot.
+    pub kv_error: Option<String>,
+    pub metrics: Metrics,
+    pub events: EventBus,
+    /// Log tails of recent invocations.
+    pub logs: LogStore,
+    pub invocations: InvocationHistory,
+    pub jobs: JobQueue,
+    pub schedules: ScheduleStore,
+    /// Wakes the status sampler ahead of its next scheduled sample.
+    pub wake: Notify,
+    in_flight: AtomicUsize,
+    /// Prefix that keeps invocation ids unique across restarts.
+    boot_id: String,
+    next_invocation: AtomicU64,
+    /// Preload paths whose last load attempt failed, with the error.
+    preload_failures: Mutex<BTreeMap<PathBuf, String>>,
+    /// Signalled when the last in-flight invocation finishes.
+    idle: Notify,
+    draining: AtomicBool,
+    linker: Linker<StoreState>,
+}
+
+impl WasmRuntime {
+    pub fn new(engine: Engine, registry: ModuleRegistry, cache: ModuleCache, config: ConfigStore) -> Result<Self, wasmtime::Error> {
+        let mut linker = Linker::new(&engine);
+        preview1::add_to_linker_sync(&mut linker)?;
+        kv::add_to_linker(&mut linker)?;
+        logging::add_to_linker(&mut linker)?;
+        apps::add_to_linker(&mut linker)?;
+        grid::add_to_linker(&mut linker)?;
+        let storage = config.get().storage.clone();
+        let (kv, kv_error) = match Some(&storage.kv_path).filter(|path| !path.as_os_str().is_empty()).map(|path| KvStore::open(path)) {
+            Some(Ok(store)) => (Some(Arc::new(store)), None),
+            Some(Err(err)) => {
+                eprintln!("[uor-engine] key-value store unavailable: {}", err);
+                (None, Some(err))
+            }
+            None => (None, None),
+        };
+        Ok(Self {
+            ticker: EpochTicker::start(engine.clone()),
+            wasi_root: storage.wasi_root,
+            kv,
+            kv_error,
+            engine,
+            registry: Mutex::new(registry),
+            cache,
+            config,
+            metrics: Metrics::default(),
+            events: EventBus::default(),
+            logs: LogStore::default(),
+            invocations: InvocationHistory::default(),
+            jobs: JobQueue::default(),
+            schedules: ScheduleStore::open(&storage.schedules_file),
+            wake: Notify::new(),
+            in_flight: AtomicUsize::new(0),
+            boot_id: format!("{:x}", chrono::Utc::now().timestamp_millis()),
+            next_invocation: AtomicU64::new(1),
+            preload_failures: Mutex::default(),
+            idle: Notify::new(),
+            draining: AtomicBool::new(false),
+            linker,
+        })
+    }
+
+    /// Compiles and registers module files as shared modules, logging the ones that fail. Blocks
+    /// while compiling; the registry is only locked to insert each finished module.
+    pub fn preload(&self, paths: &[PathBuf]) {
+        for path in paths {
+            let loaded = registry::compile_file(&self.engine, &self.cache, path).and_then(|(name, bytes, compiled)| {
+                let mut registry = self.registry.lock().map_err(|_| wasmtime::Error::msg("module registry unavailable"))?;
+                Ok(registry.insert(&name, &bytes, compiled, None))
+            });
+            if let Ok(mut failures) = self.preload_failures.lock() {
+                match &loaded {
+                    Ok(_) => failures.remove(path),
+                    Err(err) => failures.insert(path.clone(), format!("{:#}", err)),
+                };
+            }
+            match loaded {
+                Ok(info) => {
+                    println!("[uor-engine] loaded module {} ({}) from {} in {:.1} ms", info.name, info.hash, info.source, info.load_ms);
+                    self.metrics.record_compile(&info);
+                    self.events.publish(Event::ModuleLoaded {
+                        name: info.name,
+                        hash: info.hash,
+                        source: info.source,
+                    });
+                }
+                Err(err) => eprintln!("[uor-engine] failed to load {}: {:#}", path.display(), err),
+            }
+        }
+    }
+
+    pub fn preload_failures(&self) -> BTreeMap<PathBuf, String> {
+        self.preload_failures.lock().map(|failures| failures.clone()).unwrap_or_default()
+    }
+
+    /// Default limits from the current config.
+    pub fn limits(&self) -> ExecutionLimits {
+        self.config.get().limits.clone()
+    }
+
+    /// Fresh id for an invocation, e.g. `18f3a2c7b10-42`.
+    pub fn invocation_id(&self) -> String {
+        format!("{}-{}", self.boot_id, self.next_invocation.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Counts an invocation as queued until the returned guard is dropped. The guard owns a
+    /// handle to the runtime so it can move onto the blocking worker that runs the guest.
+    pub fn begin_invocation(self: &Arc<Self>) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self.clone())
+    }
+
+    /// Invocations waiting for or running on a blocking worker.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Waits until no invocation is in flight.
+    pub async fn wait_idle(&self) {
+        while self.in_flight() > 0 {
+            self.idle.notified().await;
+        }
+    }
+
+    /// Stops accepting new work; `/status` reports `draining` from here on.
+    pub fn begin_drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+impl WasmRuntime {
+    /// Runtime on `config` with the module cache, key-value store and schedule file switched off.
+    pub fn for_tests(mut config: crate::config::EngineConfig) -> Arc<Self> {
+        config.storage = crate::config::StorageConfig {
+            cache_dir: PathBuf::new(),
+            wasi_root: std::env::temp_dir().join(format!("uor-wasi-test-{}", std::process::id())),
+            kv_path: PathBuf::new(),
+            schedules_file: PathBuf::new(),
+            metrics_file: None,
+        };
+        let engine = build_engine().expect("engine");
+        let cache = ModuleCache::new(&engine, None);
+        Arc::new(Self::new(engine, ModuleRegistry::default(), cache, ConfigStore::fixed(config)).expect("linker"))
+    }
+
+    /// Compiles `wat` and registers it under `name`.
+    pub fn load_wat(&self, name: &str, wat: &str, project: Option<&str>) -> crate::registry::ModuleInfo {
+        let compiled = self.cache.compile(&self.engine, wat.as_bytes()).expect("module compiles");
+        self.registry.lock().unwrap().insert(name, wat.as_bytes(), compiled, project.map(str::to_string))
+    }
+}
+
+pub struct InFlight(Arc<WasmRuntime>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_one();
+        }
+    }
+}
+
+/// A compiled module kept warm between invocations.
+#[derive(Clone)]
+pub struct LoadedModule {
+    pub name: String,
+    pub module: Module,
+    /// Owning project, which also picks the module's key-value namespace.
+    pub project: Option<String>,
+    pub wasi: WasiConfig,
+}
+
+/// Per-invocation store data.
+pub struct StoreState {
+    limiter: GuestLimiter,
+    wasi: WasiState,
+    pub kv: KvHandle,
+    pub log: GuestLog,
+    pub http: HttpExchange,
+    pub grid: GridExchange,
+}
+
+impl WasiView for StoreState {
+    fn table(&self) -> &Table {
+        &self.wasi.table
+    }
+
+    fn table_mut(&mut self) -> &mut Table {
+        &mut self.wasi.table
+    }
+
+    fn ctx(&self) -> &WasiCtx {
+        &self.wasi.ctx
+    }
+
+    fn ctx_mut(&mut self) -> &mut WasiCtx {
+        &mut self.wasi.ctx
+    }
+}
+
+impl WasiPreview1View for StoreState {
+    fn adapter(&self) -> &WasiPreview1Adapter {
+        &self.wasi.adapter
+    }
+
+    fn adapter_mut(&mut self) -> &mut WasiPreview1Adapter {
+        &mut self.wasi.adapter
+    }
+}
+
+#[derive(Serialize)]
+pub struct InvokeResult {
+    pub invocation_id: String,
+    pub module: String,
+    pub export: String,
+    pub results: Vec<Value>,
+    /// Set when a WASI guest called `proc_exit`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub fuel_consumed: u64,
+    pub peak_memory_bytes: usize,
+    pub instantiate_ms: f64,
+    pub call_ms: f64,
+    pub wall_ms: f64,
+    /// Tail of the guest's `voike.log` records.
+    pub logs: Vec<LogRecord>,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub logs_dropped: u64,
+    /// What the guest answered, for invocations made by `/apps` requests.
+    #[serde(skip)]
+    pub response: Option<HttpResponse>,
+    /// What the guest set, for invocations that run grid jobs.
+    #[serde(skip)]
+    pub grid: Option<GridOutput>,
+}
+
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvokeError {
+    pub error: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+    /// Set once the failure belongs to an invocation.
+    #[serde(flatten)]
+    pub invocation: Option<Box<FailedInvocation>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailedInvocation {
+    pub invocation_id: String,
+    pub fuel_consumed: u64,
+    pub peak_memory_bytes: usize,
+    /// Tail of what the guest logged before failing.
+    pub logs: Vec<LogRecord>,
+}
+
+impl InvokeError {
+    pub fn new(error: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            error,
+            message: message.into(),
+            stdout: None,
+            stderr: None,
+            invocation: None,
+        }
+    }
+
+    fn with_output(mut self, output: &CapturedOutput) -> Self {
+        self.stdout = Some(output.stdout());
+        self.stderr = Some(output.stderr());
+        self
+    }
+}
+
+/// Engine with fuel metering and epoch interruption enabled so every store can be budgeted.
+pub fn build_engine() -> Result<Engine, wasmtime::Error> {
+    let mut config = Config::new();
+    config.consume_fuel(true).epoch_interruption(true);
+    Engine::new(&config)
+}
+
+/// Instantiates the invocation's module in a fresh store and calls its export with JSON-encoded
+/// arguments. The log tail is kept in `runtime.logs` under the invocation id whatever the outcome.
+/// Setting `cancel` stops the guest at the next epoch tick with a `cancelled` error.
+pub fn invoke(runtime: &WasmRuntime, invocation: Invocation) -> Result<InvokeResult, InvokeError> {
+    let recorded_project = invocation.recorded_project();
+    let Invocation {
+        id: invocation_id,
+        loaded,
+        export,
+        args,
+        limits,
+        project,
+        cancel,
+        http,
+        grid,
+        ..
+    } = invocation;
+    let (invocation_id, loaded, export, args, limits) = (invocation_id.as_str(), &loaded, export.as_str(), args.as_slice(), &limits);
+    let wall = Instant::now();
+    runtime.wake.notify_one();
+    let with_id = |mut err: InvokeError| {
+        err.invocation = Some(Box::new(FailedInvocation {
+            invocation_id: invocation_id.to_string(),
+            fuel_consumed: 0,
+            peak_memory_bytes: 0,
+            logs: Vec::new(),
+        }));
+        err
+    };
+    let (wasi, output) = wasi::build(&loaded.wasi, &wasi::module_root(&runtime.wasi_root, loaded.project.as_deref())).map_err(|message| with_id(InvokeError::new("wasi_config", message)))?;
+    let config = runtime.config.get();
+    let tags = InvocationTags {
+        invocation_id: invocation_id.to_string(),
+        module: loaded.name.clone(),
+        project: recorded_project,
+    };
+    let mut store = Store::new(
+        &runtime.engine,
+        StoreState {
+            limiter: GuestLimiter::new(limits),
+            wasi,
+            kv: KvHandle {
+                store: runtime.kv.clone(),
+                namespace: kv::namespace(&loaded.name, loaded.project.as_deref(), project.as_deref()),
+                config: config.kv.clone(),
+            },
+            log: GuestLog::new(tags, config.logging.clone()),
+            http: HttpExchange::new(http, config.apps.max_response_bytes),
+            grid: GridExchange::new(grid, config.grid.max_result_bytes),
+        },
+    );
+    let outcome = budget(&mut store, limits, cancel).and_then(|()| call(runtime, &mut store, loaded, export, args, wall));
+    let fuel_consumed = limits.fuel.saturating_sub(store.get_fuel().unwrap_or(0));
+    let peak_memory_bytes = store.data().limiter.peak_memory_bytes;
+    let data = store.into_data();
+    let logs = data.log.finish();
+    let response = data.http.finish();
+    let grid = data.grid.finish();
+    runtime.logs.push(logs.clone(), config.logging.retained_invocations);
+    match outcome {
+        Ok(mut result) => {
+            result.invocation_id = invocation_id.to_string();
+            result.fuel_consumed = fuel_consumed;
+            result.peak_memory_bytes = peak_memory_bytes;
+            result.stdout = output.stdout();
+            result.stderr = output.stderr();
+            result.logs = logs.logs;
+            result.logs_dropped = logs.dropped;
+            result.response = response;
+            result.grid = grid;
+            Ok(result)
+        }
+        Err(err) => {
+            let mut err = InvokeError {
+                invocation: Some(Box::new(FailedInvocation {
+                    invocation_id: invocation_id.to_string(),
+                    fuel_consumed,
+                    peak_memory_bytes,
+                    logs: logs.logs,
+                })),
+                ..err
+            };
+            if !matches!(err.error, "unknown_export" | "bad_arguments") {
+                err = err.with_output(&output);
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Applies fuel, memory and table caps, and the timeout. A cancellable store is checked on every
+/// epoch tick instead of only at the deadline.
+fn budget(store: &mut Store<StoreState>, limits: &ExecutionLimits, cancel: Option<Arc<AtomicBool>>) -> Result<(), InvokeError> {
+    store.limiter(|state| &mut state.limiter);
+    store
+        .set_fuel(limits.fuel)
+        .map_err(|err| InvokeError::new("internal", err.to_string()))?;
+    let deadline = limits.epoch_deadline();
+    let Some(cancel) = cancel else {
+        store.set_epoch_deadline(deadline);
+        return Ok(());
+    };
+    let mut ticks = 0;
+    store.set_epoch_deadline(1);
+    store.epoch_deadline_callback(move |mut ctx| {
+        ticks += 1;
+        if cancel.load(Ordering::SeqCst) {
+            ctx.data_mut().limiter.tripped = Some("cancelled");
+            return Err(wasmtime::Error::msg("invocation cancelled"));
+        }
+        if ticks >= deadline {
+            return Err(Trap::Interrupt.into());
+        }
+        Ok(UpdateDeadline::Continue(1))
+    });
+    Ok(())
+}
+
+fn call(
+    runtime: &WasmRuntime,
+    store: &mut Store<StoreState>,
+    loaded: &LoadedModule,
+    export: &str,
+    args: &[Value],
+    wall: Instant,
+) -> Result<InvokeResult, InvokeError> {
+    let _running = runtime.ticker.enter();
+
+    let instance = runtime
+        .linker
+        .instantiate(&mut *store, &loaded.module)
+        .map_err(|err| classify(store, err, "instantiate_failed"))?;
+    let instantiate_ms = ms_since(wall);
+
+    let func = instance
+        .get_func(&mut *store, export)
+        .ok_or_else(|| InvokeError::new("unknown_export", format!("module `{}` has no function export `{}`", loaded.name, export)))?;
+    let ty = func.ty(&*store);
+    let params: Vec<ValType> = ty.params().collect();
+    if params.len() != args.len() {
+        return Err(InvokeError::new(
+            "bad_arguments",
+            format!("`{}` expects {} argument(s), got {}", export, params.len(), args.len()),
+        ));
+    }
+    let params = params
+        .iter()
+        .zip(args)
+        .enumerate()
+        .map(|(idx, (ty, arg))| json_to_val(ty, arg).ok_or_else(|| InvokeError::new("bad_arguments", format!("argument {} is not a valid {}", idx, ty))))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut results = vec![Val::I32(0); ty.results().len()];
+
+    let call_start = Instant::now();
+    let exit_code = match func.call(&mut *store, &params, &mut results) {
+        Ok(()) => None,
+        Err(err) => match err.downcast_ref::<I32Exit>() {
+            Some(exit) => {
+                results.clear();
+                Some(exit.0)
+            }
+            None => return Err(classify(store, err, "trap")),
+        },
+    };
+    let call_ms = ms_since(call_start);
+
+    Ok(InvokeResult {
+        invocation_id: String::new(),
+        module: loaded.name.clone(),
+        export: export.to_string(),
+        results: results.iter().map(val_to_json).collect(),
+        exit_code,
+        stdout: String::new(),
+        stderr: String::new(),
+        fuel_consumed: 0,
+        peak_memory_bytes: 0,
+        instantiate_ms,
+        call_ms,
+        wall_ms: ms_since(wall),
+        logs: Vec::new(),
+        logs_dropped: 0,
+        response: None,
+        grid: None,
+    })
+}
+
+/// Names the limit that stopped the guest, falling back to `fallback` for ordinary failures.
+fn classify(store: &Store<StoreState>, err: wasmtime::Error, fallback: &'static str) -> InvokeError {
+    let kind = if let Some(tripped) = store.data().limiter.tripped {
+        tripped
+    } else {
+        match err.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => "fuel_exhausted",
+            Some(Trap::Interrupt) => "timeout",
+            _ => fallback,
+        }
+    };
+    InvokeError::new(kind, format!("{:#}", err))
+}
+
+fn memory(caller: &mut Caller<'_, StoreState>) -> wasmtime::Result<Memory> {
+    caller
+        .get_export("memory")
+        .and_then(|export| export.into_memory())
+        .ok_or_else(|| wasmtime::Error::msg("module must export `memory` to use `voike` imports"))
+}
+
+/// Copies `len` bytes at `ptr` out of the calling guest's exported memory.
+pub fn read_guest(caller: &mut Caller<'_, StoreState>, ptr: u32, len: u32) -> wasmtime::Result<Vec<u8>> {
+    let memory = memory(caller)?;
+    memory
+        .data(&caller)
+        .get(ptr as usize..(ptr as usize).saturating_add(len as usize))
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| wasmtime::Error::msg(format!("guest range {}+{} is out of bounds", ptr, len)))
+}
+
+/// Copies `bytes` into the calling guest's exported memory at `ptr`.
+pub fn write_guest(caller: &mut Caller<'_, StoreState>, ptr: u32, bytes: &[u8]) -> wasmtime::Result<()> {
+    let memory = memory(caller)?;
+    memory
+        .write(caller, ptr as usize, bytes)
+        .map_err(|_| wasmtime::Error::msg(format!("guest range {}+{} is out of bounds", ptr, bytes.len())))
+}
+
+fn json_to_val(ty: &ValType, value: &Value) -> Option<Val> {
+    match ty {
+        ValType::I32 => value.as_i64().and_then(|v| i32::try_from(v).ok()).map(Val::I32),
+        ValType::I64 => value.as_i64().map(Val::I64),
+        ValType::F32 => value.as_f64().map(|v| Val::F32((v as f32).to_bits())),
+        ValType::F64 => value.as_f64().map(|v| Val::F64(v.to_bits())),
+        _ => None,
+    }
+}
+
+fn val_to_json(val: &Val) -> Value {
+    match val {
+        Val::I32(v) => json!(v),
+        Val::I64(v) => json!(v),
+        Val::F32(bits) => json!(f32::from_bits(*bits)),
+        Val::F64(bits) => json!(f64::from_bits(*bits)),
+        _ => Value::Null,
+    }
+}
+
+fn ms_since(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+
+    const ADD: &str = r#"(module (func (export "add") (param i32 i32) (result i32) local.get 0 local.get 1 i32.add))"#;
+
+    const LOOP: &str = r#"(module (func (export "spin") (loop $again br $again)))"#;
+
+    const GROW: &str = r#"(module (memory 1) (func (export "grow") (result i32) i32.const 32 memory.grow))"#;
+
+    const WASI: &str = r#"(module
+        (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 16) "out\n")
+        (data (i32.const 32) "err\n")
+        (func (export "run")
+            (i32.store (i32.const 0) (i32.const 16))
+            (i32.store (i32.const 4) (i32.const 4))
+            (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 48)))
+            (i32.store (i32.const 8) (i32.const 32))
+            (i32.store (i32.const 12) (i32.const 4))
+            (drop (call $fd_write (i32.const 2) (i32.const 8) (i32.const 1) (i32.const 48)))
+            (call $proc_exit (i32.const 3))))"#;
+
+    fn invocation(runtime: &WasmRuntime, module: &str, export: &str, args: Vec<Value>, limits: ExecutionLimits) -> Invocation {
+        Invocation {
+            id: runtime.invocation_id(),
+            loaded: runtime.registry.lock().unwrap().checkout(module).expect("module is loaded"),
+            export: export.to_string(),
+            args,
+            limits,
+            caller: "admin".to_string(),
+            project: None,
+            cancel: None,
+            http: None,
+            grid: None,
+        }
+    }
+
+    fn failure(outcome: Result<InvokeResult, InvokeError>) -> InvokeError {
+        match outcome {
+            Ok(result) => panic!("expected a failure, got {:?}", result.results),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn calls_an_export_with_json_arguments() {
+        let runtime = WasmRuntime::for_tests(EngineConfig::default());
+        runtime.load_wat("add", ADD, None);
+        let limits = runtime.limits();
+        let result = invoke(&runtime, invocation(&runtime, "add", "add", vec![json!(2), json!(3)], limits.clone())).unwrap();
+        assert_eq!(result.results, vec![json!(5)]);
+        assert!(result.fuel_consumed > 0);
+        assert!(runtime.logs.get(&result.invocation_id).is_some());
+
+        let missing = failure(invoke(&runtime, invocation(&runtime, "add", "sub", Vec::new(), limits.clone())));
+        assert_eq!(missing.error, "unknown_export");
+        let bad = failure(invoke(&runtime, invocation(&runtime, "add", "add", vec![json!("two"), json!(3)], limits)));
+        assert_eq!(bad.error, "bad_arguments");
+    }
+
+    #[test]
+    fn names_the_limit_that_stopped_the_guest() {
+        let runtime = WasmRuntime::for_tests(EngineConfig::default());
+        runtime.load_wat("loop", LOOP, None);
+        runtime.load_wat("grow", GROW, None);
+        let defaults = runtime.limits();
+
+        let fuel = ExecutionLimits { fuel: 10_000, ..defaults.clone() };
+        let err = failure(invoke(&runtime, invocation(&runtime, "loop", "spin", Vec::new(), fuel)));
+        assert_eq!(err.error, "fuel_exhausted");
+        assert_eq!(err.invocation.map(|failed| failed.fuel_consumed), Some(10_000));
+
+        let timeout = ExecutionLimits {
+            fuel: u64::MAX,
+            timeout_ms: 50,
+            ..defaults.clone()
+        };
+        let err = failure(invoke(&runtime, invocation(&runtime, "loop", "spin", Vec::new(), timeout)));
+        assert_eq!(err.error, "timeout");
+
+        let memory = ExecutionLimits { max_memory_mb: 1, ..defaults };
+        let err = failure(invoke(&runtime, invocation(&runtime, "grow", "grow", Vec::new(), memory)));
+        assert_eq!(err.error, "memory_limit");
+    }
+
+    #[test]
+    fn captures_wasi_stdio_and_exit_code() {
+        let runtime = WasmRuntime::for_tests(EngineConfig::default());
+        runtime.load_wat("wasi", WASI, None);
+        let result = invoke(&runtime, invocation(&runtime, "wasi", "run", Vec::new(), runtime.limits())).unwrap();
+        assert_eq!((result.stdout.as_str(), result.stderr.as_str()), ("out\n", "err\n"));
+        assert_eq!(result.exit_code, Some(3));
+        assert!(result.results.is_empty());
+    }
+}
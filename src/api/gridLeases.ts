@@ -0,0 +1,65 @@
+import type { FastifyInstance } from 'fastify';
+import type { GridHeartbeat, GridLeaseRequest, GridReport, GridService } from '@grid/index';
+import { requireAdmin } from './guards';
+
+export type GridLeaseStore = Pick<GridService, 'leaseJobs' | 'renewLease' | 'completeLeasedJob' | 'failLeasedJob'>;
+
+/**
+ * Routes remote grid workers (uor-engine nodes) use to lease jobs, renew leases and report results.
+ * Workers authenticate as the cluster with the admin token, not with a project key.
+ */
+export const registerGridLeaseRoutes = (app: FastifyInstance, grid: GridLeaseStore) => {
+  app.post('/grid/jobs/lease', { preHandler: requireAdmin }, async (request, reply) => {
+    const body = request.body as GridLeaseRequest;
+    if (!body?.nodeId || !Array.isArray(body.kinds) || !body.kinds.length) {
+      reply.code(400);
+      return { error: 'nodeId and kinds required' };
+    }
+    return grid.leaseJobs(body);
+  });
+
+  app.post('/grid/jobs/:jobId/heartbeat', { preHandler: requireAdmin }, async (request, reply) => {
+    const { jobId } = request.params as { jobId: string };
+    const body = request.body as GridHeartbeat;
+    if (!body?.nodeId) {
+      reply.code(400);
+      return { error: 'nodeId required' };
+    }
+    const lease = await grid.renewLease(jobId, body);
+    if (!lease) {
+      reply.code(409);
+      return { error: 'Lease lost' };
+    }
+    return lease;
+  });
+
+  app.post('/grid/jobs/:jobId/complete', { preHandler: requireAdmin }, async (request, reply) => {
+    const { jobId } = request.params as { jobId: string };
+    const body = request.body as GridReport;
+    if (!body?.nodeId) {
+      reply.code(400);
+      return { error: 'nodeId required' };
+    }
+    const job = await grid.completeLeasedJob(jobId, body);
+    if (!job) {
+      reply.code(409);
+      return { error: 'Lease lost' };
+    }
+    return job;
+  });
+
+  app.post('/grid/jobs/:jobId/fail', { preHandler: requireAdmin }, async (request, reply) => {
+    const { jobId } = request.params as { jobId: string };
+    const body = request.body as GridReport;
+    if (!body?.nodeId) {
+      reply.code(400);
+      return { error: 'nodeId required' };
+    }
+    const job = await grid.failLeasedJob(jobId, body);
+    if (!job) {
+      reply.code(409);
+      return { error: 'Lease lost' };
+    }
+    return job;
+  });
+};
@@ -0,0 +1,15 @@
+import type { FastifyReply, FastifyRequest } from 'fastify';
+import config from '@config';
+
+export const ADMIN_HEADER = 'x-voike-admin-token';
+
+/** preHandler for admin routes: requires `ADMIN_HEADER` to match `ADMIN_TOKEN`. */
+export const requireAdmin = async (request: FastifyRequest, reply: FastifyReply) => {
+  if (!config.auth.adminToken) {
+    return reply.code(503).send({ error: 'Admin operations disabled (ADMIN_TOKEN not set).' });
+  }
+  const header = request.headers[ADMIN_HEADER] as string | undefined;
+  if (!header || header !== config.auth.adminToken) {
+    return reply.code(401).send({ error: 'Invalid admin token' });
+  }
+};
@@ -73,6 +73,9 @@ import {
   UserRecord,
   verifyProjectOwnership,
 } from '@auth/index';
+import { ADMIN_HEADER, requireAdmin } from './guards';
+import { registerGridLeaseRoutes } from './gridLeases';
+import { registerMeshNodeRoutes } from './meshNodes';
 
 type DocsPayload = {
   name: string;
@@ -973,7 +976,7 @@ export const buildServer = ({
 }): FastifyInstance => {
   const app = Fastify({ logger: logger as unknown as FastifyBaseLogger });
   const apiKeyHeaderName = 'x-voike-api-key';
-  const adminHeaderName = 'x-voike-admin-token';
+  const adminHeaderName = ADMIN_HEADER;
 
   const docsPayload: DocsPayload = {
     name: 'VOIKE-X Backend',
@@ -1009,7 +1012,7 @@ export const buildServer = ({
       blobgrid: ['/blobs (POST)', '/blobs/:id/manifest', '/blobs/:id/stream'],
       edge: ['/edge/sync', '/edge/cache', '/edge/llm'],
       irx: ['/irx/objects', '/irx/hints'],
-      grid: ['/grid/jobs', '/grid/jobs/:id'],
+      grid: ['/grid/jobs', '/grid/jobs/:id', '/grid/jobs/lease', '/grid/jobs/:id/heartbeat', '/grid/jobs/:id/complete', '/grid/jobs/:id/fail'],
       playground: ['/playground/sessions', '/playground/snippets', '/playground/datasets'],
       capsules: ['/capsules', '/capsules/:id', '/capsules/:id/restore'],
       ai: ['/ai/atlas', '/ai/ask', '/ai/policy', '/ai/irx/*', '/ai/pipelines/analyze', '/ai/capsule/*'],
@@ -1107,16 +1110,6 @@ export const buildServer = ({
   });
 
   app.register(multipart);
-  const requireAdmin = async (request: FastifyRequest, reply: FastifyReply) => {
-    if (!config.auth.adminToken) {
-      return reply.code(503).send({ error: 'Admin operations disabled (ADMIN_TOKEN not set).' });
-    }
-    const header = request.headers[adminHeaderName] as string | undefined;
-    if (!header || header !== config.auth.adminToken) {
-      return reply.code(401).send({ error: 'Invalid admin token' });
-    }
-  };
-
   const requireApiKey = async (request: FastifyRequest, reply: FastifyReply) => {
     const header = request.headers[apiKeyHeaderName] as string | undefined;
     if (!header) {
@@ -1879,6 +1872,8 @@ export const buildServer = ({
     return job;
   });
 
+  registerGridLeaseRoutes(app, grid);
+
   app.post('/playground/sessions', { preHandler: requireApiKey }, async (request) => {
     const body = request.body as { name?: string };
     const sessionId = await playground.createSession(request.project!.id, body?.name);
@@ -1976,6 +1971,8 @@ export const buildServer = ({
 
   app.get('/mesh/nodes', { preHandler: requireAdmin }, async () => mesh.listPeers());
 
+  registerMeshNodeRoutes(app, mesh);
+
   app.get('/hypermesh/status', { preHandler: requireAdmin }, async () => hypermesh.getStatus());
   app.get('/hypermesh/routes', { preHandler: requireAdmin }, async () => hypermesh.getRoutes());
   app.get('/hypermesh/agents', { preHandler: requireAdmin }, async () => hypermesh.listAgents());
@@ -0,0 +1,35 @@
+import type { FastifyInstance } from 'fastify';
+import type { MeshNodeAnnouncement, MeshService } from '@mesh/index';
+import { requireAdmin } from './guards';
+
+export type MeshNodeStore = Pick<MeshService, 'getSelf' | 'upsertNode' | 'removeNode'>;
+
+/** Routes nodes use to register themselves with the control plane's mesh and to leave it. */
+export const registerMeshNodeRoutes = (app: FastifyInstance, mesh: MeshNodeStore) => {
+  app.put('/mesh/nodes/:nodeId', { preHandler: requireAdmin }, async (request, reply) => {
+    const { nodeId } = request.params as { nodeId: string };
+    const body = (request.body || {}) as MeshNodeAnnouncement;
+    if (body.nodeId && body.nodeId !== nodeId) {
+      reply.code(400);
+      return { error: 'nodeId in the body does not match the path' };
+    }
+    if (nodeId === mesh.getSelf()?.nodeId) {
+      reply.code(409);
+      return { error: 'Cannot overwrite the control plane node' };
+    }
+    return mesh.upsertNode(nodeId, body);
+  });
+
+  app.delete('/mesh/nodes/:nodeId', { preHandler: requireAdmin }, async (request, reply) => {
+    const { nodeId } = request.params as { nodeId: string };
+    if (nodeId === mesh.getSelf()?.nodeId) {
+      reply.code(409);
+      return { error: 'Cannot remove the control plane node' };
+    }
+    if (!(await mesh.removeNode(nodeId))) {
+      reply.code(404);
+      return { error: 'Node not found' };
+    }
+    return { nodeId, removed: true };
+  });
+};
@@ -67,6 +67,8 @@ export type AppConfig = {
   };
   grid: {
     schedulerIntervalMs: number;
+    maxLeaseMs: number;
+    maxAttempts: number;
   };
   edge: {
     enabled: boolean;
@@ -147,6 +149,8 @@ export const config: AppConfig = {
   },
   grid: {
     schedulerIntervalMs: parseNumber(process.env.GRID_SCHEDULER_INTERVAL_MS, 1000),
+    maxLeaseMs: parseNumber(process.env.GRID_MAX_LEASE_MS, 5 * 60 * 1000),
+    maxAttempts: parseNumber(process.env.GRID_MAX_ATTEMPTS, 3),
   },
   edge: {
     enabled: process.env.EDGE_MODE === 'true' || false,
@@ -74,6 +74,34 @@ export type GridJobPayload = {
   inputRefs?: Record<string, unknown>;
 };
 
+/** `POST /grid/jobs/lease` body sent by engine nodes that run grid jobs. */
+export type GridLeaseRequest = {
+  nodeId: string;
+  kinds: string[];
+  /** `params.task` values of `custom` jobs the node can run. */
+  tasks?: string[];
+  max?: number;
+  leaseMs?: number;
+};
+
+export type GridHeartbeat = {
+  nodeId: string;
+  leaseMs?: number;
+  progress?: number;
+  message?: string;
+};
+
+export type GridReport = {
+  nodeId: string;
+  invocationId?: string;
+  result?: unknown;
+  resultRefs?: Record<string, string>;
+  error?: string;
+  message?: string;
+  retry?: boolean;
+  logs?: unknown[];
+};
+
 export class GridService {
   private scheduler?: NodeJS.Timeout;
 
@@ -95,6 +123,13 @@ export class GridService {
         updated_at TIMESTAMPTZ DEFAULT NOW()
       )
     `);
+    await this.pool.query(`
+      ALTER TABLE grid_jobs
+        ADD COLUMN IF NOT EXISTS attempt INTEGER NOT NULL DEFAULT 0,
+        ADD COLUMN IF NOT EXISTS lease_expires_at TIMESTAMPTZ,
+        ADD COLUMN IF NOT EXISTS progress JSONB,
+        ADD COLUMN IF NOT EXISTS result_refs JSONB
+    `);
   }
 
   async submitJob(payload: GridJobPayload) {
@@ -142,6 +177,102 @@ export class GridService {
     }
   }
 
+  /**
+   * Hands up to `max` pending jobs, or jobs whose lease ran out, to a remote node. Jobs that already
+   * used `grid.maxAttempts` fail instead of being leased again.
+   */
+  async leaseJobs(request: GridLeaseRequest) {
+    const max = Math.max(1, Math.min(50, Math.floor(Number(request.max) || 1)));
+    const leaseMs = this.leaseLength(request.leaseMs);
+    await this.pool.query(
+      `
+      UPDATE grid_jobs SET status = 'FAILED', error = 'lease expired', lease_expires_at = NULL, updated_at = NOW()
+      WHERE status = 'RUNNING' AND lease_expires_at < NOW() AND attempt >= $1
+    `,
+      [config.grid.maxAttempts],
+    );
+    const { rows } = await this.pool.query(
+      `
+      UPDATE grid_jobs
+      SET status = 'RUNNING', assigned_node_id = $1, attempt = attempt + 1, progress = NULL,
+          lease_expires_at = NOW() + $2::int * INTERVAL '1 millisecond', updated_at = NOW()
+      WHERE job_id IN (
+        SELECT job_id FROM grid_jobs
+        WHERE (status = 'PENDING' OR (status = 'RUNNING' AND lease_expires_at < NOW()))
+          AND type = ANY($3::text[])
+          AND (type <> 'custom' OR params->>'task' = ANY($4::text[]))
+        ORDER BY created_at ASC
+        LIMIT $5
+        FOR UPDATE SKIP LOCKED
+      )
+      RETURNING *
+    `,
+      [request.nodeId, leaseMs, request.kinds, request.tasks || [], max],
+    );
+    return {
+      jobs: rows.map((row) => ({
+        jobId: row.job_id,
+        projectId: row.project_id,
+        type: row.type,
+        params: row.params || {},
+        inputRefs: row.input_refs || {},
+        attempt: row.attempt,
+        leaseMs,
+      })),
+    };
+  }
+
+  /** Renews a lease held by `nodeId`; null once the job was handed elsewhere or finished. */
+  async renewLease(jobId: string, heartbeat: GridHeartbeat) {
+    const leaseMs = this.leaseLength(heartbeat.leaseMs);
+    const progress = heartbeat.progress === undefined ? null : { progress: heartbeat.progress, message: heartbeat.message };
+    const { rows } = await this.pool.query(
+      `
+      UPDATE grid_jobs
+      SET lease_expires_at = NOW() + $3::int * INTERVAL '1 millisecond', progress = COALESCE($4, progress), updated_at = NOW()
+      WHERE job_id = $1 AND assigned_node_id = $2 AND status = 'RUNNING' AND lease_expires_at IS NOT NULL
+      RETURNING job_id
+    `,
+      [jobId, heartbeat.nodeId, leaseMs, progress],
+    );
+    return rows[0] ? { jobId, leaseMs } : null;
+  }
+
+  async completeLeasedJob(jobId: string, report: GridReport) {
+    const { rows } = await this.pool.query(
+      `
+      UPDATE grid_jobs
+      SET status = 'SUCCEEDED', result = $3, result_refs = $4, error = NULL, lease_expires_at = NULL, updated_at = NOW()
+      WHERE job_id = $1 AND assigned_node_id = $2 AND status = 'RUNNING' AND lease_expires_at IS NOT NULL
+      RETURNING job_id, status
+    `,
+      [jobId, report.nodeId, report.result ?? {}, report.resultRefs || {}],
+    );
+    return rows[0] ? { jobId, status: rows[0].status } : null;
+  }
+
+  /** Records a failed run; retryable failures go back to PENDING until `grid.maxAttempts` is used up. */
+  async failLeasedJob(jobId: string, report: GridReport) {
+    const error = [report.error || 'failed', report.message].filter(Boolean).join(': ');
+    const { rows } = await this.pool.query(
+      `
+      UPDATE grid_jobs
+      SET status = CASE WHEN $3 AND attempt < $4 THEN 'PENDING' ELSE 'FAILED' END,
+          assigned_node_id = CASE WHEN $3 AND attempt < $4 THEN NULL ELSE assigned_node_id END,
+          error = $5, lease_expires_at = NULL, updated_at = NOW()
+      WHERE job_id = $1 AND assigned_node_id = $2 AND status = 'RUNNING' AND lease_expires_at IS NOT NULL
+      RETURNING job_id, status
+    `,
+      [jobId, report.nodeId, report.retry === true, config.grid.maxAttempts, error],
+    );
+    return rows[0] ? { jobId, status: rows[0].status } : null;
+  }
+
+  private leaseLength(requested?: number) {
+    const leaseMs = Math.floor(Number(requested) || 30_000);
+    return Math.max(1000, Math.min(config.grid.maxLeaseMs, leaseMs));
+  }
+
   startScheduler() {
     if (this.scheduler) return;
     this.scheduler = setInterval(() => this.tick().catch((err) => logger.error({ err }, 'grid tick')), config.grid.schedulerIntervalMs);
@@ -171,10 +302,14 @@ export class GridService {
     if (!this.shouldRunJob(job)) {
       return;
     }
-    await this.pool.query(
-      `UPDATE grid_jobs SET status = 'RUNNING', assigned_node_id = $2, updated_at = NOW() WHERE job_id = $1`,
+    const claimed = await this.pool.query(
+      `UPDATE grid_jobs SET status = 'RUNNING', assigned_node_id = $2, attempt = attempt + 1, updated_at = NOW()
+       WHERE job_id = $1 AND status = 'PENDING'`,
       [jobId, config.node.id],
     );
+    if (!claimed.rowCount) {
+      return;
+    }
     try {
       let result: any = {};
       switch (job.type) {
@@ -16,6 +16,16 @@ export type MeshNode = {
   bandwidthClass?: string;
 };
 
+/** Body of `PUT /mesh/nodes/:nodeId`, sent by nodes that register themselves (e.g. uor-engine). */
+export type MeshNodeAnnouncement = {
+  nodeId?: string;
+  roles?: string[];
+  addresses?: Record<string, string>;
+  region?: string;
+  status?: string;
+  [key: string]: unknown;
+};
+
 export type MeshRpcRequest = {
   method: string;
   params?: unknown;
@@ -75,6 +85,50 @@ export class MeshService extends EventEmitter {
     return Array.from(this.peers.values());
   }
 
+  async upsertNode(nodeId: string, announcement: MeshNodeAnnouncement): Promise<MeshNode> {
+    const { nodeId: _nodeId, roles, addresses, status, ...rest } = announcement;
+    const genesisDoc = this.genesis.getGenesis();
+    const meta: Record<string, unknown> = { ...rest, region: announcement.region };
+    const node: MeshNode = {
+      nodeId,
+      clusterId: genesisDoc.clusterId,
+      roles: Array.isArray(roles) ? roles.map(String) : [],
+      addresses: addresses && typeof addresses === 'object' ? addresses : {},
+      status: typeof status === 'string' && status ? status : 'healthy',
+      lastSeenAt: new Date().toISOString(),
+      meta,
+      region: announcement.region,
+    };
+    await this.pool.query(
+      `
+      INSERT INTO mesh_nodes (node_id, cluster_id, roles, addresses, status, meta, last_seen_at)
+      VALUES ($1,$2,$3,$4,$5,$6,NOW())
+      ON CONFLICT (node_id) DO UPDATE
+        SET cluster_id = EXCLUDED.cluster_id,
+            roles = EXCLUDED.roles,
+            addresses = EXCLUDED.addresses,
+            status = EXCLUDED.status,
+            meta = EXCLUDED.meta,
+            last_seen_at = NOW()
+    `,
+      [node.nodeId, node.clusterId, node.roles, node.addresses, node.status, meta],
+    );
+    if (!this.peers.has(nodeId)) {
+      this.emit('node.join', node);
+    }
+    this.peers.set(nodeId, node);
+    return node;
+  }
+
+  async removeNode(nodeId: string): Promise<boolean> {
+    const { rowCount } = await this.pool.query(`DELETE FROM mesh_nodes WHERE node_id = $1`, [nodeId]);
+    const known = this.peers.delete(nodeId);
+    if (known) {
+      this.emit('node.leave', nodeId);
+    }
+    return (rowCount ?? 0) > 0 || known;
+  }
+
   async rpcCall(nodeId: string, request: MeshRpcRequest) {
     const node = this.peers.get(nodeId);
     if (!node) {
@@ -0,0 +1,158 @@
+import Fastify, { FastifyInstance } from 'fastify';
+import config from '@config';
+import { GridService } from '@grid/index';
+import { registerGridLeaseRoutes } from '@api/gridLeases';
+import { GridPool } from '../utils/gridPool';
+
+const ADMIN_TOKEN = 'test-admin-token';
+const admin = { 'x-voike-admin-token': ADMIN_TOKEN };
+
+describe('grid lease routes', () => {
+  const originalToken = config.auth.adminToken;
+  let pool: GridPool;
+  let grid: GridService;
+  let app: FastifyInstance;
+
+  beforeAll(() => {
+    config.auth.adminToken = ADMIN_TOKEN;
+  });
+
+  afterAll(() => {
+    config.auth.adminToken = originalToken;
+  });
+
+  beforeEach(async () => {
+    pool = new GridPool();
+    grid = new GridService(pool as any, {} as any);
+    app = Fastify();
+    registerGridLeaseRoutes(app, grid);
+    await app.ready();
+  });
+
+  afterEach(() => app.close());
+
+  const submit = () => grid.submitJob({ projectId: 'project-a', type: 'custom', params: { task: 'fib', n: 10 } });
+
+  const post = (url: string, payload: Record<string, unknown>) => app.inject({ method: 'POST', url, headers: admin, payload });
+
+  const lease = (nodeId: string) => post('/grid/jobs/lease', { nodeId, kinds: ['custom'], tasks: ['fib'], leaseMs: 30_000 });
+
+  it('requires the admin token', async () => {
+    await submit();
+    const missing = await app.inject({ method: 'POST', url: '/grid/jobs/lease', payload: { nodeId: 'node-a', kinds: ['custom'], tasks: ['fib'] } });
+    expect(missing.statusCode).toBe(401);
+    const projectKey = await app.inject({
+      method: 'POST',
+      url: '/grid/jobs/lease',
+      headers: { 'x-voike-api-key': 'project-key' },
+      payload: { nodeId: 'node-a', kinds: ['custom'], tasks: ['fib'] },
+    });
+    expect(projectKey.statusCode).toBe(401);
+    expect(Array.from(pool.jobs.values())[0].status).toBe('PENDING');
+  });
+
+  it('needs a node id and the kinds it runs', async () => {
+    expect((await post('/grid/jobs/lease', { kinds: ['custom'] })).statusCode).toBe(400);
+    expect((await post('/grid/jobs/lease', { nodeId: 'node-a', kinds: [] })).statusCode).toBe(400);
+    expect((await post('/grid/jobs/some-job/heartbeat', {})).statusCode).toBe(400);
+  });
+
+  it('only leases custom jobs for tasks the node runs', async () => {
+    await submit();
+    const other = await post('/grid/jobs/lease', { nodeId: 'node-a', kinds: ['custom'], tasks: ['resize'] });
+    expect(other.json()).toEqual({ jobs: [] });
+    const media = await post('/grid/jobs/lease', { nodeId: 'node-a', kinds: ['media.transcode'] });
+    expect(media.json()).toEqual({ jobs: [] });
+  });
+
+  it('hands an expired lease to another node', async () => {
+    const jobId = await submit();
+    const first = await lease('node-a');
+    expect(first.statusCode).toBe(200);
+    expect(first.json().jobs).toEqual([
+      { jobId, projectId: 'project-a', type: 'custom', params: { task: 'fib', n: 10 }, inputRefs: {}, attempt: 1, leaseMs: 30_000 },
+    ]);
+    expect((await lease('node-b')).json()).toEqual({ jobs: [] });
+
+    pool.now += 31_000;
+    const second = await lease('node-b');
+    expect(second.json().jobs).toMatchObject([{ jobId, attempt: 2 }]);
+    expect(pool.jobs.get(jobId)).toMatchObject({ status: 'RUNNING', assigned_node_id: 'node-b' });
+
+    const stale = await post(`/grid/jobs/${jobId}/heartbeat`, { nodeId: 'node-a' });
+    expect(stale.statusCode).toBe(409);
+    const late = await post(`/grid/jobs/${jobId}/complete`, { nodeId: 'node-a', result: { fib: '55' } });
+    expect(late.statusCode).toBe(409);
+    expect(pool.jobs.get(jobId).status).toBe('RUNNING');
+  });
+
+  it('fails a job whose lease expired after its last attempt', async () => {
+    const jobId = await submit();
+    for (let attempt = 1; attempt <= config.grid.maxAttempts; attempt += 1) {
+      expect((await lease(`node-${attempt}`)).json().jobs).toMatchObject([{ jobId, attempt }]);
+      pool.now += 31_000;
+    }
+    expect((await lease('node-last')).json()).toEqual({ jobs: [] });
+    expect(pool.jobs.get(jobId)).toMatchObject({ status: 'FAILED', error: 'lease expired' });
+  });
+
+  it('renews and completes a lease only for the node holding it', async () => {
+    const jobId = await submit();
+    await lease('node-a');
+
+    const wrongNode = await post(`/grid/jobs/${jobId}/heartbeat`, { nodeId: 'node-b', progress: 0.5 });
+    expect(wrongNode.statusCode).toBe(409);
+    expect(wrongNode.json()).toEqual({ error: 'Lease lost' });
+
+    const heartbeat = await post(`/grid/jobs/${jobId}/heartbeat`, { nodeId: 'node-a', leaseMs: 60_000, progress: 0.5, message: 'half way' });
+    expect(heartbeat.statusCode).toBe(200);
+    expect(heartbeat.json()).toEqual({ jobId, leaseMs: 60_000 });
+    expect(pool.jobs.get(jobId).progress).toEqual({ progress: 0.5, message: 'half way' });
+
+    // The renewed lease outlives the original 30s one.
+    pool.now += 45_000;
+    expect((await lease('node-b')).json()).toEqual({ jobs: [] });
+
+    expect((await post(`/grid/jobs/${jobId}/complete`, { nodeId: 'node-b', result: {} })).statusCode).toBe(409);
+    const complete = await post(`/grid/jobs/${jobId}/complete`, {
+      nodeId: 'node-a',
+      result: { fib: '55' },
+      resultRefs: { invocation: 'http://engine/invocations/inv-1' },
+    });
+    expect(complete.statusCode).toBe(200);
+    expect(complete.json()).toEqual({ jobId, status: 'SUCCEEDED' });
+    expect(pool.jobs.get(jobId)).toMatchObject({
+      status: 'SUCCEEDED',
+      result: { fib: '55' },
+      result_refs: { invocation: 'http://engine/invocations/inv-1' },
+      lease_expires_at: null,
+    });
+
+    expect((await post(`/grid/jobs/${jobId}/complete`, { nodeId: 'node-a', result: {} })).statusCode).toBe(409);
+    expect((await post(`/grid/jobs/${jobId}/heartbeat`, { nodeId: 'node-a' })).statusCode).toBe(409);
+  });
+
+  it('retries failed runs until the attempts are used up', async () => {
+    const jobId = await submit();
+    for (let attempt = 1; attempt < config.grid.maxAttempts; attempt += 1) {
+      await lease('node-a');
+      const retried = await post(`/grid/jobs/${jobId}/fail`, { nodeId: 'node-a', error: 'trap', message: 'out of fuel', retry: true });
+      expect(retried.json()).toEqual({ jobId, status: 'PENDING' });
+      expect(pool.jobs.get(jobId)).toMatchObject({ assigned_node_id: null, error: 'trap: out of fuel', attempt });
+    }
+    await lease('node-a');
+    expect((await post(`/grid/jobs/${jobId}/fail`, { nodeId: 'node-b', retry: true })).statusCode).toBe(409);
+    const last = await post(`/grid/jobs/${jobId}/fail`, { nodeId: 'node-a', error: 'trap', retry: true });
+    expect(last.json()).toEqual({ jobId, status: 'FAILED' });
+    expect(pool.jobs.get(jobId)).toMatchObject({ status: 'FAILED', assigned_node_id: 'node-a', attempt: config.grid.maxAttempts });
+    expect((await lease('node-b')).json()).toEqual({ jobs: [] });
+  });
+
+  it('fails at once when the node does not ask for a retry', async () => {
+    const jobId = await submit();
+    await lease('node-a');
+    const failed = await post(`/grid/jobs/${jobId}/fail`, { nodeId: 'node-a', error: 'bad_params' });
+    expect(failed.json()).toEqual({ jobId, status: 'FAILED' });
+    expect(pool.jobs.get(jobId)).toMatchObject({ status: 'FAILED', error: 'bad_params', attempt: 1 });
+  });
+});
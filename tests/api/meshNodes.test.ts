@@ -0,0 +1,98 @@
+import Fastify, { FastifyInstance } from 'fastify';
+import config from '@config';
+import { MeshService } from '@mesh/index';
+import { registerMeshNodeRoutes } from '@api/meshNodes';
+import { MockPool } from '../utils/mockPool';
+
+const ADMIN_TOKEN = 'test-admin-token';
+const admin = { 'x-voike-admin-token': ADMIN_TOKEN };
+
+describe('mesh node routes', () => {
+  const originalToken = config.auth.adminToken;
+  let pool: MockPool;
+  let mesh: MeshService;
+  let app: FastifyInstance;
+
+  beforeAll(() => {
+    config.auth.adminToken = ADMIN_TOKEN;
+  });
+
+  afterAll(() => {
+    config.auth.adminToken = originalToken;
+  });
+
+  beforeEach(async () => {
+    pool = new MockPool();
+    const genesis = { getGenesis: () => ({ clusterId: 'cluster-test' }) };
+    mesh = new MeshService(pool as any, genesis as any, {} as any);
+    app = Fastify();
+    registerMeshNodeRoutes(app, mesh);
+    await app.ready();
+  });
+
+  afterEach(() => app.close());
+
+  const announcement = {
+    nodeId: 'edge 1/a',
+    roles: ['wasm'],
+    addresses: { http: 'http://10.0.0.2:9090' },
+    region: 'eu-west',
+    modules: [{ name: 'report', hash: 'abc', project: 'acme' }],
+  };
+
+  it('requires the admin token', async () => {
+    const missing = await app.inject({ method: 'PUT', url: '/mesh/nodes/edge-1', payload: {} });
+    expect(missing.statusCode).toBe(401);
+    const projectKey = await app.inject({
+      method: 'DELETE',
+      url: '/mesh/nodes/edge-1',
+      headers: { 'x-voike-api-key': 'project-key' },
+    });
+    expect(projectKey.statusCode).toBe(401);
+    expect(mesh.listPeers()).toHaveLength(0);
+  });
+
+  it('registers a node under its encoded id and removes it again', async () => {
+    const query = jest.spyOn(pool, 'query');
+    const put = await app.inject({ method: 'PUT', url: '/mesh/nodes/edge%201%2Fa', headers: admin, payload: announcement });
+    expect(put.statusCode).toBe(200);
+    expect(put.json()).toMatchObject({
+      nodeId: 'edge 1/a',
+      clusterId: 'cluster-test',
+      roles: ['wasm'],
+      addresses: { http: 'http://10.0.0.2:9090' },
+      status: 'healthy',
+      region: 'eu-west',
+      meta: { region: 'eu-west', modules: announcement.modules },
+    });
+    const insert = query.mock.calls.find(([sql]) => sql.includes('INSERT INTO mesh_nodes'));
+    expect(insert?.[1]?.[0]).toBe('edge 1/a');
+    expect(mesh.listPeers().map((node) => node.nodeId)).toEqual(['edge 1/a']);
+
+    const again = await app.inject({ method: 'PUT', url: '/mesh/nodes/edge%201%2Fa', headers: admin, payload: { ...announcement, status: 'draining' } });
+    expect(again.json().status).toBe('draining');
+    expect(mesh.listPeers()).toHaveLength(1);
+
+    const removed = await app.inject({ method: 'DELETE', url: '/mesh/nodes/edge%201%2Fa', headers: admin });
+    expect(removed.statusCode).toBe(200);
+    expect(removed.json()).toEqual({ nodeId: 'edge 1/a', removed: true });
+    expect(mesh.listPeers()).toHaveLength(0);
+
+    const unknown = await app.inject({ method: 'DELETE', url: '/mesh/nodes/edge%201%2Fa', headers: admin });
+    expect(unknown.statusCode).toBe(404);
+  });
+
+  it('rejects a body that names another node', async () => {
+    const response = await app.inject({ method: 'PUT', url: '/mesh/nodes/edge-2', headers: admin, payload: announcement });
+    expect(response.statusCode).toBe(400);
+    expect(mesh.listPeers()).toHaveLength(0);
+  });
+
+  it('protects the control plane node itself', async () => {
+    jest.spyOn(mesh, 'getSelf').mockReturnValue({ nodeId: 'control' } as any);
+    const put = await app.inject({ method: 'PUT', url: '/mesh/nodes/control', headers: admin, payload: {} });
+    expect(put.statusCode).toBe(409);
+    const remove = await app.inject({ method: 'DELETE', url: '/mesh/nodes/control', headers: admin });
+    expect(remove.statusCode).toBe(409);
+  });
+});
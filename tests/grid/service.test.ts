@@ -0,0 +1,57 @@
+import config from '@config';
+import { GridService } from '@grid/index';
+import { GridPool } from '../utils/gridPool';
+
+describe('GridService scheduler', () => {
+  const originalNodeId = config.node.id;
+  let pool: GridPool;
+  let grid: GridService;
+
+  beforeAll(() => {
+    config.node.id = 'node-local';
+  });
+
+  afterAll(() => {
+    config.node.id = originalNodeId;
+  });
+
+  beforeEach(() => {
+    pool = new GridPool();
+    grid = new GridService(pool as any, {} as any);
+  });
+
+  const tick = () => (grid as any).tick() as Promise<void>;
+
+  it('runs pending jobs on this node', async () => {
+    const jobId = await grid.submitJob({ projectId: 'project-a', type: 'custom', params: { task: 'fib', n: 10 } });
+    await tick();
+    expect(await grid.getJob(jobId)).toMatchObject({
+      status: 'SUCCEEDED',
+      result: { fib: '55' },
+      assigned_node_id: 'node-local',
+      attempt: 1,
+    });
+  });
+
+  it('records local failures', async () => {
+    const jobId = await grid.submitJob({ projectId: 'project-a', type: 'query.analytics', params: {} });
+    await tick();
+    expect(await grid.getJob(jobId)).toMatchObject({ status: 'FAILED', error: 'sql parameter required for analytics job' });
+  });
+
+  it('leaves jobs that a node leased in the meantime alone', async () => {
+    const jobId = await grid.submitJob({ projectId: 'project-a', type: 'custom', params: { task: 'fib', n: 10 } });
+    const query = pool.query.bind(pool);
+    jest.spyOn(pool, 'query').mockImplementation(async (sql: string, params?: any[]) => {
+      if (sql.startsWith('SELECT * FROM grid_jobs')) {
+        // The scheduler read the job as PENDING just before a remote node leased it.
+        const stale = { ...pool.jobs.get(jobId) };
+        await grid.leaseJobs({ nodeId: 'node-a', kinds: ['custom'], tasks: ['fib'] });
+        return { rows: [stale], rowCount: 1 };
+      }
+      return query(sql, params);
+    });
+    await tick();
+    expect(pool.jobs.get(jobId)).toMatchObject({ status: 'RUNNING', assigned_node_id: 'node-a', attempt: 1, result: null });
+  });
+});
@@ -0,0 +1,126 @@
+type QueryResponse = { rows: any[]; rowCount: number };
+
+const result = (rows: any[]): QueryResponse => ({ rows, rowCount: rows.length });
+
+/**
+ * In-memory `grid_jobs` table for GridService tests. Statements are matched by the fragments that tell
+ * them apart; `now` stands in for the database clock so tests can move leases past their expiry.
+ */
+export class GridPool {
+  now = Date.now();
+  jobs = new Map<string, any>();
+  private sequence = 0;
+
+  async query(sql: string, params: any[] = []): Promise<QueryResponse> {
+    const normalized = sql.replace(/\s+/g, ' ').trim().toLowerCase();
+    if (normalized.startsWith('create table') || normalized.startsWith('alter table')) {
+      return result([]);
+    }
+    if (normalized.startsWith('insert into grid_jobs')) {
+      const [jobId, projectId, type, jobParams, inputRefs] = params;
+      this.jobs.set(jobId, {
+        job_id: jobId,
+        project_id: projectId,
+        type,
+        params: jobParams,
+        input_refs: inputRefs,
+        status: 'PENDING',
+        assigned_node_id: null,
+        result: null,
+        result_refs: null,
+        error: null,
+        attempt: 0,
+        lease_expires_at: null,
+        progress: null,
+        created_at: this.sequence++,
+      });
+      return result([]);
+    }
+    if (normalized.startsWith('select * from grid_jobs where job_id')) {
+      const job = this.jobs.get(params[0]);
+      return result(job ? [{ ...job }] : []);
+    }
+    if (normalized.startsWith("select job_id from grid_jobs where status = 'pending'")) {
+      return result(this.ordered().filter((job) => job.status === 'PENDING').slice(0, 5));
+    }
+    if (normalized.includes("error = 'lease expired'")) {
+      const expired = this.ordered().filter((job) => this.expired(job) && job.attempt >= params[0]);
+      expired.forEach((job) => Object.assign(job, { status: 'FAILED', error: 'lease expired', lease_expires_at: null }));
+      return result(expired);
+    }
+    if (normalized.includes('for update skip locked')) {
+      const [nodeId, leaseMs, kinds, tasks, max] = params;
+      const leased = this.ordered()
+        .filter((job) => job.status === 'PENDING' || this.expired(job))
+        .filter((job) => kinds.includes(job.type) && (job.type !== 'custom' || tasks.includes(job.params?.task)))
+        .slice(0, max);
+      leased.forEach((job) =>
+        Object.assign(job, {
+          status: 'RUNNING',
+          assigned_node_id: nodeId,
+          attempt: job.attempt + 1,
+          progress: null,
+          lease_expires_at: this.now + leaseMs,
+        }),
+      );
+      return result(leased.map((job) => ({ ...job })));
+    }
+    if (normalized.includes('progress = coalesce($4, progress)')) {
+      const job = this.leased(params[0], params[1]);
+      if (!job) return result([]);
+      job.lease_expires_at = this.now + params[2];
+      job.progress = params[3] ?? job.progress;
+      return result([{ job_id: job.job_id }]);
+    }
+    if (normalized.includes("set status = 'succeeded', result = $3")) {
+      const job = this.leased(params[0], params[1]);
+      if (!job) return result([]);
+      Object.assign(job, { status: 'SUCCEEDED', result: params[2], result_refs: params[3], error: null, lease_expires_at: null });
+      return result([{ job_id: job.job_id, status: job.status }]);
+    }
+    if (normalized.includes('case when $3 and attempt < $4')) {
+      const [jobId, nodeId, retry, maxAttempts, error] = params;
+      const job = this.leased(jobId, nodeId);
+      if (!job) return result([]);
+      const again = retry && job.attempt < maxAttempts;
+      Object.assign(job, {
+        status: again ? 'PENDING' : 'FAILED',
+        assigned_node_id: again ? null : job.assigned_node_id,
+        error,
+        lease_expires_at: null,
+      });
+      return result([{ job_id: job.job_id, status: job.status }]);
+    }
+    if (normalized.startsWith("update grid_jobs set status = 'running', assigned_node_id = $2")) {
+      const job = this.jobs.get(params[0]);
+      if (!job || job.status !== 'PENDING') return result([]);
+      Object.assign(job, { status: 'RUNNING', assigned_node_id: params[1], attempt: job.attempt + 1 });
+      return { rows: [], rowCount: 1 };
+    }
+    if (normalized.startsWith("update grid_jobs set status = 'succeeded', result = $2")) {
+      Object.assign(this.jobs.get(params[0]), { status: 'SUCCEEDED', result: params[1] });
+      return { rows: [], rowCount: 1 };
+    }
+    if (normalized.startsWith("update grid_jobs set status = 'failed', error = $2")) {
+      Object.assign(this.jobs.get(params[0]), { status: 'FAILED', error: params[1] });
+      return { rows: [], rowCount: 1 };
+    }
+    throw new Error(`GridPool does not understand: ${normalized}`);
+  }
+
+  private ordered() {
+    return Array.from(this.jobs.values()).sort((a, b) => a.created_at - b.created_at);
+  }
+
+  private expired(job: any) {
+    return job.status === 'RUNNING' && job.lease_expires_at !== null && job.lease_expires_at < this.now;
+  }
+
+  private leased(jobId: string, nodeId: string) {
+    const job = this.jobs.get(jobId);
+    if (!job || job.assigned_node_id !== nodeId || job.status !== 'RUNNING' || job.lease_expires_at === null) {
+      return undefined;
+    }
+    return job;
+  }
+}
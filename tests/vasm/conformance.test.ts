@@ -0,0 +1,26 @@
+import { readdirSync, readFileSync } from 'fs';
+import { join } from 'path';
+import type { RegisterName, VasmProgram } from '@vasm/program';
+import { VasmVM } from '@vasm/vm';
+
+/** Shared with the Rust interpreter in `services/uor-engine/src/vasm.rs`. */
+interface VasmFixture {
+  name: string;
+  program: VasmProgram;
+  registers: Partial<Record<RegisterName, number>>;
+}
+
+const fixturesDir = join(__dirname, 'fixtures');
+const fixtures: VasmFixture[] = readdirSync(fixturesDir)
+  .filter((file) => file.endsWith('.json'))
+  .sort()
+  .map((file) => JSON.parse(readFileSync(join(fixturesDir, file), 'utf8')));
+
+describe('VasmVM conformance fixtures', () => {
+  it.each(fixtures.map((fixture) => [fixture.name, fixture] as const))('%s', (_name, fixture) => {
+    const vm = new VasmVM(fixture.program).run();
+    for (const [register, expected] of Object.entries(fixture.registers)) {
+      expect(vm.getRegister(register as RegisterName)).toBe(expected);
+    }
+  });
+});
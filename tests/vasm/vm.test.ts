@@ -33,4 +33,30 @@ describe('VasmVM', () => {
     const vm = new VasmVM(program).run();
     expect(vm.getRegister('r0')).toBe(0 + 1 + 2 + 3 + 4);
   });
+
+  // DIV used to return the remainder (`a % b`), the same as MOD.
+  it('divides with DIV and takes the remainder only with MOD', () => {
+    const program: VasmProgram = {
+      instructions: [
+        { op: VasmOpcode.LOAD_CONST, args: ['r1', 9] },
+        { op: VasmOpcode.LOAD_CONST, args: ['r2', 4] },
+        { op: VasmOpcode.DIV, args: ['r0', 'r1', 'r2'] },
+        { op: VasmOpcode.MOD, args: ['r3', 'r1', 'r2'] },
+        { op: VasmOpcode.HALT },
+      ],
+    };
+    const vm = new VasmVM(program).run();
+    expect(vm.getRegister('r0')).toBe(2.25);
+    expect(vm.getRegister('r3')).toBe(1);
+  });
+
+  it('rejects DIV by zero', () => {
+    const program: VasmProgram = {
+      instructions: [
+        { op: VasmOpcode.LOAD_CONST, args: ['r1', 1] },
+        { op: VasmOpcode.DIV, args: ['r0', 'r1', 'r2'] },
+      ],
+    };
+    expect(() => new VasmVM(program).run()).toThrow('Division by zero');
+  });
 });
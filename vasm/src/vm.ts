@@ -221,10 +221,7 @@ export class VasmVM {
         if (b === 0) {
           throw new Error("Division by zero");
         }
-        if (b === 0) {
-          throw new Error("Modulo by zero");
-        }
-        result = a % b;
+        result = a / b;
         break;
       case VasmOpcode.MOD:
         if (b === 0) {